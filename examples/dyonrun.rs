@@ -1,13 +1,29 @@
 extern crate dyon;
 
-use dyon::{error, run};
+use std::sync::Arc;
+
+use dyon::{error, load, Module, Runtime};
 
 fn main() {
     let file = std::env::args_os()
         .nth(1)
         .and_then(|s| s.into_string().ok());
     if let Some(file) = file {
-        error(run(&file));
+        let mut module = Module::new();
+        if error(load(&file, &mut module)) {
+            return;
+        }
+        let mut runtime = Runtime::new();
+        let res = runtime.run(&Arc::new(module));
+        // Scripts calling `exit` stop with an error, which is not reported.
+        #[cfg(feature = "process")]
+        if let Some(code) = runtime.exit_code() {
+            use std::io::Write;
+
+            let _ = std::io::stdout().flush();
+            std::process::exit(code);
+        }
+        error(res);
     } else {
         eprintln!("dyonrun <file.dyon>");
     }
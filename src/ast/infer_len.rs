@@ -58,6 +58,26 @@ fn infer_expr(expr: &Expression, name: &str, decls: &mut Vec<Arc<String>>) -> Op
                 }
             }
         }
+        Map(ref map) => {
+            for &(ref k, ref v) in &map.key_values {
+                let res = infer_expr(k, name, decls);
+                if res.is_some() {
+                    return res;
+                }
+                let res = infer_expr(v, name, decls);
+                if res.is_some() {
+                    return res;
+                }
+            }
+        }
+        Set(ref set) => {
+            for expr in &set.items {
+                let res = infer_expr(expr, name, decls);
+                if res.is_some() {
+                    return res;
+                }
+            }
+        }
         Array(ref arr) => {
             for expr in &arr.items {
                 let res = infer_expr(expr, name, decls);
@@ -83,7 +103,20 @@ fn infer_expr(expr: &Expression, name: &str, decls: &mut Vec<Arc<String>>) -> Op
             }
         }
         ReturnVoid(_) => {}
-        Break(_) => {}
+        Yield(ref yield_expr) => {
+            let res = infer_expr(yield_expr, name, decls);
+            if res.is_some() {
+                return res;
+            }
+        }
+        Break(ref br) => {
+            if let Some(ref val) = br.val {
+                let res = infer_expr(val, name, decls);
+                if res.is_some() {
+                    return res;
+                }
+            }
+        }
         Continue(_) => {}
         Block(ref block) => {
             let res = infer_block(block, name, decls);
@@ -147,75 +180,69 @@ fn infer_expr(expr: &Expression, name: &str, decls: &mut Vec<Arc<String>>) -> Op
                 return res;
             }
         }
+        WhileLet(ref while_let) => {
+            let res = infer_expr(&while_let.expr, name, decls);
+            if res.is_some() {
+                return res;
+            }
+            if &**while_let.name == name {
+                return None;
+            }
+            let res = infer_block(&while_let.block, name, decls);
+            if res.is_some() {
+                return res;
+            }
+        }
         ForN(ref for_n_expr) => return infer_for_n(for_n_expr, name, decls),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         ForIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-        ForIn(ref for_in_expr) |
-        SumIn(ref for_in_expr) |
-        ProdIn(ref for_in_expr) |
-        MinIn(ref for_in_expr) |
-        MaxIn(ref for_in_expr) |
-        SiftIn(ref for_in_expr) |
-        AnyIn(ref for_in_expr) |
-        AllIn(ref for_in_expr) |
-        LinkIn(ref for_in_expr) => match **for_in_expr {},
         Sum(ref for_n_expr) => return infer_for_n(for_n_expr, name, decls),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         SumIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         ProdIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         MinIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         MaxIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         AnyIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         AllIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         SiftIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
                 return res;
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         LinkIn(ref for_in_expr) => {
             let res = infer_expr(&for_in_expr.iter, name, decls);
             if res.is_some() {
@@ -402,6 +429,11 @@ fn infer_block(block: &Block, name: &str, decls: &mut Vec<Arc<String>>) -> Optio
                     } else if item.ids.is_empty() && assign_expr.op == AssignOp::Assign {
                         decls.push(item.name.clone());
                     }
+                } else if let Some(items) = assign_expr.destructure() {
+                    if items.iter().any(|item| &**item.name == name) {
+                        return None;
+                    }
+                    decls.extend(items.iter().map(|item| item.name.clone()));
                 }
                 let left = infer_expr(&assign_expr.left, name, decls);
                 if left.is_some() {
@@ -27,6 +27,14 @@ pub fn convert(
 ) -> Result<(), ()> {
     let mut convert = Convert::new(data);
 
+    // Loops record contributions only when they can be read.
+    module.contributions |= data.iter().any(|d| match d.data {
+        MetaData::String(ref name, ref val) => {
+            (&**name == "name" || &**name == "word") && &**val == "contributions"
+        }
+        _ => false,
+    });
+
     let namespace = if let Ok((range, val)) = Namespace::from_meta_data(convert, ignored) {
         convert.update(range);
         val.names
@@ -34,7 +42,7 @@ pub fn convert(
         Arc::new(vec![])
     };
 
-    let use_lookup = if let Ok((range, val)) = Uses::from_meta_data(convert, ignored) {
+    let mut use_lookup = if let Ok((range, val)) = Uses::from_meta_data(convert, ignored) {
         convert.update(range);
         UseLookup::from_uses_module(&val, module)
     } else {
@@ -53,6 +61,7 @@ pub fn convert(
             break;
         }
     }
+    use_lookup.add_module_defaults(module);
     let mut new_functions = module.functions.clone();
     for i in 0..new_functions.len() {
         new_functions[i].resolve_locals(i, module, &use_lookup);
@@ -70,12 +79,26 @@ pub enum FnAlias {
     External(usize),
 }
 
-/// Used to resolve calls to imported functions.
+/// Call that leaves out arguments with default values.
+#[derive(Clone)]
+pub struct DefaultCall {
+    /// The index of the loaded function.
+    pub index: usize,
+    /// The declared position of each argument in the call.
+    pub positions: Vec<usize>,
+}
+
+/// Used to resolve calls to imported functions,
+/// and calls that leave out arguments with default values.
 pub struct UseLookup {
     /// Stores namespace aliases.
     /// The first key is the alias to namespace.
     /// The second key is the alias to the function.
     pub aliases: HashMap<Arc<String>, HashMap<Arc<String>, FnAlias>>,
+    /// Stores calls that leave out arguments with default values.
+    /// The first key is the alias to namespace, or `None` without alias.
+    /// The second key is the name of the call.
+    pub defaults: HashMap<Option<Arc<String>>, HashMap<Arc<String>, DefaultCall>>,
 }
 
 impl Default for UseLookup {
@@ -89,11 +112,45 @@ impl UseLookup {
     pub fn new() -> UseLookup {
         UseLookup {
             aliases: HashMap::new(),
+            defaults: HashMap::new(),
+        }
+    }
+
+    /// Adds calls to loaded functions that leave out arguments with default values.
+    ///
+    /// The calls are added for `alias`, or without alias when `None`,
+    /// where `rename` replaces the name of the function.
+    fn add_defaults(
+        &mut self,
+        alias: Option<&Arc<String>>,
+        rename: Option<&Arc<String>>,
+        i: usize,
+        f: &Function,
+    ) {
+        let base = f.name.split('(').next().unwrap_or(&f.name);
+        let calls = self.defaults.entry(alias.cloned()).or_default();
+        for (name, positions) in f.default_calls() {
+            let name = match rename {
+                Some(rename) => Arc::new(format!("{}{}", rename, &name[base.len()..])),
+                None => name,
+            };
+            calls.insert(name, DefaultCall { index: i, positions });
+        }
+    }
+
+    /// Adds calls that leave out arguments with default values,
+    /// for functions called without alias.
+    ///
+    /// This is called when constructing the AST, after loading all functions.
+    pub fn add_module_defaults(&mut self, module: &Module) {
+        for (i, f) in module.functions.iter().enumerate() {
+            self.add_defaults(None, None, i, f);
         }
     }
 
     /// This is called when constructing the AST.
     pub fn from_uses_module(uses: &Uses, module: &Module) -> UseLookup {
+        let mut use_lookup = UseLookup::new();
         let mut aliases = HashMap::new();
         // First, add all glob imports.
         for use_import in &uses.use_imports {
@@ -107,6 +164,7 @@ impl UseLookup {
             for (i, f) in module.functions.iter().enumerate().rev() {
                 if *f.namespace == use_import.names {
                     fns.insert(f.name.clone(), FnAlias::Loaded(i));
+                    use_lookup.add_defaults(Some(&use_import.alias), None, i, f);
                 }
             }
             for (i, f) in module.ext_prelude.iter().enumerate().rev() {
@@ -142,7 +200,15 @@ impl UseLookup {
                         let mut name: Arc<String> = use_fn.1.as_ref().unwrap_or(&use_fn.0).clone();
                         Arc::make_mut(&mut name).push_str(&f.name.as_str()[use_fn.0.len()..]);
                         fns.insert(name, FnAlias::Loaded(i));
+                    } else {
+                        continue;
                     }
+                    use_lookup.add_defaults(
+                        Some(&use_import.alias),
+                        Some(use_fn.1.as_ref().unwrap_or(&use_fn.0)),
+                        i,
+                        f,
+                    );
                 }
                 for (i, f) in module.ext_prelude.iter().enumerate().rev() {
                     if *f.namespace != use_import.names {
@@ -165,7 +231,8 @@ impl UseLookup {
                 }
             }
         }
-        UseLookup { aliases }
+        use_lookup.aliases = aliases;
+        use_lookup
     }
 
     /// This is called from lifetime/type checker.
@@ -214,11 +281,23 @@ impl UseLookup {
                         let mut name: Arc<String> = use_fn.1.as_ref().unwrap_or(&use_fn.0).clone();
                         Arc::make_mut(&mut name).push_str(&f.1.as_str()[use_fn.0.len()..]);
                         fns.insert(name, FnAlias::Loaded(i));
+                    } else {
+                        continue;
+                    }
+                    // Calls that leave out arguments with default values.
+                    for &j in prelude.defaults.get(&i).into_iter().flatten() {
+                        let call = &prelude.namespaces[j].1;
+                        let mut name: Arc<String> = use_fn.1.as_ref().unwrap_or(&use_fn.0).clone();
+                        Arc::make_mut(&mut name).push_str(&call[use_fn.0.len()..]);
+                        fns.insert(name, FnAlias::Loaded(j));
                     }
                 }
             }
         }
-        UseLookup { aliases }
+        UseLookup {
+            aliases,
+            defaults: HashMap::new(),
+        }
     }
 }
 
@@ -516,6 +595,20 @@ impl Function {
         self.ret != Type::Void
     }
 
+    /// Returns the calls that leave out arguments with default values.
+    ///
+    /// Each call is returned by name with the declared positions of its arguments.
+    pub fn default_calls(&self) -> Vec<(Arc<String>, Vec<usize>)> {
+        let name = self.name.split('(').next().unwrap_or(&self.name);
+        let args: Vec<(Arc<String>, bool)> = self
+            .args
+            .iter()
+            .map(|arg| (arg.name.clone(), arg.mutable))
+            .collect();
+        let required = self.args.iter().take_while(|arg| arg.default.is_none()).count();
+        default_calls(name, &args, required)
+    }
+
     fn resolve_locals(&mut self, relative: usize, module: &Module, use_lookup: &UseLookup) {
         use std::sync::atomic::Ordering;
 
@@ -541,6 +634,68 @@ impl Function {
     }
 }
 
+/// The maximum number of arguments with default values in a function.
+pub const MAX_DEFAULTS: usize = 8;
+
+/// Returns the calls that leave out arguments with default values.
+///
+/// The `name` is the function name without mutability information.
+/// Each argument stores its name and whether it is mutable.
+/// Arguments from `required` and out have default values.
+///
+/// Each call is returned by name with the declared positions of its arguments.
+/// Positional calls leave out trailing arguments, e.g. `f` for `fn f(x, y = 0)`.
+/// Named argument calls can leave out any argument with default value,
+/// e.g. `f__x_z` for `fn f(x, y = 0, z = 0)`.
+pub fn default_calls(
+    name: &str,
+    args: &[(Arc<String>, bool)],
+    required: usize,
+) -> Vec<(Arc<String>, Vec<usize>)> {
+    // Appends mutability information like in the function name.
+    fn mutable(name: &mut String, args: &[(Arc<String>, bool)], positions: &[usize]) {
+        if positions.iter().any(|&i| args[i].1) {
+            name.push('(');
+            for (j, &i) in positions.iter().enumerate() {
+                if j > 0 {
+                    name.push(',');
+                }
+                name.push_str(if args[i].1 { "mut" } else { "_" });
+            }
+            name.push(')');
+        }
+    }
+
+    let mut res = vec![];
+    if required >= args.len() {
+        return res;
+    }
+    for n in required..args.len() {
+        let positions: Vec<usize> = (0..n).collect();
+        let mut call = String::from(name);
+        mutable(&mut call, args, &positions);
+        res.push((Arc::new(call), positions));
+    }
+    // Every subset of arguments with default values, using bits of `mask`.
+    let optional = args.len() - required;
+    for mask in 0..1_usize << optional {
+        let positions: Vec<usize> = (0..required)
+            .chain((0..optional).filter(|j| mask & (1 << j) != 0).map(|j| required + j))
+            .collect();
+        if positions.is_empty() {
+            continue;
+        }
+        let mut call = format!("{}_", name);
+        for &i in &positions {
+            call.push('_');
+            call.push_str(&args[i].0);
+        }
+        mutable(&mut call, args, &positions);
+        res.push((Arc::new(call), positions));
+    }
+    res
+}
+
 /// Closure.
 #[derive(Debug, Clone)]
 pub struct Closure {
@@ -840,6 +995,8 @@ pub struct Arg {
     pub source_range: Range,
     /// Whether the argument is mutable.
     pub mutable: bool,
+    /// Default value used when the argument is left out in a call.
+    pub default: Option<Expression>,
 }
 
 impl Arg {
@@ -859,6 +1016,7 @@ impl Arg {
         let mut lifetime: Option<Arc<String>> = None;
         let mut ty: Option<Type> = None;
         let mut mutable = false;
+        let mut default: Option<Expression> = None;
         let mut lazy: Vec<Lazy> = vec![];
         loop {
             if let Ok(range) = convert.end_node(node) {
@@ -876,6 +1034,11 @@ impl Arg {
             } else if let Ok((range, val)) = Type::from_meta_data("type", convert, ignored) {
                 convert.update(range);
                 ty = Some(val);
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "default", convert, ignored)
+            {
+                convert.update(range);
+                default = Some(val);
             } else if let Ok((range, val)) = Grab::from_meta_data(file, source, convert, ignored) {
                 convert.update(range);
                 if let Some(val) = val.precompute() {
@@ -912,6 +1075,7 @@ impl Arg {
                 ty,
                 source_range: convert.source(start).unwrap(),
                 mutable,
+                default,
             },
             lazy,
         ))
@@ -1047,6 +1211,10 @@ pub enum Expression {
     Link(Box<Link>),
     /// Object expression.
     Object(Box<Object>),
+    /// Map expression.
+    Map(Box<Map>),
+    /// Set expression.
+    Set(Box<Set>),
     /// Array expression.
     Array(Box<Array>),
     /// Array fill expression.
@@ -1055,6 +1223,8 @@ pub enum Expression {
     Return(Box<Expression>),
     /// Returns with value expression.
     ReturnVoid(Box<Range>),
+    /// Yield expression, used in generator functions.
+    Yield(Box<Expression>),
     /// Break expression.
     Break(Box<Break>),
     /// Continue expression.
@@ -1087,6 +1257,8 @@ pub enum Expression {
     Mat4(Box<Mat4>),
     /// For expression, e.g. `for i := 0; i < 10; i += 1 { ... }`.
     For(Box<For>),
+    /// While-let expression, e.g. `while let some(x) = f() { ... }`.
+    WhileLet(Box<WhileLet>),
     /// For-n expression.
     ForN(Box<ForN>),
     /// For-in expression.
@@ -1180,6 +1352,12 @@ impl Expression {
             {
                 convert.update(range);
                 result = Some(Expression::Object(Box::new(val)));
+            } else if let Ok((range, val)) = Map::from_meta_data(file, source, convert, ignored) {
+                convert.update(range);
+                result = Some(Expression::Map(Box::new(val)));
+            } else if let Ok((range, val)) = Set::from_meta_data(file, source, convert, ignored) {
+                convert.update(range);
+                result = Some(Expression::Set(Box::new(val)));
             } else if let Ok((range, val)) = Array::from_meta_data(file, source, convert, ignored) {
                 convert.update(range);
                 result = Some(Expression::Array(Box::new(val)));
@@ -1198,7 +1376,12 @@ impl Expression {
                 result = Some(Expression::ReturnVoid(Box::new(
                     convert.source(start).unwrap(),
                 )));
-            } else if let Ok((range, val)) = Break::from_meta_data(convert, ignored) {
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "yield", convert, ignored)
+            {
+                convert.update(range);
+                result = Some(Expression::Yield(Box::new(val)));
+            } else if let Ok((range, val)) = Break::from_meta_data(file, source, convert, ignored) {
                 convert.update(range);
                 result = Some(Expression::Break(Box::new(val)));
             } else if let Ok((range, val)) = Continue::from_meta_data(convert, ignored) {
@@ -1353,6 +1536,9 @@ impl Expression {
             } else if let Ok((range, val)) = Loop::from_meta_data(file, source, convert, ignored) {
                 convert.update(range);
                 result = Some(val.into_expression());
+            } else if let Ok((range, val)) = While::from_meta_data(file, source, convert, ignored) {
+                convert.update(range);
+                result = Some(val.into_expression());
             } else if let Ok((range, val)) = If::from_meta_data(file, source, convert, ignored) {
                 convert.update(range);
                 result = Some(Expression::If(Box::new(val)));
@@ -1455,6 +1641,8 @@ impl Expression {
             ArrayFill(ref array_fill) => array_fill.precompute(),
             Array(ref array) => array.precompute(),
             Object(ref obj) => obj.precompute(),
+            Map(ref map) => map.precompute(),
+            Set(ref set) => set.precompute(),
             Vec4(ref vec4) => vec4.precompute(),
             Link(ref link) => link.precompute(),
             Variable(ref range_var) => Some(range_var.1.clone()),
@@ -1469,11 +1657,15 @@ impl Expression {
         match *self {
             Link(ref link) => link.source_range,
             Object(ref obj) => obj.source_range,
+            Map(ref map) => map.source_range,
+            Set(ref set) => set.source_range,
             Array(ref arr) => arr.source_range,
             ArrayFill(ref arr_fill) => arr_fill.source_range,
             Return(ref expr) => expr.source_range(),
             ReturnVoid(ref range) => **range,
+            Yield(ref expr) => expr.source_range(),
             Break(ref br) => br.source_range,
+            WhileLet(ref while_let) => while_let.source_range,
             Continue(ref c) => c.source_range,
             Block(ref bl) => bl.source_range,
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
@@ -1493,43 +1685,24 @@ impl Expression {
             Mat4(ref mat4) => mat4.source_range,
             For(ref for_expr) => for_expr.source_range,
             ForN(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             ForIn(ref for_in_expr) => for_in_expr.source_range,
-            #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-            ForIn(ref for_in_expr) |
-            SumIn(ref for_in_expr) |
-            ProdIn(ref for_in_expr) |
-            MinIn(ref for_in_expr) |
-            MaxIn(ref for_in_expr) |
-            SiftIn(ref for_in_expr) |
-            AnyIn(ref for_in_expr) |
-            AllIn(ref for_in_expr) |
-            LinkIn(ref for_in_expr) => match **for_in_expr {},
             Sum(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             SumIn(ref for_in_expr) => for_in_expr.source_range,
             SumVec4(ref for_n_expr) => for_n_expr.source_range,
             Prod(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             ProdIn(ref for_in_expr) => for_in_expr.source_range,
             ProdVec4(ref for_n_expr) => for_n_expr.source_range,
             Min(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             MinIn(ref for_in_expr) => for_in_expr.source_range,
             Max(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             MaxIn(ref for_in_expr) => for_in_expr.source_range,
             Sift(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             SiftIn(ref for_in_expr) => for_in_expr.source_range,
             Any(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             AnyIn(ref for_in_expr) => for_in_expr.source_range,
             All(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             AllIn(ref for_in_expr) => for_in_expr.source_range,
             LinkFor(ref for_n_expr) => for_n_expr.source_range,
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             LinkIn(ref for_in_expr) => for_in_expr.source_range,
             If(ref if_expr) => if_expr.source_range,
             Variable(ref range_var) => range_var.0,
@@ -1563,6 +1736,12 @@ impl Expression {
             Object(ref mut obj) => {
                 obj.resolve_locals(relative, stack, closure_stack, module, use_lookup)
             }
+            Map(ref mut map) => {
+                map.resolve_locals(relative, stack, closure_stack, module, use_lookup)
+            }
+            Set(ref mut set) => {
+                set.resolve_locals(relative, stack, closure_stack, module, use_lookup)
+            }
             Array(ref mut arr) => {
                 arr.resolve_locals(relative, stack, closure_stack, module, use_lookup)
             }
@@ -1575,7 +1754,14 @@ impl Expression {
                 stack.truncate(st);
             }
             ReturnVoid(_) => {}
-            Break(_) => {}
+            Yield(ref mut expr) => {
+                let st = stack.len();
+                expr.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+                stack.truncate(st);
+            }
+            Break(ref mut br) => {
+                br.resolve_locals(relative, stack, closure_stack, module, use_lookup)
+            }
             Continue(_) => {}
             Block(ref mut bl) => {
                 bl.resolve_locals(relative, stack, closure_stack, module, use_lookup)
@@ -1629,6 +1815,9 @@ impl Expression {
                             info: call.info.clone(),
                         }))
                     }
+                    // Async functions are called through `Call`.
+                    #[cfg(feature = "async")]
+                    FnIndex::Async(_) => {}
                     FnIndex::None => {}
                 }
             }
@@ -1653,6 +1842,9 @@ impl Expression {
             For(ref mut for_expr) => {
                 for_expr.resolve_locals(relative, stack, closure_stack, module, use_lookup)
             }
+            WhileLet(ref mut while_let) => {
+                while_let.resolve_locals(relative, stack, closure_stack, module, use_lookup)
+            }
             ForN(ref mut for_n_expr) => {
                 for_n_expr.resolve_locals(relative, stack, closure_stack, module, use_lookup)
             }
@@ -1903,6 +2095,166 @@ impl Object {
     }
 }
 
+/// Map expression, e.g. `map {1: a, "b": c}`.
+#[derive(Debug, Clone)]
+pub struct Map {
+    /// Key-value pair expressions.
+    pub key_values: Vec<(Expression, Expression)>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Map {
+    /// Creates map expression from meta data.
+    pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>,
+    ) -> Result<(Range, Map), ()> {
+        let start = convert;
+        let node = "map";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut key_values = vec![];
+        let mut key: Option<Expression> = None;
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "map_key", convert, ignored)
+            {
+                convert.update(range);
+                key = Some(val);
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "val", convert, ignored)
+            {
+                convert.update(range);
+                key_values.push((key.take().ok_or(())?, val));
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        Ok((
+            convert.subtract(start),
+            Map {
+                key_values,
+                source_range: convert.source(start).unwrap(),
+            },
+        ))
+    }
+
+    fn precompute(&self) -> Option<Variable> {
+        use crate::Key;
+
+        let mut map: HashMap<_, _> = HashMap::new();
+        for &(ref key, ref value) in &self.key_values {
+            let key = Key::from_variable(&key.precompute()?, &[])?;
+            if map.insert(key, value.precompute()?).is_some() {
+                return None;
+            }
+        }
+        Some(Variable::Map(Arc::new(map)))
+    }
+
+    fn resolve_locals(
+        &mut self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        let st = stack.len();
+        for &mut (ref mut key, ref mut value) in &mut self.key_values {
+            key.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+            value.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+        }
+    }
+}
+
+/// Set expression, e.g. `set {a, b, c}`.
+#[derive(Debug, Clone)]
+pub struct Set {
+    /// Set item expressions.
+    pub items: Vec<Expression>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Set {
+    /// Creates set expression from meta data.
+    pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>,
+    ) -> Result<(Range, Set), ()> {
+        let start = convert;
+        let node = "set";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut items = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "set_item", convert, ignored)
+            {
+                convert.update(range);
+                items.push(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        Ok((
+            convert.subtract(start),
+            Set {
+                items,
+                source_range: convert.source(start).unwrap(),
+            },
+        ))
+    }
+
+    fn precompute(&self) -> Option<Variable> {
+        use crate::Key;
+        use std::collections::HashSet;
+
+        let mut set: HashSet<_> = HashSet::new();
+        for item in &self.items {
+            set.insert(Key::from_variable(&item.precompute()?, &[])?);
+        }
+        Some(Variable::Set(Arc::new(set)))
+    }
+
+    fn resolve_locals(
+        &mut self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        let st = stack.len();
+        for item in &mut self.items {
+            item.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+        }
+    }
+}
+
 /// Array expression, e.g. `[a, b, c]`.
 #[derive(Debug, Clone)]
 pub struct Array {
@@ -2142,6 +2494,21 @@ impl BinOpSeq {
             } else if let Ok((range, _)) = convert.meta_bool("&&") {
                 convert.update(range);
                 ops.push(BinOp::AndAlso);
+            } else if let Ok((range, _)) = convert.meta_bool("&") {
+                convert.update(range);
+                ops.push(BinOp::BitAnd);
+            } else if let Ok((range, _)) = convert.meta_bool("|") {
+                convert.update(range);
+                ops.push(BinOp::BitOr);
+            } else if let Ok((range, _)) = convert.meta_bool("xor") {
+                convert.update(range);
+                ops.push(BinOp::BitXor);
+            } else if let Ok((range, _)) = convert.meta_bool("<<") {
+                convert.update(range);
+                ops.push(BinOp::Shl);
+            } else if let Ok((range, _)) = convert.meta_bool(">>") {
+                convert.update(range);
+                ops.push(BinOp::Shr);
             } else if let Ok((range, _)) = convert.meta_bool("<") {
                 convert.update(range);
                 ops.push(BinOp::Less);
@@ -2221,6 +2588,16 @@ pub enum BinOp {
     OrElse,
     /// Lazy AND operator (`&&`).
     AndAlso,
+    /// Bitwise AND operator (`&`).
+    BitAnd,
+    /// Bitwise OR operator (`|`).
+    BitOr,
+    /// Bitwise XOR operator (`xor`).
+    BitXor,
+    /// Shift left operator (`<<`).
+    Shl,
+    /// Shift right operator (`>>`).
+    Shr,
     /// Less.
     Less,
     /// Less or equal.
@@ -2254,6 +2631,11 @@ impl BinOp {
             BinOp::Pow => "^",
             BinOp::OrElse => "||",
             BinOp::AndAlso => "&&",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "xor",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
             BinOp::Less => "<",
             BinOp::LessOrEqual => "<=",
             BinOp::Greater => ">",
@@ -2284,9 +2666,16 @@ impl BinOp {
             | BinOp::NotEqual => BINOP_PREC_EQ,
             BinOp::OrElse => BINOP_PREC_ADD,
             BinOp::AndAlso => BINOP_PREC_MUL,
-            BinOp::Add | BinOp::Sub => BINOP_PREC_ADD,
-            BinOp::Mul | BinOp::Dot | BinOp::Cross | BinOp::Div | BinOp::Rem => BINOP_PREC_MUL,
-            BinOp::Pow => BINOP_PREC_POW,
+            BinOp::Add | BinOp::Sub | BinOp::BitOr => BINOP_PREC_ADD,
+            BinOp::Mul
+            | BinOp::Dot
+            | BinOp::Cross
+            | BinOp::Div
+            | BinOp::Rem
+            | BinOp::BitAnd
+            | BinOp::Shl
+            | BinOp::Shr => BINOP_PREC_MUL,
+            BinOp::Pow | BinOp::BitXor => BINOP_PREC_POW,
         }
     }
 }
@@ -2875,6 +3264,14 @@ impl Call {
         };
 
         let st = stack.len();
+        // Look up calls that leave out arguments with default values.
+        let default_call = |alias: &Option<Arc<String>>| {
+            use_lookup
+                .defaults
+                .get(alias)
+                .and_then(|map| map.get(&self.info.name))
+        };
+        let mut positions: Option<&[usize]> = None;
         let f_index = if let Some(ref alias) = self.info.alias {
             if let Some(&i) = use_lookup
                 .aliases
@@ -2890,22 +3287,58 @@ impl Call {
                             FnExt::Return(ff) => FnIndex::Return(FnReturnRef(ff)),
                             FnExt::BinOp(ff) => FnIndex::BinOp(FnBinOpRef(ff)),
                             FnExt::UnOp(ff) => FnIndex::UnOp(FnUnOpRef(ff)),
+                            #[cfg(feature = "async")]
+                            FnExt::Async(ff) => FnIndex::Async(crate::FnAsyncRef(ff)),
                         }
                     }
                 }
+            } else if let Some(call) = default_call(&self.info.alias) {
+                positions = Some(&call.positions);
+                FnIndex::Loaded(call.index as isize - relative as isize)
             } else {
                 FnIndex::None
             }
         } else {
-            module.find_function(&self.info.name, relative)
+            match module.find_function(&self.info.name, relative) {
+                FnIndex::None => match default_call(&None) {
+                    Some(call) => {
+                        positions = Some(&call.positions);
+                        FnIndex::Loaded(call.index as isize - relative as isize)
+                    }
+                    None => FnIndex::None,
+                },
+                x => x,
+            }
         };
         self.f_index = f_index;
         match f_index {
             FnIndex::Loaded(f_index) => {
                 let index = (f_index + relative as isize) as usize;
-                if module.functions[index].returns() {
+                let f = &module.functions[index];
+                if f.returns() {
                     stack.push(None);
                 }
+                // Fill in default values of arguments that are left out.
+                let n = self.arg_len();
+                if n < f.args.len() {
+                    match positions {
+                        Some(positions) if positions.iter().enumerate().any(|(i, &p)| i != p) => {
+                            // Named arguments are moved to their declared positions.
+                            let mut args = std::mem::take(&mut self.args).into_iter();
+                            let mut positions = positions.iter().peekable();
+                            for (i, arg) in f.args.iter().enumerate() {
+                                if positions.next_if(|&&p| p == i).is_some() {
+                                    self.args.extend(args.next());
+                                } else {
+                                    self.args.extend(arg.default.clone());
+                                }
+                            }
+                        }
+                        _ => self
+                            .args
+                            .extend(f.args[n..].iter().filter_map(|arg| arg.default.clone())),
+                    }
+                }
             }
             FnIndex::Void(_)
             | FnIndex::Return(_)
@@ -2915,6 +3348,8 @@ impl Call {
                 // Don't push return since last value in block
                 // is used as return value.
             }
+            #[cfg(feature = "async")]
+            FnIndex::Async(_) => {}
             FnIndex::None => {}
         }
         for arg in &mut self.args {
@@ -3242,6 +3677,11 @@ impl BinOpExpression {
                     Cross => crate::CROSS.clone(),
                     AndAlso => crate::AND_ALSO.clone(),
                     OrElse => crate::OR_ELSE.clone(),
+                    BitAnd => crate::BIT_AND.clone(),
+                    BitOr => crate::BIT_OR.clone(),
+                    BitXor => crate::BIT_XOR.clone(),
+                    Shl => crate::SHL.clone(),
+                    Shr => crate::SHR.clone(),
                     Less => crate::LESS.clone(),
                     LessOrEqual => crate::LESS_OR_EQUAL.clone(),
                     Greater => crate::GREATER.clone(),
@@ -3366,6 +3806,9 @@ impl Assign {
             } else if let Ok((range, _)) = convert.meta_bool("^=") {
                 convert.update(range);
                 op = Some(AssignOp::Pow);
+            } else if let Ok((range, val)) = Assign::destructure_from_meta_data(convert) {
+                convert.update(range);
+                left = Some(val);
             } else if let Ok((range, val)) =
                 Expression::from_meta_data(file, source, "left", convert, ignored)
             {
@@ -3397,6 +3840,93 @@ impl Assign {
         ))
     }
 
+    /// Creates destructuring left expression from meta data,
+    /// e.g. `[a, b]`, `{x, y}` or `(x, y, z)`.
+    ///
+    /// The declared names are stored as items without extra.
+    fn destructure_from_meta_data(mut convert: Convert) -> Result<(Range, Expression), ()> {
+        let start = convert;
+        let node = "left";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let shape_start = convert;
+        let shape = ["array", "object", "vec4"]
+            .iter()
+            .find(|shape| convert.start_node(shape).is_ok())
+            .ok_or(())?;
+        let start_range = convert.start_node(shape)?;
+        convert.update(start_range);
+
+        let mut items = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(shape) {
+                convert.update(range);
+                break;
+            }
+            let item_start = convert;
+            let start_range = convert.start_node("item")?;
+            convert.update(start_range);
+            let (range, name) = convert.meta_string("name")?;
+            convert.update(range);
+            let range = convert.end_node("item")?;
+            convert.update(range);
+            items.push(Item::from_variable(name, convert.source(item_start).unwrap()));
+        }
+        if items.is_empty() {
+            return Err(());
+        }
+        let range = convert.end_node(node)?;
+        convert.update(range);
+
+        let source_range = convert.source(shape_start).unwrap();
+        let left = match *shape {
+            "array" => Expression::Array(Box::new(Array {
+                items: items.into_iter().map(|it| Expression::Item(Box::new(it))).collect(),
+                source_range,
+            })),
+            "object" => Expression::Object(Box::new(Object {
+                key_values: items
+                    .into_iter()
+                    .map(|it| (it.name.clone(), Expression::Item(Box::new(it))))
+                    .collect(),
+                source_range,
+            })),
+            _ => Expression::Vec4(Box::new(Vec4 {
+                args: items.into_iter().map(|it| Expression::Item(Box::new(it))).collect(),
+                source_range,
+            })),
+        };
+        Ok((convert.subtract(start), left))
+    }
+
+    /// Returns the items declared by destructuring, e.g. `[a, b] := ...`.
+    pub fn destructure(&self) -> Option<Vec<&Item>> {
+        if self.op != AssignOp::Assign {
+            return None;
+        }
+        let exprs: Vec<&Expression> = match self.left {
+            Expression::Array(ref arr) => arr.items.iter().collect(),
+            Expression::Object(ref obj) => obj.key_values.iter().map(|kv| &kv.1).collect(),
+            Expression::Vec4(ref vec4) => vec4.args.iter().collect(),
+            _ => return None,
+        };
+        let mut items = vec![];
+        for expr in exprs {
+            match *expr {
+                Expression::Item(ref item) if item.ids.is_empty() && !item.current => {
+                    items.push(&**item)
+                }
+                _ => return None,
+            }
+        }
+        if items.is_empty() {
+            None
+        } else {
+            Some(items)
+        }
+    }
+
     fn resolve_locals(
         &mut self,
         relative: usize,
@@ -3418,6 +3948,13 @@ impl Assign {
                 return;
             }
         }
+        // Declare new locals in the order they are destructured.
+        if let Some(items) = self.destructure() {
+            for item in items {
+                stack.push(Some(item.name.clone()));
+            }
+            return;
+        }
 
         self.left
             .resolve_locals(relative, stack, closure_stack, module, use_lookup);
@@ -4034,7 +4571,6 @@ impl For {
 
 /// For-In expression.
 #[derive(Debug, Clone)]
-#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 pub struct ForIn {
     /// Name of the loop variable.
     pub name: Arc<String>,
@@ -4048,14 +4584,8 @@ pub struct ForIn {
     pub source_range: Range,
 }
 
-/// For-In expression.
-#[derive(Debug, Clone)]
-#[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-pub enum ForIn {}
-
 impl ForIn {
     /// Creates For-In expression from meta data.
-    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     pub fn from_meta_data(
         file: &Arc<String>,
         source: &Arc<String>,
@@ -4113,19 +4643,6 @@ impl ForIn {
         ))
     }
 
-    /// Creates For-In expression from meta data.
-    #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-    pub fn from_meta_data(
-        _file: &Arc<String>,
-        _source: &Arc<String>,
-        _node: &str,
-        _convert: Convert,
-        _ignored: &mut Vec<Range>,
-    ) -> Result<(Range, ForIn), ()> {
-        Err(())
-    }
-
-    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     fn resolve_locals(
         &mut self,
         relative: usize,
@@ -4143,16 +4660,6 @@ impl ForIn {
             .resolve_locals(relative, stack, closure_stack, module, use_lookup);
         stack.truncate(st);
     }
-
-    #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-    fn resolve_locals(
-        &mut self,
-        _relative: usize,
-        _stack: &mut Vec<Option<Arc<String>>>,
-        _closure_stack: &mut Vec<usize>,
-        _module: &Module,
-        _use_lookup: &UseLookup,
-    ) {}
 }
 
 /// For-N expression.
@@ -4172,6 +4679,10 @@ pub struct ForN {
     pub block: Block,
     /// Loop label.
     pub label: Option<Arc<String>>,
+    /// Whether the loop runs in parallel.
+    ///
+    /// Without threading support, the loop runs sequentially.
+    pub par: bool,
     /// The range in source.
     pub source_range: Range,
 }
@@ -4192,6 +4703,7 @@ impl ForN {
         let mut indices: Vec<(Arc<String>, Option<Expression>, Option<Expression>)> = vec![];
         let mut block: Option<Block> = None;
         let mut label: Option<Arc<String>> = None;
+        let mut par = false;
         loop {
             if let Ok(range) = convert.end_node(node) {
                 convert.update(range);
@@ -4204,6 +4716,9 @@ impl ForN {
             } else if let Ok((range, val)) = convert.meta_string("label") {
                 convert.update(range);
                 label = Some(val);
+            } else if let Ok((range, val)) = convert.meta_bool("par") {
+                convert.update(range);
+                par = val;
             } else if let Ok((range, val)) = convert.meta_string("name") {
                 convert.update(range);
                 let mut start_expr: Option<Expression> = None;
@@ -4233,6 +4748,7 @@ impl ForN {
             convert.subtract(start),
             convert.source(start).unwrap(),
             label,
+            par,
             &indices,
             block,
         )
@@ -4243,6 +4759,7 @@ impl ForN {
         range: Range,
         source_range: Range,
         label: Option<Arc<String>>,
+        par: bool,
         indices: &[(Arc<String>, Option<Expression>, Option<Expression>)],
         mut block: Option<Block>,
     ) -> Result<(Range, ForN), ()> {
@@ -4256,7 +4773,7 @@ impl ForN {
 
         if indices.len() > 1 {
             let (_, new_for_n) =
-                ForN::create(node, range, source_range, None, &indices[1..], block)?;
+                ForN::create(node, range, source_range, None, false, &indices[1..], block)?;
             block = Some(Block {
                 source_range,
                 expressions: vec![match node {
@@ -4292,6 +4809,7 @@ impl ForN {
                 end: end_expr,
                 block,
                 label,
+                par,
                 source_range,
             },
         ))
@@ -4395,11 +4913,168 @@ impl Loop {
     }
 }
 
+/// While expression.
+///
+/// This is converted into a for-expression,
+/// or a while-let expression when using `while let some(x) = ...`.
+#[derive(Debug, Clone)]
+pub struct While {
+    /// Condition expression.
+    pub cond: Option<Expression>,
+    /// Name of variable when using `while let`.
+    pub name: Option<Arc<String>>,
+    /// Option expression when using `while let`.
+    pub let_some: Option<Expression>,
+    /// Block expression.
+    pub block: Block,
+    /// Loop label.
+    pub label: Option<Arc<String>>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl While {
+    /// Creates while expression from meta data.
+    pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>,
+    ) -> Result<(Range, While), ()> {
+        let start = convert;
+        let node = "while";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut cond: Option<Expression> = None;
+        let mut name: Option<Arc<String>> = None;
+        let mut let_some: Option<Expression> = None;
+        let mut block: Option<Block> = None;
+        let mut label: Option<Arc<String>> = None;
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "cond", convert, ignored)
+            {
+                convert.update(range);
+                cond = Some(val);
+            } else if let Ok((range, val)) = convert.meta_string("name") {
+                convert.update(range);
+                name = Some(val);
+            } else if let Ok((range, val)) =
+                Expression::from_meta_data(file, source, "let_some", convert, ignored)
+            {
+                convert.update(range);
+                let_some = Some(val);
+            } else if let Ok((range, val)) =
+                Block::from_meta_data(file, source, "block", convert, ignored)
+            {
+                convert.update(range);
+                block = Some(val);
+            } else if let Ok((range, val)) = convert.meta_string("label") {
+                convert.update(range);
+                label = Some(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let block = block.ok_or(())?;
+        if cond.is_none() && (name.is_none() || let_some.is_none()) {
+            return Err(());
+        }
+        Ok((
+            convert.subtract(start),
+            While {
+                cond,
+                name,
+                let_some,
+                block,
+                label,
+                source_range: convert.source(start).unwrap(),
+            },
+        ))
+    }
+
+    fn into_expression(self) -> Expression {
+        let source_range = self.source_range;
+        match (self.cond, self.name, self.let_some) {
+            (Some(cond), _, _) => Expression::For(Box::new(For {
+                block: self.block,
+                label: self.label,
+                init: Expression::Block(Box::new(Block {
+                    expressions: vec![],
+                    source_range,
+                })),
+                step: Expression::Block(Box::new(Block {
+                    expressions: vec![],
+                    source_range,
+                })),
+                cond,
+                source_range,
+            })),
+            (None, Some(name), Some(expr)) => Expression::WhileLet(Box::new(WhileLet {
+                name,
+                expr,
+                block: self.block,
+                label: self.label,
+                source_range,
+            })),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// While-let expression.
+///
+/// Loops while the expression returns `some(x)`.
+#[derive(Debug, Clone)]
+pub struct WhileLet {
+    /// Name of the loop variable.
+    pub name: Arc<String>,
+    /// The option expression evaluated at each step.
+    pub expr: Expression,
+    /// Block expression.
+    pub block: Block,
+    /// Loop label.
+    pub label: Option<Arc<String>>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl WhileLet {
+    fn resolve_locals(
+        &mut self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        let st = stack.len();
+        self.expr
+            .resolve_locals(relative, stack, closure_stack, module, use_lookup);
+        stack.truncate(st);
+        stack.push(Some(self.name.clone()));
+        self.block
+            .resolve_locals(relative, stack, closure_stack, module, use_lookup);
+        stack.truncate(st);
+    }
+}
+
 /// Break expression.
 #[derive(Debug, Clone)]
 pub struct Break {
     /// Loop label.
     pub label: Option<Arc<String>>,
+    /// The value to break out of a `loop` with.
+    ///
+    /// This value is deep cloned, such that it can outlive the loop.
+    pub val: Option<Expression>,
     /// The range in source.
     pub source_range: Range,
 }
@@ -4407,6 +5082,8 @@ pub struct Break {
 impl Break {
     /// Creates break expression from meta data.
     pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
         mut convert: Convert,
         ignored: &mut Vec<Range>,
     ) -> Result<(Range, Break), ()> {
@@ -4416,6 +5093,7 @@ impl Break {
         convert.update(start_range);
 
         let mut label: Option<Arc<String>> = None;
+        let mut val: Option<Expression> = None;
         loop {
             if let Ok(range) = convert.end_node(node) {
                 convert.update(range);
@@ -4423,6 +5101,11 @@ impl Break {
             } else if let Ok((range, val)) = convert.meta_string("label") {
                 convert.update(range);
                 label = Some(val);
+            } else if let Ok((range, expr)) =
+                Expression::from_meta_data(file, source, "val", convert, ignored)
+            {
+                convert.update(range);
+                val = Some(expr);
             } else {
                 let range = convert.ignore();
                 convert.update(range);
@@ -4434,10 +5117,26 @@ impl Break {
             convert.subtract(start),
             Break {
                 label,
+                val,
                 source_range: convert.source(start).unwrap(),
             },
         ))
     }
+
+    fn resolve_locals(
+        &mut self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        if let Some(ref mut val) = self.val {
+            let st = stack.len();
+            val.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+        }
+    }
 }
 
 /// Continue expression.
@@ -4700,6 +5399,8 @@ impl In {
                             FnExt::Return(ff) => FnIndex::Return(FnReturnRef(ff)),
                             FnExt::BinOp(ff) => FnIndex::BinOp(FnBinOpRef(ff)),
                             FnExt::UnOp(ff) => FnIndex::UnOp(FnUnOpRef(ff)),
+                            #[cfg(feature = "async")]
+                            FnExt::Async(ff) => FnIndex::Async(crate::FnAsyncRef(ff)),
                         }
                     }
                 }
@@ -1,11 +1,11 @@
 use std::sync::Arc;
 
 use super::{
-    Array, ArrayFill, Assign, Block, Call, CallClosure, Expression, For, ForN, Grab,
-    Id, If, Item, Link, Mat4, Object, Swizzle, TryExpr, Vec4,
+    Array, ArrayFill, Assign, Block, Break, Call, CallClosure, Expression, For, ForIn, ForN,
+    Grab, Id, If, Item, Link, Map, Mat4, Object, Set, Swizzle, TryExpr, Vec4, WhileLet,
 };
 #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
-use super::{ForIn, Go};
+use super::Go;
 use crate::Variable;
 
 /// Replaces an item with a number.
@@ -74,6 +74,29 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
         E::CallLoaded(_) => unimplemented!("`CallLoaded` is transform from `Call` later"),
         E::CallBinOp(_) => unimplemented!("`CallBinOp` is transformed from `Call` later"),
         E::CallUnOp(_) => unimplemented!("`CallUnOp` is transformed from `Call` later"),
+        E::Map(ref map_expr) => {
+            let mut new_key_values: Vec<(Expression, Expression)> = vec![];
+            for key_value in &map_expr.key_values {
+                new_key_values.push((
+                    number(&key_value.0, name, val),
+                    number(&key_value.1, name, val),
+                ));
+            }
+            E::Map(Box::new(Map {
+                key_values: new_key_values,
+                source_range: map_expr.source_range,
+            }))
+        }
+        E::Set(ref set_expr) => {
+            let mut new_items: Vec<Expression> = vec![];
+            for item in &set_expr.items {
+                new_items.push(number(item, name, val));
+            }
+            E::Set(Box::new(Set {
+                items: new_items,
+                source_range: set_expr.source_range,
+            }))
+        }
         E::Array(ref array_expr) => {
             let mut new_items: Vec<Expression> = vec![];
             for item in &array_expr.items {
@@ -91,7 +114,12 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
         })),
         E::Return(ref ret_expr) => E::Return(Box::new(number(ret_expr, name, val))),
         E::ReturnVoid(_) => expr.clone(),
-        E::Break(_) => expr.clone(),
+        E::Yield(ref yield_expr) => E::Yield(Box::new(number(yield_expr, name, val))),
+        E::Break(ref br) => E::Break(Box::new(Break {
+            label: br.label.clone(),
+            val: br.val.as_ref().map(|val_expr| number(val_expr, name, val)),
+            source_range: br.source_range,
+        })),
         E::Continue(_) => expr.clone(),
         #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::Go(ref go) => E::Go(Box::new(Go {
@@ -155,7 +183,22 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
                 }))
             }
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        E::WhileLet(ref while_let) => {
+            let expr = number(&while_let.expr, name, val);
+            // The loop variable shadows the name in the block.
+            let block = if &while_let.name == name {
+                while_let.block.clone()
+            } else {
+                number_block(&while_let.block, name, val)
+            };
+            E::WhileLet(Box::new(WhileLet {
+                label: while_let.label.clone(),
+                name: while_let.name.clone(),
+                expr,
+                block,
+                source_range: while_let.source_range,
+            }))
+        }
         E::ForIn(ref for_in_expr) => E::ForIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -163,17 +206,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-        E::ForIn(ref for_in_expr) |
-        E::SumIn(ref for_in_expr) |
-        E::ProdIn(ref for_in_expr) |
-        E::MinIn(ref for_in_expr) |
-        E::MaxIn(ref for_in_expr) |
-        E::SiftIn(ref for_in_expr) |
-        E::AnyIn(ref for_in_expr) |
-        E::AllIn(ref for_in_expr) |
-        E::LinkIn(ref for_in_expr) => match **for_in_expr {},
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SumIn(ref for_in_expr) => E::SumIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -181,7 +213,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::ProdIn(ref for_in_expr) => E::ProdIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -189,7 +220,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MinIn(ref for_in_expr) => E::MinIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -197,7 +227,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MaxIn(ref for_in_expr) => E::MaxIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -205,7 +234,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AnyIn(ref for_in_expr) => E::AnyIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -213,7 +241,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AllIn(ref for_in_expr) => E::AllIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -221,7 +248,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SiftIn(ref for_in_expr) => E::SiftIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -229,7 +255,6 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
             block: number_block(&for_in_expr.block, name, val),
             source_range: for_in_expr.source_range,
         })),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::LinkIn(ref for_in_expr) => E::LinkIn(Box::new(ForIn {
             label: for_in_expr.label.clone(),
             name: for_in_expr.name.clone(),
@@ -330,17 +355,22 @@ fn number_block(block: &Block, name: &Arc<String>, val: f64) -> Block {
         } else {
             if let Expression::Assign(ref assign_expr) = *expr {
                 // Check for declaration of same name.
-                if let Expression::Item(ref item) = assign_expr.left {
-                    if &item.name == name {
-                        new_expressions.push(Expression::Assign(Box::new(Assign {
-                            op: assign_expr.op,
-                            left: assign_expr.left.clone(),
-                            right: number(&assign_expr.right, name, val),
-                            source_range: assign_expr.source_range,
-                        })));
-                        just_clone = true;
-                        continue;
-                    }
+                let declared = match assign_expr.left {
+                    Expression::Item(ref item) => &item.name == name,
+                    _ => assign_expr
+                        .destructure()
+                        .map(|items| items.iter().any(|item| &item.name == name))
+                        .unwrap_or(false),
+                };
+                if declared {
+                    new_expressions.push(Expression::Assign(Box::new(Assign {
+                        op: assign_expr.op,
+                        left: assign_expr.left.clone(),
+                        right: number(&assign_expr.right, name, val),
+                        source_range: assign_expr.source_range,
+                    })));
+                    just_clone = true;
+                    continue;
                 }
             }
             new_expressions.push(number(expr, name, val));
@@ -358,6 +388,7 @@ fn number_for_n(for_n_expr: &ForN, name: &Arc<String>, val: f64) -> ForN {
     } else {
         ForN {
             label: for_n_expr.label.clone(),
+            par: for_n_expr.par,
             name: for_n_expr.name.clone(),
             start: for_n_expr
                 .start
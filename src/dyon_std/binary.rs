@@ -0,0 +1,412 @@
+//! Binary encoding of Dyon data.
+//!
+//! Supports the same values as the text data format, but loads much faster.
+//! Strings are stored once in a table and referred to by index,
+//! and arrays of only numbers or only `vec4` are packed.
+//!
+//! The layout is:
+//!
+//! - The magic bytes `DYBN` followed by the version as `u32`
+//! - The number of strings, followed by each string as length and UTF-8 bytes
+//! - The root value, as a tag byte followed by its content
+//!
+//! Lengths and indices are unsigned LEB128 and numbers are little endian.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::{Key, Link, Variable};
+
+const MAGIC: &[u8; 4] = b"DYBN";
+
+/// The current version of the format.
+pub const VERSION: u32 = 1;
+
+const NONE: u8 = 0;
+const SOME: u8 = 1;
+const FALSE: u8 = 2;
+const TRUE: u8 = 3;
+const F64: u8 = 4;
+const STR: u8 = 5;
+const VEC4: u8 = 6;
+const ARRAY: u8 = 7;
+const OBJECT: u8 = 8;
+const MAP: u8 = 9;
+const SET: u8 = 10;
+const LINK: u8 = 11;
+const F64_ARRAY: u8 = 12;
+const VEC4_ARRAY: u8 = 13;
+
+/// The deepest nesting of values when decoding,
+/// such that malformed data can not overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Loads binary data from a file.
+#[cfg(feature = "file")]
+pub fn load_file(file: &str) -> Result<Variable, String> {
+    use super::io::io_error;
+
+    let bytes = std::fs::read(file).map_err(|err| io_error("read", file, &err))?;
+    decode(&bytes)
+}
+
+/// Loads binary data from a file.
+#[cfg(not(feature = "file"))]
+pub fn load_file(_: &str) -> Result<Variable, String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Saves binary data to a file.
+#[cfg(feature = "file")]
+pub fn save_file(v: &Variable, file: &str) -> Result<(), String> {
+    use super::io::io_error;
+
+    let bytes = encode(v)?;
+    std::fs::write(file, bytes).map_err(|err| io_error("write", file, &err))
+}
+
+/// Saves binary data to a file.
+#[cfg(not(feature = "file"))]
+pub fn save_file(_: &Variable, _: &str) -> Result<(), String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Encodes data to bytes.
+pub fn encode(v: &Variable) -> Result<Vec<u8>, String> {
+    let mut enc = Encoder {
+        strings: vec![],
+        indices: HashMap::new(),
+        body: vec![],
+    };
+    enc.value(v)?;
+
+    let mut res = Vec::with_capacity(enc.body.len() + 16);
+    res.extend_from_slice(MAGIC);
+    res.extend_from_slice(&VERSION.to_le_bytes());
+    write_len(&mut res, enc.strings.len());
+    for s in &enc.strings {
+        write_len(&mut res, s.len());
+        res.extend_from_slice(s.as_bytes());
+    }
+    res.extend_from_slice(&enc.body);
+    Ok(res)
+}
+
+/// Decodes data from bytes.
+pub fn decode(bytes: &[u8]) -> Result<Variable, String> {
+    if bytes.len() < 8 || &bytes[0..4] != MAGIC {
+        return Err("Expected binary Dyon data".into());
+    }
+    let mut version = [0; 4];
+    version.copy_from_slice(&bytes[4..8]);
+    let version = u32::from_le_bytes(version);
+    if version > VERSION {
+        return Err(format!(
+            "Unsupported version {} of binary Dyon data, expected {} or less",
+            version, VERSION
+        ));
+    }
+    let mut dec = Decoder {
+        bytes,
+        pos: 8,
+        strings: vec![],
+        depth: 0,
+    };
+    let n = dec.seq_len(1)?;
+    for _ in 0..n {
+        let len = dec.len()?;
+        let s = std::str::from_utf8(dec.take(len)?)
+            .map_err(|_| format!("Invalid UTF-8 in string at byte {}", dec.pos - len))?;
+        dec.strings.push(Arc::new(s.into()));
+    }
+    let res = dec.value()?;
+    if dec.pos < bytes.len() {
+        return Err(format!("Unexpected bytes after data at byte {}", dec.pos));
+    }
+    Ok(res)
+}
+
+fn write_len(w: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            w.push(byte);
+            break;
+        }
+        w.push(byte | 0x80);
+    }
+}
+
+struct Encoder {
+    strings: Vec<Arc<String>>,
+    indices: HashMap<Arc<String>, usize>,
+    body: Vec<u8>,
+}
+
+impl Encoder {
+    fn str(&mut self, s: &Arc<String>) {
+        let ind = match self.indices.get(s) {
+            Some(&ind) => ind,
+            None => {
+                let ind = self.strings.len();
+                self.strings.push(s.clone());
+                self.indices.insert(s.clone(), ind);
+                ind
+            }
+        };
+        write_len(&mut self.body, ind);
+    }
+
+    fn vec4(&mut self, v: &[f32; 4]) {
+        for x in v {
+            self.body.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    fn value(&mut self, v: &Variable) -> Result<(), String> {
+        match *v {
+            Variable::Option(None) => self.body.push(NONE),
+            Variable::Option(Some(ref v)) => {
+                self.body.push(SOME);
+                self.value(v)?;
+            }
+            Variable::Bool(false, _) => self.body.push(FALSE),
+            Variable::Bool(true, _) => self.body.push(TRUE),
+            Variable::F64(x, _) => {
+                self.body.push(F64);
+                self.body.extend_from_slice(&x.to_le_bytes());
+            }
+            Variable::Str(ref s) => {
+                self.body.push(STR);
+                self.str(s);
+            }
+            Variable::Vec4(ref v) => {
+                self.body.push(VEC4);
+                self.vec4(v);
+            }
+            Variable::Array(ref arr) => {
+                if !arr.is_empty() && arr.iter().all(|v| matches!(*v, Variable::F64(_, _))) {
+                    self.body.push(F64_ARRAY);
+                    write_len(&mut self.body, arr.len());
+                    for v in arr.iter() {
+                        if let Variable::F64(x, _) = *v {
+                            self.body.extend_from_slice(&x.to_le_bytes());
+                        }
+                    }
+                } else if !arr.is_empty() && arr.iter().all(|v| matches!(*v, Variable::Vec4(_))) {
+                    self.body.push(VEC4_ARRAY);
+                    write_len(&mut self.body, arr.len());
+                    for v in arr.iter() {
+                        if let Variable::Vec4(ref v) = *v {
+                            self.vec4(v);
+                        }
+                    }
+                } else {
+                    self.body.push(ARRAY);
+                    write_len(&mut self.body, arr.len());
+                    for v in arr.iter() {
+                        self.value(v)?;
+                    }
+                }
+            }
+            Variable::Object(ref obj) => {
+                self.body.push(OBJECT);
+                write_len(&mut self.body, obj.len());
+                for (key, v) in obj.iter() {
+                    self.str(key);
+                    self.value(v)?;
+                }
+            }
+            Variable::Map(ref map) => {
+                self.body.push(MAP);
+                write_len(&mut self.body, map.len());
+                for (key, v) in map.iter() {
+                    self.value(&key.to_variable())?;
+                    self.value(v)?;
+                }
+            }
+            Variable::Set(ref set) => {
+                self.body.push(SET);
+                write_len(&mut self.body, set.len());
+                for key in set.iter() {
+                    self.value(&key.to_variable())?;
+                }
+            }
+            Variable::Link(ref link) => {
+                self.body.push(LINK);
+                let n: usize = link.slices.iter().map(|s| (s.end - s.start) as usize).sum();
+                write_len(&mut self.body, n);
+                for slice in &link.slices {
+                    for i in slice.start..slice.end {
+                        self.value(&slice.block.var(i))?;
+                    }
+                }
+            }
+            ref x => {
+                return Err(format!(
+                    "Can not encode `{}` as binary data",
+                    x.typeof_var()
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    strings: Vec<Arc<String>>,
+    /// The number of values being decoded that contain the current one.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if n > self.bytes.len() - self.pos {
+            return Err("Unexpected end of binary data".into());
+        }
+        let res = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let mut res: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.take(1)?[0];
+            if shift >= usize::BITS {
+                return Err(format!("Invalid length at byte {}", self.pos - 1));
+            }
+            res |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Reads the length of a sequence, where every item takes at least `size` bytes.
+    fn seq_len(&mut self, size: usize) -> Result<usize, String> {
+        let n = self.len()?;
+        if n.saturating_mul(size) > self.bytes.len() - self.pos {
+            return Err("Unexpected end of binary data".into());
+        }
+        Ok(n)
+    }
+
+    fn str(&mut self) -> Result<Arc<String>, String> {
+        let pos = self.pos;
+        let ind = self.len()?;
+        match self.strings.get(ind) {
+            Some(s) => Ok(s.clone()),
+            None => Err(format!("Invalid string index {} at byte {}", ind, pos)),
+        }
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(buf))
+    }
+
+    fn vec4(&mut self) -> Result<[f32; 4], String> {
+        let mut res = [0.0; 4];
+        for x in &mut res {
+            let mut buf = [0; 4];
+            buf.copy_from_slice(self.take(4)?);
+            *x = f32::from_le_bytes(buf);
+        }
+        Ok(res)
+    }
+
+    fn key(&mut self) -> Result<Key, String> {
+        let pos = self.pos;
+        let v = self.value()?;
+        Key::from_variable(&v, &[]).ok_or_else(|| format!("Expected hashable key at byte {}", pos))
+    }
+
+    fn value(&mut self) -> Result<Variable, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("Nesting deeper than {} at byte {}", MAX_DEPTH, self.pos));
+        }
+        self.depth += 1;
+        let res = self.tagged_value();
+        self.depth -= 1;
+        res
+    }
+
+    fn tagged_value(&mut self) -> Result<Variable, String> {
+        let pos = self.pos;
+        Ok(match self.take(1)?[0] {
+            NONE => Variable::Option(None),
+            SOME => Variable::Option(Some(Box::new(self.value()?))),
+            FALSE => Variable::bool(false),
+            TRUE => Variable::bool(true),
+            F64 => Variable::f64(self.f64()?),
+            STR => Variable::Str(self.str()?),
+            VEC4 => Variable::Vec4(self.vec4()?),
+            ARRAY => {
+                let n = self.seq_len(1)?;
+                let mut res = Vec::with_capacity(n);
+                for _ in 0..n {
+                    res.push(self.value()?);
+                }
+                Variable::Array(Arc::new(res))
+            }
+            F64_ARRAY => {
+                let n = self.seq_len(8)?;
+                let mut res = Vec::with_capacity(n);
+                for _ in 0..n {
+                    res.push(Variable::f64(self.f64()?));
+                }
+                Variable::Array(Arc::new(res))
+            }
+            VEC4_ARRAY => {
+                let n = self.seq_len(16)?;
+                let mut res = Vec::with_capacity(n);
+                for _ in 0..n {
+                    res.push(Variable::Vec4(self.vec4()?));
+                }
+                Variable::Array(Arc::new(res))
+            }
+            OBJECT => {
+                let n = self.seq_len(2)?;
+                let mut res = HashMap::with_capacity(n);
+                for _ in 0..n {
+                    let key = self.str()?;
+                    res.insert(key, self.value()?);
+                }
+                Variable::Object(Arc::new(res))
+            }
+            MAP => {
+                let n = self.seq_len(2)?;
+                let mut res = HashMap::with_capacity(n);
+                for _ in 0..n {
+                    let key = self.key()?;
+                    res.insert(key, self.value()?);
+                }
+                Variable::Map(Arc::new(res))
+            }
+            SET => {
+                let n = self.seq_len(1)?;
+                let mut res = HashSet::with_capacity(n);
+                for _ in 0..n {
+                    res.insert(self.key()?);
+                }
+                Variable::Set(Arc::new(res))
+            }
+            LINK => {
+                let n = self.seq_len(1)?;
+                let mut link = Link::new();
+                for _ in 0..n {
+                    link.push(&self.value()?)?;
+                }
+                Variable::Link(Box::new(link))
+            }
+            tag => return Err(format!("Unknown tag {} at byte {}", tag, pos)),
+        })
+    }
+}
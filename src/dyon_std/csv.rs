@@ -0,0 +1,421 @@
+//! Reading and writing CSV and TSV.
+//!
+//! Fields may be quoted with `"`, where `""` is an escaped quote.
+//! Quoted fields can contain delimiters and new lines.
+//! Blank lines are skipped.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use crate::Variable;
+
+/// Stores the fields of a record, telling whether each was quoted,
+/// with the line number where the record starts.
+type Record = (Vec<(String, bool)>, usize);
+
+/// Stores options for reading and writing CSV.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The field delimiter.
+    ///
+    /// When `None`, files ending with `.tsv` use tab and everything else uses comma.
+    pub delimiter: Option<char>,
+    /// Whether the first row is a header, such that rows become objects keyed by header.
+    pub header: bool,
+    /// Whether unquoted fields are converted to numbers and bools when possible.
+    pub infer: bool,
+    /// The columns to write when rows are objects.
+    ///
+    /// When `None`, all keys are written in sorted order.
+    pub columns: Option<Vec<Arc<String>>>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            delimiter: None,
+            header: false,
+            infer: true,
+            columns: None,
+        }
+    }
+}
+
+impl Options {
+    /// Reads options from an object, using defaults for missing fields.
+    pub(crate) fn from_variable(v: &Variable) -> Result<Options, String> {
+        let obj = match *v {
+            Variable::Object(ref obj) => obj,
+            _ => return Err("Expected object with options".into()),
+        };
+        let mut res = Options::default();
+        for (key, val) in obj.iter() {
+            match (&***key, val) {
+                ("delimiter", Variable::Str(s)) if s.chars().count() == 1 => {
+                    res.delimiter = s.chars().next()
+                }
+                ("header", &Variable::Bool(x, _)) => res.header = x,
+                ("infer", &Variable::Bool(x, _)) => res.infer = x,
+                ("columns", Variable::Array(arr)) => {
+                    let mut columns = vec![];
+                    for it in arr.iter() {
+                        match *it {
+                            Variable::Str(ref s) => columns.push(s.clone()),
+                            _ => return Err("Expected `[str]` for `columns`".into()),
+                        }
+                    }
+                    res.columns = Some(columns);
+                }
+                ("delimiter", _) => {
+                    return Err("Expected `str` with one character for `delimiter`".into())
+                }
+                ("header", _) | ("infer", _) => {
+                    return Err(format!("Expected `bool` for `{}`", key))
+                }
+                ("columns", _) => return Err("Expected `[str]` for `columns`".into()),
+                _ => return Err(format!("Unknown option `{}`", key)),
+            }
+        }
+        Ok(res)
+    }
+
+    fn delimiter_for(&self, file: Option<&str>) -> char {
+        match (self.delimiter, file) {
+            (Some(ch), _) => ch,
+            (None, Some(file)) if file.ends_with(".tsv") => '\t',
+            (None, _) => ',',
+        }
+    }
+}
+
+/// Reads rows one by one.
+///
+/// With a header, every row is an object keyed by the header.
+/// Otherwise, every row is an array of fields.
+pub struct Rows<R> {
+    read: R,
+    delimiter: char,
+    infer: bool,
+    header: Option<Vec<Arc<String>>>,
+    /// The line number of the next line.
+    line: usize,
+}
+
+impl<R: BufRead> Rows<R> {
+    /// Creates a new row reader.
+    ///
+    /// When the options require a header, it is read right away.
+    pub fn new(read: R, delimiter: char, options: &Options) -> Result<Rows<R>, String> {
+        let mut res = Rows {
+            read,
+            delimiter,
+            infer: options.infer,
+            header: None,
+            line: 1,
+        };
+        if options.header {
+            let header: Vec<Arc<String>> = match res.record()? {
+                None => vec![],
+                Some((fields, _)) => fields.into_iter().map(|(s, _)| Arc::new(s)).collect(),
+            };
+            for (i, name) in header.iter().enumerate() {
+                if header[..i].contains(name) {
+                    return Err(format!("Duplicate header name `{}`", name));
+                }
+            }
+            res.header = Some(header);
+        }
+        Ok(res)
+    }
+
+    /// Reads the next record.
+    fn record(&mut self) -> Result<Option<Record>, String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = self
+                .read
+                .read_line(&mut line)
+                .map_err(|err| format!("Could not read line {}:\n{}", self.line, err))?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if !line.trim_end_matches(['\n', '\r']).is_empty() {
+                break;
+            }
+        }
+        let start = self.line - 1;
+
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(ch) = chars.next() {
+                if in_quotes {
+                    if ch == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(ch);
+                    }
+                } else if ch == '"' && field.is_empty() && !quoted {
+                    in_quotes = true;
+                    quoted = true;
+                } else if ch == self.delimiter {
+                    fields.push((std::mem::take(&mut field), quoted));
+                    quoted = false;
+                } else if ch == '\n' || (ch == '\r' && chars.peek() == Some(&'\n')) {
+                    // End of line.
+                } else {
+                    field.push(ch);
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            // The quoted field continues on the next line.
+            line.clear();
+            let n = self
+                .read
+                .read_line(&mut line)
+                .map_err(|err| format!("Could not read line {}:\n{}", self.line, err))?;
+            if n == 0 {
+                return Err(format!("Unterminated quote in row starting at line {}", start));
+            }
+            self.line += 1;
+        }
+        fields.push((field, quoted));
+        Ok(Some((fields, start)))
+    }
+
+    fn field(&self, text: String, quoted: bool) -> Variable {
+        if self.infer && !quoted {
+            if let Some(v) = infer(&text) {
+                return v;
+            }
+        }
+        Variable::Str(Arc::new(text))
+    }
+
+    /// Reads the next row.
+    pub fn next_row(&mut self) -> Result<Option<Variable>, String> {
+        let (fields, line) = match self.record()? {
+            None => return Ok(None),
+            Some(x) => x,
+        };
+        Ok(Some(match self.header {
+            None => Variable::Array(Arc::new(
+                fields
+                    .into_iter()
+                    .map(|(text, quoted)| self.field(text, quoted))
+                    .collect(),
+            )),
+            Some(ref header) => {
+                if fields.len() != header.len() {
+                    return Err(format!(
+                        "Row at line {} has {} fields, expected {} from header",
+                        line,
+                        fields.len(),
+                        header.len()
+                    ));
+                }
+                let mut obj = HashMap::new();
+                for (key, (text, quoted)) in header.iter().zip(fields) {
+                    obj.insert(key.clone(), self.field(text, quoted));
+                }
+                Variable::Object(Arc::new(obj))
+            }
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for Rows<R> {
+    type Item = Result<Variable, String>;
+
+    fn next(&mut self) -> Option<Result<Variable, String>> {
+        self.next_row().transpose()
+    }
+}
+
+/// Converts an unquoted field to a number or bool when possible.
+fn infer(text: &str) -> Option<Variable> {
+    match text {
+        "true" => return Some(Variable::bool(true)),
+        "false" => return Some(Variable::bool(false)),
+        _ => {}
+    }
+    // Require a digit, such that e.g. `inf` and `NaN` stay text.
+    if text.bytes().any(|b| b.is_ascii_digit()) {
+        if let Ok(x) = text.parse::<f64>() {
+            return Some(Variable::f64(x));
+        }
+    }
+    None
+}
+
+/// Parses CSV from text.
+pub fn parse(text: &str, options: &Options) -> Result<Variable, String> {
+    let rows = Rows::new(text.as_bytes(), options.delimiter_for(None), options)?;
+    Ok(Variable::Array(Arc::new(rows.collect::<Result<_, _>>()?)))
+}
+
+/// Opens a file for reading rows one by one.
+#[cfg(feature = "file")]
+pub fn open_file(
+    file: &str,
+    options: &Options,
+) -> Result<Rows<std::io::BufReader<std::fs::File>>, String> {
+    use super::io::io_error;
+
+    let f = std::fs::File::open(file).map_err(|err| io_error("open", file, &err))?;
+    Rows::new(std::io::BufReader::new(f), options.delimiter_for(Some(file)), options)
+}
+
+/// Loads CSV from a file.
+#[cfg(feature = "file")]
+pub fn load_file(file: &str, options: &Options) -> Result<Variable, String> {
+    let rows = open_file(file, options)?;
+    Ok(Variable::Array(Arc::new(rows.collect::<Result<_, _>>()?)))
+}
+
+/// Loads CSV from a file.
+#[cfg(not(feature = "file"))]
+pub fn load_file(_: &str, _: &Options) -> Result<Variable, String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Writes rows to CSV text.
+///
+/// Rows are arrays of fields, or objects that are written with a header.
+pub fn write(rows: &Variable, options: &Options) -> Result<String, String> {
+    write_with_delimiter(rows, options.delimiter_for(None), options)
+}
+
+/// Saves rows to a CSV file.
+#[cfg(feature = "file")]
+pub fn save_file(rows: &Variable, file: &str, options: &Options) -> Result<(), String> {
+    use super::io::io_error;
+
+    let text = write_with_delimiter(rows, options.delimiter_for(Some(file)), options)?;
+    std::fs::write(file, text).map_err(|err| io_error("write", file, &err))
+}
+
+/// Saves rows to a CSV file.
+#[cfg(not(feature = "file"))]
+pub fn save_file(_: &Variable, _: &str, _: &Options) -> Result<(), String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+fn write_with_delimiter(
+    rows: &Variable,
+    delimiter: char,
+    options: &Options,
+) -> Result<String, String> {
+    let rows = match *rows {
+        Variable::Array(ref arr) => arr,
+        _ => return Err("Expected array of rows".into()),
+    };
+    let mut res = String::new();
+    let objects = matches!(rows.first(), Some(Variable::Object(_)));
+    let columns = if objects {
+        let columns = match options.columns {
+            Some(ref columns) => columns.clone(),
+            None => {
+                let mut columns: Vec<Arc<String>> = vec![];
+                for row in rows.iter() {
+                    if let Variable::Object(ref obj) = *row {
+                        for key in obj.keys() {
+                            if !columns.contains(key) {
+                                columns.push(key.clone());
+                            }
+                        }
+                    }
+                }
+                columns.sort();
+                columns
+            }
+        };
+        let header: Vec<Variable> = columns.iter().map(|c| Variable::Str(c.clone())).collect();
+        write_row(&mut res, &header, delimiter, 0)?;
+        columns
+    } else {
+        vec![]
+    };
+    for (i, row) in rows.iter().enumerate() {
+        match *row {
+            Variable::Array(ref fields) if !objects => write_row(&mut res, fields, delimiter, i)?,
+            Variable::Object(ref obj) if objects => {
+                let fields: Vec<Variable> = columns
+                    .iter()
+                    .map(|c| obj.get(c).cloned().unwrap_or(Variable::Option(None)))
+                    .collect();
+                write_row(&mut res, &fields, delimiter, i)?;
+            }
+            _ => {
+                return Err(format!(
+                    "Expected row {} to be {}",
+                    i,
+                    if objects { "an object" } else { "an array" }
+                ))
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn write_row(
+    w: &mut String,
+    fields: &[Variable],
+    delimiter: char,
+    row: usize,
+) -> Result<(), String> {
+    use std::fmt::Write;
+
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            w.push(delimiter);
+        }
+        let field = match *field {
+            Variable::Option(Some(ref v)) => v,
+            ref x => x,
+        };
+        match *field {
+            Variable::Str(ref s) => {
+                // Quote text that would otherwise be read as something else.
+                // An empty row would be read as a blank line, which is skipped.
+                let quote = s.is_empty()
+                    || s.contains([delimiter, '"', '\n', '\r'])
+                    || s.trim() != **s
+                    || infer(s).is_some();
+                if quote {
+                    w.push('"');
+                    w.push_str(&s.replace('"', "\"\""));
+                    w.push('"');
+                } else {
+                    w.push_str(s);
+                }
+            }
+            Variable::F64(x, _) => write!(w, "{}", x).unwrap(),
+            Variable::Bool(x, _) => w.push_str(if x { "true" } else { "false" }),
+            Variable::Option(None) => {}
+            ref x => {
+                return Err(format!(
+                    "Can not write `{}` in row {}, field {}",
+                    x.typeof_var(),
+                    row,
+                    i
+                ))
+            }
+        }
+    }
+    w.push('\n');
+    Ok(())
+}
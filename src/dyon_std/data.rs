@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 #[cfg(feature = "file")]
 use std::fs::File;
 #[cfg(feature = "file")]
@@ -11,10 +11,58 @@ use read_token::{NumberSettings, ReadToken};
 #[cfg(feature = "file")]
 use super::io::io_error;
 
-use crate::Variable;
+use crate::{Key, Runtime, Variable};
 
 type Strings = HashSet<Arc<String>>;
 
+/// Stores state while loading data.
+struct State {
+    strings: Strings,
+    /// The path of the current value, e.g. `units[3].speed`.
+    path: String,
+    /// Source ranges of values by path, when tracked.
+    ranges: Option<HashMap<String, Range>>,
+    /// Comments and key order, when tracked.
+    layout: Option<Layout>,
+    /// Comments that are not yet assigned to a value.
+    pending: Vec<String>,
+}
+
+impl State {
+    fn new(track_ranges: bool) -> State {
+        State {
+            strings: HashSet::new(),
+            path: String::new(),
+            ranges: if track_ranges { Some(HashMap::new()) } else { None },
+            layout: None,
+            pending: vec![],
+        }
+    }
+
+    /// Returns `true` if the path of the current value is tracked.
+    fn tracks_path(&self) -> bool {
+        self.ranges.is_some() || self.layout.is_some()
+    }
+}
+
+/// Stores comments and key order of objects in loaded data, by path.
+///
+/// This is used to keep comments when saving data that was loaded from a file.
+/// Only comments in objects and arrays are tracked.
+#[derive(Debug, Default)]
+pub(crate) struct Layout {
+    /// Comments on the lines before a value.
+    pub comments: HashMap<String, Vec<String>>,
+    /// Comment on the same line after a value.
+    pub trailing: HashMap<String, String>,
+    /// Comments before the end of an object or array.
+    pub end_comments: HashMap<String, Vec<String>>,
+    /// Comments after the root value.
+    pub footer: Vec<String>,
+    /// Keys of objects in the order they were loaded.
+    pub keys: HashMap<String, Vec<Arc<String>>>,
+}
+
 /// Loads data from a file.
 #[cfg(feature = "file")]
 pub fn load_file(file: &str) -> Result<Variable, String> {
@@ -34,9 +82,35 @@ pub fn load_file(_: &str) -> Result<Variable, String> {
 /// Loads data from text.
 pub fn load_data(data: &str) -> Result<Variable, String> {
     let mut read = ReadToken::new(data, 0);
-    let mut strings: Strings = HashSet::new();
+    let mut state = State::new(false);
     opt_w(&mut read);
-    expr(&mut read, &mut strings, data)
+    expr(&mut read, &mut state, data)
+}
+
+/// Loads data from text, together with the source range of every value in objects and arrays.
+///
+/// The ranges are stored by path, e.g. `units[3].speed`, where the path of the root is empty.
+pub fn load_data_ranges(data: &str) -> Result<(Variable, HashMap<String, Range>), String> {
+    let mut read = ReadToken::new(data, 0);
+    let mut state = State::new(true);
+    opt_w(&mut read);
+    let res = expr(&mut read, &mut state, data)?;
+    Ok((res, state.ranges.unwrap_or_default()))
+}
+
+/// Loads data from text, together with its comments and key order.
+#[cfg(feature = "file")]
+pub(crate) fn load_layout(data: &str) -> Result<(Variable, Layout), String> {
+    let mut read = ReadToken::new(data, 0);
+    let mut state = State::new(false);
+    state.layout = Some(Layout::default());
+    opt_w_layout(&mut read, &mut state, false);
+    take_comments(&mut state, |layout| &mut layout.comments);
+    let res = expr(&mut read, &mut state, data)?;
+    opt_w_layout(&mut read, &mut state, true);
+    let mut layout = state.layout.unwrap();
+    layout.footer = state.pending;
+    Ok((res, layout))
 }
 
 static NUMBER_SETTINGS: NumberSettings = NumberSettings {
@@ -45,16 +119,26 @@ static NUMBER_SETTINGS: NumberSettings = NumberSettings {
 
 const SEPS: &str = "(){}[],.:;\n\"\\";
 
-fn expr(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Variable, String> {
+fn expr(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
+    let start = read.start().offset;
+    let res = value(read, state, data)?;
+    if let Some(ref mut ranges) = state.ranges {
+        let end = read.start().offset;
+        ranges.insert(state.path.clone(), Range::new(start, end - start));
+    }
+    Ok(res)
+}
+
+fn value(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
     if let Some(range) = read.tag("{") {
         // Object.
         *read = read.consume(range.length);
-        return object(read, strings, data);
+        return object(read, state, data);
     }
     if let Some(range) = read.tag("[") {
         // Array.
         *read = read.consume(range.length);
-        return array(read, strings, data);
+        return array(read, state, data);
     }
     if let Some(range) = read.tag("(") {
         // Vec4.
@@ -87,14 +171,24 @@ fn expr(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Varia
     if let Some(range) = read.tag("link") {
         // Link.
         *read = read.consume(range.length);
-        return link(read, strings, data);
+        return link(read, state, data);
+    }
+    if let Some(range) = read.tag("map") {
+        // Map.
+        *read = read.consume(range.length);
+        return map(read, state, data);
+    }
+    if let Some(range) = read.tag("set") {
+        // Set.
+        *read = read.consume(range.length);
+        return set(read, state, data);
     }
     // Text.
     if let Some(range) = read.string() {
         match read.parse_string(range.length) {
             Ok(s) => {
                 *read = read.consume(range.length);
-                return Ok(Variable::Str(if let Some(s) = strings.get(&s) {
+                return Ok(Variable::Str(if let Some(s) = state.strings.get(&s) {
                     s.clone()
                 } else {
                     Arc::new(s)
@@ -133,7 +227,7 @@ fn expr(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Varia
     if let Some(range) = read.tag("some(") {
         *read = read.consume(range.length);
         opt_w(read);
-        let res = expr(read, strings, data)?;
+        let res = expr(read, state, data)?;
         opt_w(read);
         return if let Some(range) = read.tag(")") {
             *read = read.consume(range.length);
@@ -145,16 +239,15 @@ fn expr(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Varia
     Err(error(read.start(), "Reached end of file", data))
 }
 
-fn object(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Variable, String> {
-    use std::collections::HashMap;
-
+fn object(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
     let mut res: HashMap<Arc<String>, Variable> = HashMap::new();
     let mut was_comma = false;
     loop {
-        opt_w(read);
+        opt_w_layout(read, state, false);
 
         if let Some(range) = read.tag("}") {
             *read = read.consume(range.length);
+            take_comments(state, |layout| &mut layout.end_comments);
             break;
         }
 
@@ -167,7 +260,7 @@ fn object(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Var
             match read.parse_string(range.length) {
                 Ok(s) => {
                     // Use reference to existing string to reduce memory.
-                    key = if let Some(s) = strings.get(&s) {
+                    key = if let Some(s) = state.strings.get(&s) {
                         s.clone()
                     } else {
                         Arc::new(s)
@@ -186,7 +279,7 @@ fn object(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Var
             } else {
                 let k = read.raw_string(range.length);
                 // Use reference to existing string to reduce memory.
-                key = if let Some(s) = strings.get(&k) {
+                key = if let Some(s) = state.strings.get(&k) {
                     s.clone()
                 } else {
                     Arc::new(k)
@@ -205,21 +298,38 @@ fn object(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Var
 
         opt_w(read);
 
-        res.insert(key, expr(read, strings, data)?);
-
-        was_comma = comma(read);
+        let len = state.path.len();
+        if state.tracks_path() {
+            if let Some(ref mut layout) = state.layout {
+                layout
+                    .keys
+                    .entry(state.path.clone())
+                    .or_default()
+                    .push(key.clone());
+            }
+            if len > 0 {
+                state.path.push('.');
+            }
+            state.path.push_str(&key);
+            take_comments(state, |layout| &mut layout.comments);
+        }
+        let val = expr(read, state, data)?;
+        was_comma = comma_layout(read, state);
+        state.path.truncate(len);
+        res.insert(key, val);
     }
     Ok(Variable::Object(Arc::new(res)))
 }
 
-fn array(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Variable, String> {
+fn array(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
     let mut res = vec![];
     let mut was_comma = false;
     loop {
-        opt_w(read);
+        opt_w_layout(read, state, false);
 
         if let Some(range) = read.tag("]") {
             *read = read.consume(range.length);
+            take_comments(state, |layout| &mut layout.end_comments);
             break;
         }
 
@@ -227,13 +337,20 @@ fn array(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Vari
             return Err(error(read.start(), "Expected `,`", data));
         }
 
-        res.push(expr(read, strings, data)?);
-        was_comma = comma(read);
+        let len = state.path.len();
+        if state.tracks_path() {
+            state.path.push_str(&format!("[{}]", res.len()));
+            take_comments(state, |layout| &mut layout.comments);
+        }
+        let val = expr(read, state, data)?;
+        was_comma = comma_layout(read, state);
+        state.path.truncate(len);
+        res.push(val);
     }
     Ok(Variable::Array(Arc::new(res)))
 }
 
-fn link(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Variable, String> {
+fn link(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
     use crate::Link;
 
     opt_w(read);
@@ -256,7 +373,7 @@ fn link(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Varia
             break;
         }
 
-        match link.push(&expr(read, strings, data)?) {
+        match link.push(&expr(read, state, data)?) {
             Ok(()) => {}
             Err(err) => return Err(err),
         };
@@ -264,6 +381,87 @@ fn link(read: &mut ReadToken, strings: &mut Strings, data: &str) -> Result<Varia
     Ok(Variable::Link(Box::new(link)))
 }
 
+/// Reads a key of a map or set.
+fn key(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Key, String> {
+    let start = read.start();
+    let v = expr(read, state, data)?;
+    match Key::from_variable(&v, &[]) {
+        Some(key) => Ok(key),
+        None => Err(error(start, "Expected hashable key", data)),
+    }
+}
+
+fn map(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
+    opt_w(read);
+
+    if let Some(range) = read.tag("{") {
+        *read = read.consume(range.length);
+    } else {
+        return Err(error(read.start(), "Expected `{`", data));
+    }
+
+    let mut res: HashMap<Key, Variable> = HashMap::new();
+    let mut was_comma = false;
+    loop {
+        opt_w(read);
+
+        if let Some(range) = read.tag("}") {
+            *read = read.consume(range.length);
+            break;
+        }
+
+        if !res.is_empty() && !was_comma {
+            return Err(error(read.start(), "Expected `,`", data));
+        }
+
+        let key = key(read, state, data)?;
+
+        opt_w(read);
+
+        if let Some(range) = read.tag(":") {
+            *read = read.consume(range.length);
+        } else {
+            return Err(error(read.start(), "Expected `:`", data));
+        }
+
+        opt_w(read);
+
+        res.insert(key, expr(read, state, data)?);
+
+        was_comma = comma(read);
+    }
+    Ok(Variable::Map(Arc::new(res)))
+}
+
+fn set(read: &mut ReadToken, state: &mut State, data: &str) -> Result<Variable, String> {
+    opt_w(read);
+
+    if let Some(range) = read.tag("{") {
+        *read = read.consume(range.length);
+    } else {
+        return Err(error(read.start(), "Expected `{`", data));
+    }
+
+    let mut res: HashSet<Key> = HashSet::new();
+    let mut was_comma = false;
+    loop {
+        opt_w(read);
+
+        if let Some(range) = read.tag("}") {
+            *read = read.consume(range.length);
+            break;
+        }
+
+        if !res.is_empty() && !was_comma {
+            return Err(error(read.start(), "Expected `,`", data));
+        }
+
+        res.insert(key(read, state, data)?);
+        was_comma = comma(read);
+    }
+    Ok(Variable::Set(Arc::new(res)))
+}
+
 fn vec4(read: &mut ReadToken, data: &str) -> Result<Variable, String> {
     let x = if let Some(range) = read.number(&NUMBER_SETTINGS) {
         match read.parse_number(&NUMBER_SETTINGS, range.length) {
@@ -387,6 +585,74 @@ fn multi_line_comment(read: &mut ReadToken) {
     }
 }
 
+/// Reads optional whitespace including comments, keeping the comments when tracked.
+///
+/// When `trailing` is `true`, a comment on the same line is kept as the trailing
+/// comment of the current value. Other comments wait to be assigned to a value.
+fn opt_w_layout(read: &mut ReadToken, state: &mut State, trailing: bool) {
+    if state.layout.is_none() {
+        opt_w(read);
+        return;
+    }
+    let mut newline = false;
+    loop {
+        let start = *read;
+        let range = read.whitespace();
+        if read.raw_string(range.length).contains('\n') {
+            newline = true;
+        }
+        *read = read.consume(range.length);
+
+        let comment_start = *read;
+        if let Some(range) = read.tag("//") {
+            *read = read.consume(range.length);
+            let (range, _) = read.until_any("\n");
+            *read = read.consume(range.length);
+        } else {
+            multi_line_comment(read);
+        }
+        let n = read.subtract(&comment_start).length;
+        if n > 0 {
+            let text = comment_start.raw_string(n);
+            let layout = state.layout.as_mut().unwrap();
+            if trailing && !newline && !layout.trailing.contains_key(&state.path) {
+                layout.trailing.insert(state.path.clone(), text);
+            } else {
+                state.pending.push(text);
+            }
+        }
+
+        if read.subtract(&start).length == 0 {
+            break;
+        }
+    }
+}
+
+/// Assigns the waiting comments to the current path.
+fn take_comments<F>(state: &mut State, f: F)
+where
+    F: FnOnce(&mut Layout) -> &mut HashMap<String, Vec<String>>,
+{
+    if let Some(ref mut layout) = state.layout {
+        if !state.pending.is_empty() {
+            let comments = std::mem::take(&mut state.pending);
+            f(layout).insert(state.path.clone(), comments);
+        }
+    }
+}
+
+/// Reads comma, keeping comments when tracked.
+fn comma_layout(read: &mut ReadToken, state: &mut State) -> bool {
+    let mut res = false;
+    opt_w_layout(read, state, true);
+    if let Some(range) = read.tag(",") {
+        *read = read.consume(range.length);
+        res = true;
+    }
+    opt_w_layout(read, state, true);
+    res
+}
+
 /// Reads comma.
 fn comma(read: &mut ReadToken) -> bool {
     let mut res = false;
@@ -399,8 +665,366 @@ fn comma(read: &mut ReadToken) -> bool {
     res
 }
 
+/// The order of keys in objects when writing data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum KeyOrder {
+    /// Sorts keys alphabetically.
+    Sorted,
+    /// Keeps the order of keys from the loaded file, with new keys sorted at the end.
+    Insertion,
+}
+
+/// Stores options for writing data.
+#[derive(Clone, Debug)]
+pub(crate) struct Options {
+    pub keys: KeyOrder,
+    /// The number of spaces per indention level.
+    pub indent: usize,
+    /// The maximum line width before objects and arrays are wrapped.
+    pub width: usize,
+    /// Whether to write a comma after the last item of wrapped objects and arrays.
+    pub trailing_comma: bool,
+    /// Whether to keep comments from the loaded file.
+    pub comments: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            keys: KeyOrder::Sorted,
+            indent: 4,
+            width: 80,
+            trailing_comma: false,
+            comments: true,
+        }
+    }
+}
+
+impl Options {
+    /// Reads options from an object, using defaults for missing fields.
+    pub fn from_variable(v: &Variable) -> Result<Options, String> {
+        let obj = match *v {
+            Variable::Object(ref obj) => obj,
+            _ => return Err("Expected object with options".into()),
+        };
+        let mut res = Options::default();
+        for (key, val) in obj.iter() {
+            match (&***key, val) {
+                ("keys", Variable::Str(order)) => {
+                    res.keys = match &***order {
+                        "sorted" => KeyOrder::Sorted,
+                        "insertion" => KeyOrder::Insertion,
+                        _ => {
+                            return Err(format!(
+                                "Expected `\"sorted\"` or `\"insertion\"`, found `\"{}\"`",
+                                order
+                            ))
+                        }
+                    }
+                }
+                ("indent", &Variable::F64(x, _)) if x >= 0.0 => res.indent = x as usize,
+                ("width", &Variable::F64(x, _)) if x >= 0.0 => res.width = x as usize,
+                ("trailing_comma", &Variable::Bool(x, _)) => res.trailing_comma = x,
+                ("comments", &Variable::Bool(x, _)) => res.comments = x,
+                ("keys", _) => return Err("Expected `str` for `keys`".into()),
+                ("indent", _) | ("width", _) => {
+                    return Err(format!("Expected non-negative `f64` for `{}`", key))
+                }
+                ("trailing_comma", _) | ("comments", _) => {
+                    return Err(format!("Expected `bool` for `{}`", key))
+                }
+                _ => return Err(format!("Unknown option `{}`", key)),
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Writes data to text, wrapping objects and arrays that do not fit on a line.
+///
+/// When a layout is given, comments are written back to values with the same path.
+pub(crate) fn write_data(
+    rt: &Runtime,
+    v: &Variable,
+    options: &Options,
+    layout: Option<&Layout>,
+) -> String {
+    let layout = if options.comments || options.keys == KeyOrder::Insertion {
+        layout
+    } else {
+        None
+    };
+    let mut w = Writer {
+        rt,
+        options,
+        layout,
+        out: String::new(),
+        path: String::new(),
+    };
+    w.comments(0);
+    w.value(v, 0, 0);
+    if let Some(comment) = w.trailing() {
+        w.out.push(' ');
+        w.out.push_str(&comment);
+    }
+    w.out.push('\n');
+    if let Some(layout) = w.layout {
+        for comment in &layout.footer {
+            w.out.push_str(comment);
+            w.out.push('\n');
+        }
+    }
+    w.out
+}
+
+struct Writer<'a> {
+    rt: &'a Runtime,
+    options: &'a Options,
+    layout: Option<&'a Layout>,
+    out: String,
+    /// The path of the current value.
+    path: String,
+}
+
+impl<'a> Writer<'a> {
+    fn comments_enabled(&self) -> Option<&'a Layout> {
+        if self.options.comments {
+            self.layout
+        } else {
+            None
+        }
+    }
+
+    /// Writes the comments before the current value, one per line.
+    fn comments(&mut self, tabs: usize) {
+        if let Some(layout) = self.comments_enabled() {
+            if let Some(comments) = layout.comments.get(&self.path) {
+                for comment in comments {
+                    self.indent(tabs);
+                    self.out.push_str(comment);
+                    self.out.push('\n');
+                }
+            }
+        }
+    }
+
+    /// Returns the trailing comment of the current value.
+    fn trailing(&self) -> Option<String> {
+        self.comments_enabled()
+            .and_then(|layout| layout.trailing.get(&self.path).cloned())
+    }
+
+    /// Returns `true` if there are comments inside the current value.
+    fn has_inner_comments(&self) -> bool {
+        let layout = match self.comments_enabled() {
+            None => return false,
+            Some(layout) => layout,
+        };
+        let inner = |p: &String| {
+            p.len() > self.path.len()
+                && p.starts_with(&self.path)
+                && (self.path.is_empty() || matches!(p.as_bytes()[self.path.len()], b'.' | b'['))
+        };
+        layout.end_comments.contains_key(&self.path)
+            || layout.comments.keys().any(inner)
+            || layout.trailing.keys().any(inner)
+    }
+
+    fn indent(&mut self, tabs: usize) {
+        for _ in 0..tabs * self.options.indent {
+            self.out.push(' ');
+        }
+    }
+
+    /// Returns the keys of an object in the order they should be written.
+    fn keys(&self, obj: &'a HashMap<Arc<String>, Variable>) -> Vec<&'a Arc<String>> {
+        let mut keys: Vec<&Arc<String>> = obj.keys().collect();
+        keys.sort();
+        if self.options.keys == KeyOrder::Insertion {
+            if let Some(order) = self.layout.and_then(|layout| layout.keys.get(&self.path)) {
+                let mut res: Vec<&Arc<String>> = order
+                    .iter()
+                    .filter_map(|k| obj.get_key_value(k).map(|(k, _)| k))
+                    .collect();
+                keys.retain(|k| !order.contains(k));
+                res.extend(keys);
+                return res;
+            }
+        }
+        keys
+    }
+
+    fn push_path_key(&mut self, key: &str) {
+        if !self.path.is_empty() {
+            self.path.push('.');
+        }
+        self.path.push_str(key);
+    }
+
+    /// Writes a value that starts at column `col`.
+    fn value(&mut self, v: &Variable, tabs: usize, col: usize) {
+        let v = self.rt.resolve(v);
+        let (start, end, empty) = match *v {
+            Variable::Object(ref obj) => ("{", "}", obj.is_empty()),
+            Variable::Array(ref arr) => ("[", "]", arr.is_empty()),
+            Variable::Map(ref map) => ("map {", "}", map.is_empty()),
+            Variable::Set(ref set) => ("set {", "}", set.is_empty()),
+            _ => {
+                let text = self.leaf(v);
+                self.out.push_str(&text);
+                return;
+            }
+        };
+        if !self.has_inner_comments() {
+            let text = self.compact(v);
+            // Leave room for a comma after the value.
+            if empty || col + text.chars().count() < self.options.width {
+                self.out.push_str(&text);
+                return;
+            }
+        }
+
+        self.out.push_str(start);
+        self.out.push('\n');
+        let inner = tabs + 1;
+        let col = inner * self.options.indent;
+        let len = self.path.len();
+        match *v {
+            Variable::Object(ref obj) => {
+                let keys = self.keys(obj);
+                let n = keys.len();
+                for (i, key) in keys.into_iter().enumerate() {
+                    self.push_path_key(key);
+                    self.comments(inner);
+                    self.indent(inner);
+                    let key_text = key_to_string(key);
+                    self.out.push_str(&key_text);
+                    self.out.push_str(": ");
+                    self.value(&obj[key], inner, col + key_text.chars().count() + 2);
+                    self.item_end(i + 1 == n);
+                    self.path.truncate(len);
+                }
+            }
+            Variable::Array(ref arr) => {
+                let n = arr.len();
+                for (i, item) in arr.iter().enumerate() {
+                    self.path.push_str(&format!("[{}]", i));
+                    self.comments(inner);
+                    self.indent(inner);
+                    self.value(item, inner, col);
+                    self.item_end(i + 1 == n);
+                    self.path.truncate(len);
+                }
+            }
+            Variable::Map(ref map) => {
+                let mut items: Vec<(String, &Variable)> = map
+                    .iter()
+                    .map(|(k, v)| (self.compact(&k.to_variable()), v))
+                    .collect();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                let n = items.len();
+                for (i, (key_text, val)) in items.into_iter().enumerate() {
+                    self.indent(inner);
+                    self.out.push_str(&key_text);
+                    self.out.push_str(": ");
+                    self.value(val, inner, col + key_text.chars().count() + 2);
+                    self.item_end(i + 1 == n);
+                }
+            }
+            Variable::Set(ref set) => {
+                let mut items: Vec<String> =
+                    set.iter().map(|k| self.compact(&k.to_variable())).collect();
+                items.sort();
+                let n = items.len();
+                for (i, text) in items.into_iter().enumerate() {
+                    self.indent(inner);
+                    self.out.push_str(&text);
+                    self.item_end(i + 1 == n);
+                }
+            }
+            _ => unreachable!(),
+        }
+        if let Some(layout) = self.comments_enabled() {
+            if let Some(comments) = layout.end_comments.get(&self.path) {
+                for comment in comments {
+                    self.indent(inner);
+                    self.out.push_str(comment);
+                    self.out.push('\n');
+                }
+            }
+        }
+        self.indent(tabs);
+        self.out.push_str(end);
+    }
+
+    /// Writes the comma and trailing comment after an item of a wrapped value.
+    fn item_end(&mut self, last: bool) {
+        if !last || self.options.trailing_comma {
+            self.out.push(',');
+        }
+        if let Some(comment) = self.trailing() {
+            self.out.push(' ');
+            self.out.push_str(&comment);
+        }
+        self.out.push('\n');
+    }
+
+    /// Returns a value written on a single line.
+    fn compact(&self, v: &Variable) -> String {
+        let v = self.rt.resolve(v);
+        match *v {
+            Variable::Object(ref obj) => {
+                let items: Vec<String> = self
+                    .keys(obj)
+                    .into_iter()
+                    .map(|k| format!("{}: {}", key_to_string(k), self.compact(&obj[k])))
+                    .collect();
+                format!("{{{}}}", items.join(", "))
+            }
+            Variable::Array(ref arr) => {
+                let items: Vec<String> = arr.iter().map(|v| self.compact(v)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Variable::Map(ref map) => {
+                let mut items: Vec<String> = map
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", self.compact(&k.to_variable()), self.compact(v)))
+                    .collect();
+                items.sort();
+                format!("map {{{}}}", items.join(", "))
+            }
+            Variable::Set(ref set) => {
+                let mut items: Vec<String> =
+                    set.iter().map(|k| self.compact(&k.to_variable())).collect();
+                items.sort();
+                format!("set {{{}}}", items.join(", "))
+            }
+            _ => self.leaf(v),
+        }
+    }
+
+    fn leaf(&self, v: &Variable) -> String {
+        use crate::write::{write_variable, EscapeString};
+
+        let mut buf: Vec<u8> = vec![];
+        write_variable(&mut buf, self.rt, v, EscapeString::Json, 0).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+/// Writes a key, using quotes when it is not an identifier.
+fn key_to_string(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        key.into()
+    } else {
+        let mut buf: Vec<u8> = vec![];
+        piston_meta::json::write_string(&mut buf, key).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+}
+
 /// Generates error message using Piston-Meta's error handler.
-fn error(range: Range, msg: &str, data: &str) -> String {
+pub(crate) fn error(range: Range, msg: &str, data: &str) -> String {
     use piston_meta::ParseErrorHandler;
 
     let mut handler = ParseErrorHandler::new(data);
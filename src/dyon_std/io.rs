@@ -14,3 +14,153 @@ pub fn io_error(action: &str, file: &str, err: &io::Error) -> String {
         }
     )
 }
+
+/// Returns the entries of a directory as paths, in sorted order.
+pub fn read_dir(dir: &str) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|err| io_error("read directory", dir, &err))?;
+    let mut res = vec![];
+    for entry in entries {
+        let entry = entry.map_err(|err| io_error("read directory", dir, &err))?;
+        res.push(entry.path().to_string_lossy().into_owned());
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// Returns the time a file was last modified, in seconds since the UNIX epoch.
+pub fn modified_time(file: &str) -> Result<f64, String> {
+    use std::time::UNIX_EPOCH;
+
+    let time = std::fs::metadata(file)
+        .and_then(|meta| meta.modified())
+        .map_err(|err| io_error("read modified time of", file, &err))?;
+    Ok(match time.duration_since(UNIX_EPOCH) {
+        Ok(val) => val.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    })
+}
+
+/// Returns the paths matching a glob pattern, in sorted order.
+///
+/// - `?` matches any character
+/// - `*` matches any sequence of characters within a path component
+/// - `**` matches any sequence of directories, or everything when it comes last
+/// - `[abc]`, `[a-z]` and `[!abc]` match a character in or not in a set
+pub fn glob(pattern: &str) -> Result<Vec<String>, String> {
+    use std::path::PathBuf;
+
+    let comps: Vec<&str> = pattern.split('/').collect();
+    let (start, comps) = match comps.split_first() {
+        Some((&"", rest)) if !rest.is_empty() => (PathBuf::from("/"), rest),
+        _ => (PathBuf::new(), &comps[..]),
+    };
+    let mut res = vec![];
+    glob_walk(start, comps, &mut res);
+    res.sort();
+    res.dedup();
+    Ok(res)
+}
+
+fn glob_walk(path: std::path::PathBuf, comps: &[&str], res: &mut Vec<String>) {
+    let (comp, rest) = match comps.split_first() {
+        None => {
+            res.push(path.to_string_lossy().into_owned());
+            return;
+        }
+        Some(x) => x,
+    };
+    let is_wildcard = |s: &str| s.contains(['*', '?', '[']);
+    if comp.is_empty() || !is_wildcard(comp) {
+        let next = path.join(comp);
+        if next.exists() {
+            glob_walk(next, rest, res);
+        }
+        return;
+    }
+    let dir = if path.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        &path
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    if *comp == "**" {
+        glob_walk(path.clone(), rest, res);
+    }
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if *comp == "**" {
+            // Symbolic links are not followed, which avoids cycles.
+            if entry.file_type().map(|ty| ty.is_dir()).unwrap_or(false) {
+                glob_walk(path.join(&name), comps, res);
+            } else if rest.is_empty() {
+                res.push(path.join(&name).to_string_lossy().into_owned());
+            }
+        } else if wildcard(&comp.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>()) {
+            glob_walk(path.join(&name), rest, res);
+        }
+    }
+}
+
+/// Returns `true` if a text matches a wildcard pattern.
+///
+/// Only the last `*` is backtracked, so this takes at most `O(pattern * text)` steps.
+fn wildcard(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern after the last `*` and where it was tried in the text.
+    let mut star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, t));
+        } else if let Some(n) = wildcard_char(&pattern[p..], text[t]) {
+            p += n;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` match one more character.
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches a character with the start of a wildcard pattern that is not `*`.
+///
+/// Returns the length of the matching part of the pattern.
+fn wildcard_char(pattern: &[char], ch: char) -> Option<usize> {
+    match pattern.first() {
+        None => None,
+        Some('?') => Some(1),
+        Some('[') => {
+            let end = match pattern.iter().skip(2).position(|&c| c == ']') {
+                Some(i) => i + 2,
+                // Treat unmatched `[` as a character.
+                None => return if ch == '[' { Some(1) } else { None },
+            };
+            let (negate, set) = match pattern[1] {
+                '!' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    found |= set[i] <= ch && ch <= set[i + 2];
+                    i += 3;
+                } else {
+                    found |= set[i] == ch;
+                    i += 1;
+                }
+            }
+            if found != negate { Some(end + 1) } else { None }
+        }
+        Some(&c) if c == ch => Some(1),
+        Some(_) => None,
+    }
+}
@@ -0,0 +1,157 @@
+//! Converts between Dyon values and JSON.
+//!
+//! - `null` becomes `none()`, and `none()` becomes `null`
+//! - numbers become `f64`, and `f64` that is not finite becomes `null`
+//! - `some(x)` becomes `x`
+//! - `ok(x)` becomes `{"ok": x}` and `err(x)` becomes `{"err": x}`
+//! - vec4 becomes an array of 4 numbers
+//! - mat4 becomes an array of 4 rows
+//! - maps with string keys become objects
+//! - sets and links become arrays
+
+#[cfg(feature = "file")]
+use std::fs::File;
+#[cfg(feature = "file")]
+use std::io::Read;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use range::Range;
+use serde_json::{Map, Number, Value};
+
+#[cfg(feature = "file")]
+use super::io::io_error;
+use super::data::error;
+
+use crate::{Key, Variable};
+
+/// Loads JSON from a file.
+#[cfg(feature = "file")]
+pub fn load_file(file: &str) -> Result<Variable, String> {
+    let mut data_file = File::open(file).map_err(|err| io_error("open", file, &err))?;
+    let mut d = String::new();
+    data_file
+        .read_to_string(&mut d)
+        .map_err(|err| io_error("read", file, &err))?;
+    load_json(&d)
+}
+
+#[cfg(not(feature = "file"))]
+pub fn load_file(_: &str) -> Result<Variable, String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Loads JSON from text.
+pub fn load_json(data: &str) -> Result<Variable, String> {
+    match serde_json::from_str::<Value>(data) {
+        Ok(val) => Ok(from_json_value(&val)),
+        Err(err) => {
+            // Convert line and column to an offset in the text.
+            let mut offset: usize = data
+                .split_inclusive('\n')
+                .take(err.line().saturating_sub(1))
+                .map(|line| line.len())
+                .sum();
+            offset += err.column().saturating_sub(1);
+            while offset > data.len() || !data.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            let msg = err.to_string();
+            // Remove the position, since it is shown by the error reporter.
+            let msg = match msg.rfind(" at line ") {
+                Some(i) => &msg[..i],
+                None => &msg,
+            };
+            Err(error(Range::new(offset, 0), msg, data))
+        }
+    }
+}
+
+/// Converts a JSON value to a Dyon value.
+pub fn from_json_value(val: &Value) -> Variable {
+    match *val {
+        Value::Null => Variable::Option(None),
+        Value::Bool(val) => Variable::bool(val),
+        Value::Number(ref val) => Variable::f64(val.as_f64().unwrap_or(f64::NAN)),
+        Value::String(ref val) => Variable::Str(Arc::new(val.clone())),
+        Value::Array(ref arr) => Variable::Array(Arc::new(arr.iter().map(from_json_value).collect())),
+        Value::Object(ref obj) => {
+            let mut res = HashMap::with_capacity(obj.len());
+            for (key, val) in obj {
+                res.insert(Arc::new(key.clone()), from_json_value(val));
+            }
+            Variable::Object(Arc::new(res))
+        }
+    }
+}
+
+/// Converts a Dyon value to a JSON value.
+pub fn to_json_value(val: &Variable) -> Result<Value, String> {
+    fn number(x: f64) -> Value {
+        Number::from_f64(x).map(Value::Number).unwrap_or(Value::Null)
+    }
+
+    fn key(key: &Key) -> Result<String, String> {
+        match *key {
+            Key::Str(ref key) => Ok((**key).clone()),
+            _ => Err(format!(
+                "Expected `str` key to convert map to JSON, found `{}`",
+                key.to_variable().typeof_var()
+            )),
+        }
+    }
+
+    Ok(match *val {
+        Variable::Bool(val, _) => Value::Bool(val),
+        Variable::F64(val, _) => number(val),
+        Variable::Str(ref val) => Value::String((**val).clone()),
+        Variable::Vec4(v) => Value::Array(v.iter().map(|&x| number(x.into())).collect()),
+        Variable::Mat4(ref m) => Value::Array(
+            (0..4)
+                .map(|i| Value::Array((0..4).map(|j| number(m[j][i].into())).collect()))
+                .collect(),
+        ),
+        Variable::Array(ref arr) => {
+            Value::Array(arr.iter().map(to_json_value).collect::<Result<_, _>>()?)
+        }
+        Variable::Object(ref obj) => {
+            let mut res = Map::new();
+            for (key, val) in obj.iter() {
+                res.insert((**key).clone(), to_json_value(val)?);
+            }
+            Value::Object(res)
+        }
+        Variable::Map(ref map) => {
+            let mut res = Map::new();
+            for (k, val) in map.iter() {
+                res.insert(key(k)?, to_json_value(val)?);
+            }
+            Value::Object(res)
+        }
+        Variable::Set(ref set) => Value::Array(
+            set.iter()
+                .map(|k| to_json_value(&k.to_variable()))
+                .collect::<Result<_, _>>()?,
+        ),
+        Variable::Link(ref link) => {
+            let mut arr = vec![];
+            let mut link = (**link).clone();
+            while let Some(item) = link.head() {
+                arr.push(to_json_value(&item)?);
+                link = link.tail();
+            }
+            Value::Array(arr)
+        }
+        Variable::Option(None) => Value::Null,
+        Variable::Option(Some(ref val)) => to_json_value(val)?,
+        Variable::Result(ref res) => {
+            let mut obj = Map::new();
+            match *res {
+                Ok(ref val) => obj.insert("ok".into(), to_json_value(val)?),
+                Err(ref err) => obj.insert("err".into(), to_json_value(&err.message)?),
+            };
+            Value::Object(obj)
+        }
+        ref x => return Err(format!("Can not convert `{}` to JSON", x.typeof_var())),
+    })
+}
@@ -84,8 +84,19 @@ fn min_ref(v: &Variable, min: &mut Option<usize>) {
                 min_ref(v, min);
             }
         }
+        Map(ref map) => {
+            for v in map.values() {
+                min_ref(v, min);
+            }
+        }
+        Set(_) => {}
         Closure(_, _) => {}
         #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         In(_) => {}
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Out(_) => {}
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Shared(_) => {}
+        Iter(_) => {}
     }
 }
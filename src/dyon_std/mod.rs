@@ -2,12 +2,18 @@
 
 use crate::*;
 
-mod data;
+pub mod binary;
+pub mod csv;
+pub(crate) mod data;
 mod functions;
 #[cfg(feature = "file")]
 mod io;
+#[cfg(feature = "json")]
+pub(crate) mod json;
 mod lifetimechk;
 mod meta;
+pub mod schema;
+mod template;
 
 #[cfg(not(all(not(target_family = "wasm"), feature = "http")))]
 const HTTP_SUPPORT_DISABLED: &'static str = "Http support is disabled";
@@ -94,6 +100,17 @@ pub(crate) fn equal(a: &Variable, b: &Variable) -> Result<Variable, String> {
                     }
                 }),
         ),
+        (&Map(ref a), &Map(ref b)) => Variable::bool(
+            a.len() == b.len()
+                && a.iter().all(|a| {
+                    if let Some(b_val) = b.get(a.0) {
+                        matches!(equal(a.1, b_val), Ok(Variable::Bool(true, _)))
+                    } else {
+                        false
+                    }
+                }),
+        ),
+        (&Set(ref a), &Set(ref b)) => Variable::bool(a == b),
         (&Array(ref a), &Array(ref b)) => Variable::bool(
             a.len() == b.len()
                 && a.iter()
@@ -104,7 +121,11 @@ pub(crate) fn equal(a: &Variable, b: &Variable) -> Result<Variable, String> {
         (&Option(None), &Option(_)) => Variable::bool(false),
         (&Option(_), &Option(None)) => Variable::bool(false),
         (&Option(Some(ref a)), &Option(Some(ref b))) => equal(a, b)?,
-        _ => return Err("Expected `f64`, `str`, `bool`, `vec4`, `{}`, `[]` or `opt`".into()),
+        _ => {
+            return Err(
+                "Expected `f64`, `str`, `bool`, `vec4`, `{}`, `map`, `set`, `[]` or `opt`".into(),
+            )
+        }
     })
 }
 
@@ -274,6 +295,81 @@ pub(crate) fn pow(a: &Variable, b: &Variable) -> Result<Variable, String> {
     })
 }
 
+/// Converts a number to an integer, returning an error if it is not integral.
+fn integral(a: f64) -> Result<i64, String> {
+    // Only integers that can be represented exactly by `f64` are accepted.
+    if a.fract() == 0.0 && a.abs() <= 9_007_199_254_740_992.0 {
+        Ok(a as i64)
+    } else {
+        Err(format!("Expected integer, found `{}`", a))
+    }
+}
+
+/// Converts a number to a shift amount, returning an error if it is out of bounds.
+fn shift_amount(b: f64) -> Result<u32, String> {
+    let b = integral(b)?;
+    if (0..64).contains(&b) {
+        Ok(b as u32)
+    } else {
+        Err(format!("Expected shift amount in range `[0, 64)`, found `{}`", b))
+    }
+}
+
+pub(crate) fn bit_and(a: &Variable, b: &Variable) -> Result<Variable, String> {
+    use Variable::*;
+
+    Ok(match (a, b) {
+        (&F64(a, ref sec), &F64(b, _)) => F64((integral(a)? & integral(b)?) as f64, sec.clone()),
+        _ => return Err("Expected `f64`".into()),
+    })
+}
+
+pub(crate) fn bit_or(a: &Variable, b: &Variable) -> Result<Variable, String> {
+    use Variable::*;
+
+    Ok(match (a, b) {
+        (&F64(a, ref sec), &F64(b, _)) => F64((integral(a)? | integral(b)?) as f64, sec.clone()),
+        _ => return Err("Expected `f64`".into()),
+    })
+}
+
+pub(crate) fn bit_xor(a: &Variable, b: &Variable) -> Result<Variable, String> {
+    use Variable::*;
+
+    Ok(match (a, b) {
+        (&F64(a, ref sec), &F64(b, _)) => F64((integral(a)? ^ integral(b)?) as f64, sec.clone()),
+        (&Bool(a, ref sec), &Bool(b, _)) => Bool(a ^ b, sec.clone()),
+        _ => return Err("Expected `f64` or `bool`".into()),
+    })
+}
+
+pub(crate) fn shl(a: &Variable, b: &Variable) -> Result<Variable, String> {
+    use Variable::*;
+
+    Ok(match (a, b) {
+        (&F64(a, ref sec), &F64(b, _)) => {
+            let res = (integral(a)? as i128) << shift_amount(b)?;
+            // Keep the result in the range accepted by `integral`.
+            if res.unsigned_abs() > 9_007_199_254_740_992 {
+                return Err(format!("Shift overflows integer range, found `{}`", res));
+            }
+            F64(res as f64, sec.clone())
+        }
+        _ => return Err("Expected `f64`".into()),
+    })
+}
+
+pub(crate) fn shr(a: &Variable, b: &Variable) -> Result<Variable, String> {
+    use Variable::*;
+
+    Ok(match (a, b) {
+        (&F64(a, ref sec), &F64(b, _)) => {
+            F64((integral(a)? >> shift_amount(b)?) as f64, sec.clone())
+        }
+        _ => return Err("Expected `f64`".into()),
+    })
+}
+
 pub(crate) fn not(a: &Variable) -> Result<Variable, String> {
     Ok(match *a {
         Variable::Bool(ref b, ref sec) => Variable::Bool(!b, sec.clone()),
@@ -483,6 +579,20 @@ pub(crate) fn _where(rt: &mut Runtime) -> Result<Variable, String> {
     })))
 }
 
+pub(crate) fn contributions(rt: &mut Runtime) -> Result<Variable, String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    Ok(Variable::Array(Arc::new(match rt.resolve(&v) {
+        &Variable::F64(_, Some(ref sec)) => (**sec).clone(),
+        &Variable::F64(_, None) => {
+            return Err({
+                rt.arg_err_index.set(Some(0));
+"This does not make sense, perhaps it is not from a `sum` or `prod` loop?".into()
+            })
+        }
+        x => return Err(rt.expected_arg(0, x, "f64")),
+    })))
+}
+
 pub(crate) fn explain_why(rt: &mut Runtime) -> Result<Variable, String> {
     let why = rt.stack.pop().expect(TINVOTS);
     let val = rt.stack.pop().expect(TINVOTS);
@@ -584,7 +694,19 @@ dyon_fn! {fn sleep(v: f64) {
 
     let secs = v as u64;
     let nanos = (v.fract() * 1.0e9) as u32;
-    sleep(Duration::new(secs, nanos));
+    let dur = Duration::new(secs, nanos);
+    // On a worker of a multi-threaded Tokio runtime, other tasks move to other workers.
+    #[cfg(feature = "async")]
+    {
+        use tokio::runtime::{Handle, RuntimeFlavor};
+
+        if let Ok(handle) = Handle::try_current() {
+            if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+                return tokio::task::block_in_place(|| sleep(dur));
+            }
+        }
+    }
+    sleep(dur);
 }}
 
 pub(crate) fn head(rt: &mut Runtime) -> Result<Variable, String> {
@@ -634,8 +756,326 @@ pub(crate) fn random(rt: &mut Runtime) -> Result<Variable, String> {
     Ok(Variable::f64(rt.rng.gen()))
 }
 
+#[cfg(feature = "rand")]
+pub(crate) fn seed(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = match rt.resolve(&v) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(0, x, "number")),
+    };
+    rt.set_seed(v as i64 as u64);
+    Ok(())
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn rng(rt: &mut Runtime) -> Result<Variable, String> {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = match rt.resolve(&v) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(0, x, "number")),
+    };
+    let rng = StdRng::seed_from_u64(v as i64 as u64);
+    Ok(Variable::RustObject(Arc::new(Mutex::new(rng)) as RustObject))
+}
+
+/// Pops a random number generator created by `rng`.
+#[cfg(feature = "rand")]
+fn pop_rng(rt: &mut Runtime, arg: usize) -> Result<RustObject, String> {
+    use rand::rngs::StdRng;
+
+    let v = rt.stack.pop().expect(TINVOTS);
+    match rt.resolve(&v) {
+        &Variable::RustObject(ref obj) if obj.lock().unwrap().is::<StdRng>() => Ok(obj.clone()),
+        x => Err(rt.expected_arg(arg, x, "Rng")),
+    }
+}
+
+/// Calls a closure with a generator created by `rng`,
+/// or with the runtime's generator when there is none.
+#[cfg(feature = "rand")]
+fn with_rng<T, F>(default: &mut rand::rngs::StdRng, rng: Option<RustObject>, f: F) -> T
+where
+    F: FnOnce(&mut rand::rngs::StdRng) -> T,
+{
+    match rng {
+        None => f(default),
+        Some(obj) => {
+            let mut guard = obj.lock().unwrap();
+            f(guard.downcast_mut().expect("Expected `Rng`"))
+        }
+    }
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn random__rng(rt: &mut Runtime) -> Result<Variable, String> {
+    use rand::Rng;
+
+    let rng = pop_rng(rt, 0)?;
+    Ok(Variable::f64(with_rng(&mut rt.rng, Some(rng), |rng| rng.gen())))
+}
+
+/// Returns a random number between `min` and `max`.
+#[cfg(feature = "rand")]
+fn gen_range(rt: &mut Runtime, has_rng: bool) -> Result<Variable, String> {
+    use rand::Rng;
+
+    let off = has_rng as usize;
+    let max = rt.stack.pop().expect(TINVOTS);
+    let min = rt.stack.pop().expect(TINVOTS);
+    let max = match rt.resolve(&max) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(off + 1, x, "number")),
+    };
+    let min = match rt.resolve(&min) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(off, x, "number")),
+    };
+    let rng = if has_rng { Some(pop_rng(rt, 0)?) } else { None };
+    if !min.is_finite() || !max.is_finite() || min >= max {
+        return Err(format!(
+            "Expected finite `min` less than `max`, found {} and {}",
+            min, max
+        ));
+    }
+    Ok(Variable::f64(with_rng(&mut rt.rng, rng, |rng| rng.gen_range(min, max))))
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn range(rt: &mut Runtime) -> Result<Variable, String> {
+    gen_range(rt, false)
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn range__rng_min_max(rt: &mut Runtime) -> Result<Variable, String> {
+    gen_range(rt, true)
+}
+
+/// Returns a random number from a normal distribution.
+#[cfg(feature = "rand")]
+fn gen_normal(rt: &mut Runtime, has_rng: bool) -> Result<Variable, String> {
+    use rand::distributions::{Distribution, Normal};
+
+    let off = has_rng as usize;
+    let std = rt.stack.pop().expect(TINVOTS);
+    let mean = rt.stack.pop().expect(TINVOTS);
+    let std = match rt.resolve(&std) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(off + 1, x, "number")),
+    };
+    let mean = match rt.resolve(&mean) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(off, x, "number")),
+    };
+    let rng = if has_rng { Some(pop_rng(rt, 0)?) } else { None };
+    if std.is_nan() || std < 0.0 {
+        return Err(format!("Expected non-negative standard deviation, found {}", std));
+    }
+    let normal = Normal::new(mean, std);
+    Ok(Variable::f64(with_rng(&mut rt.rng, rng, |rng| normal.sample(rng))))
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn normal(rt: &mut Runtime) -> Result<Variable, String> {
+    gen_normal(rt, false)
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn normal__rng_mean_std(rt: &mut Runtime) -> Result<Variable, String> {
+    gen_normal(rt, true)
+}
+
+/// Shuffles an array in place.
+#[cfg(feature = "rand")]
+fn shuffle_array(rt: &mut Runtime, has_rng: bool) -> Result<(), String> {
+    use rand::seq::SliceRandom;
+
+    let off = has_rng as usize;
+    let v = rt.stack.pop().expect(TINVOTS);
+    let ind = match v {
+        Variable::Ref(ind) if matches!(rt.stack[ind], Variable::Array(_)) => ind,
+        _ => {
+            return Err({
+                rt.arg_err_index.set(Some(off));
+                "Expected reference to array".into()
+            })
+        }
+    };
+    let rng = if has_rng { Some(pop_rng(rt, 0)?) } else { None };
+    with_rng(&mut rt.rng, rng, |rng| {
+        if let Variable::Array(ref mut arr) = rt.stack[ind] {
+            Arc::make_mut(arr).shuffle(rng);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn shuffle(rt: &mut Runtime) -> Result<(), String> {
+    shuffle_array(rt, false)
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn shuffle__rng_array(rt: &mut Runtime) -> Result<(), String> {
+    shuffle_array(rt, true)
+}
+
+/// Picks a random item from an array.
+#[cfg(feature = "rand")]
+fn choose_item(rt: &mut Runtime, has_rng: bool) -> Result<Variable, String> {
+    use rand::seq::SliceRandom;
+
+    let off = has_rng as usize;
+    let v = rt.stack.pop().expect(TINVOTS);
+    let arr = match rt.resolve(&v) {
+        &Variable::Array(ref arr) => arr.clone(),
+        x => return Err(rt.expected_arg(off, x, "array")),
+    };
+    let rng = if has_rng { Some(pop_rng(rt, 0)?) } else { None };
+    let item = with_rng(&mut rt.rng, rng, |rng| arr.choose(rng).cloned());
+    Ok(Variable::Option(item.map(Box::new)))
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn choose(rt: &mut Runtime) -> Result<Variable, String> {
+    choose_item(rt, false)
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn choose__rng_array(rt: &mut Runtime) -> Result<Variable, String> {
+    choose_item(rt, true)
+}
+
+/// Picks a random item from an array, where each item has a weight.
+#[cfg(feature = "rand")]
+fn weighted_item(rt: &mut Runtime, has_rng: bool) -> Result<Variable, String> {
+    use rand::distributions::{Distribution, WeightedIndex};
+
+    let off = has_rng as usize;
+    let weights = rt.stack.pop().expect(TINVOTS);
+    let arr = rt.stack.pop().expect(TINVOTS);
+    let weights = match rt.resolve(&weights) {
+        &Variable::Array(ref weights) => weights.clone(),
+        x => return Err(rt.expected_arg(off + 1, x, "[f64]")),
+    };
+    let arr = match rt.resolve(&arr) {
+        &Variable::Array(ref arr) => arr.clone(),
+        x => return Err(rt.expected_arg(off, x, "array")),
+    };
+    let rng = if has_rng { Some(pop_rng(rt, 0)?) } else { None };
+    if arr.len() != weights.len() {
+        return Err(format!(
+            "Expected {} weights, found {}",
+            arr.len(),
+            weights.len()
+        ));
+    }
+    if arr.is_empty() {
+        return Ok(Variable::Option(None));
+    }
+    let mut ws = Vec::with_capacity(weights.len());
+    for w in weights.iter() {
+        match rt.resolve(w) {
+            &Variable::F64(val, _) => ws.push(val),
+            x => return Err(rt.expected_arg(off + 1, x, "[f64]")),
+        }
+    }
+    if !ws.iter().all(|w| w.is_finite()) || !ws.iter().sum::<f64>().is_finite() {
+        return Err("Expected finite weights with a finite sum".into());
+    }
+    let dist = WeightedIndex::new(&ws).map_err(|err| format!("Invalid weights: {}", err))?;
+    let i = with_rng(&mut rt.rng, rng, |rng| dist.sample(rng));
+    Ok(Variable::Option(Some(Box::new(arr[i].clone()))))
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn weighted_choice(rt: &mut Runtime) -> Result<Variable, String> {
+    weighted_item(rt, false)
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn weighted_choice__rng_array_weights(rt: &mut Runtime) -> Result<Variable, String> {
+    weighted_item(rt, true)
+}
+
 dyon_fn! {fn tau() -> f64 {6.283_185_307_179_586}}
 
+/// Pops two integer arguments from the stack.
+fn integral_args(rt: &mut Runtime) -> Result<(i64, i64), String> {
+    let b = rt.stack.pop().expect(TINVOTS);
+    let a = rt.stack.pop().expect(TINVOTS);
+    let a = match rt.resolve(&a) {
+        &Variable::F64(a, _) => integral(a).inspect_err(|_| rt.arg_err_index.set(Some(0)))?,
+        x => return Err(rt.expected_arg(0, x, "f64")),
+    };
+    let b = match rt.resolve(&b) {
+        &Variable::F64(b, _) => integral(b).inspect_err(|_| rt.arg_err_index.set(Some(1)))?,
+        x => return Err(rt.expected_arg(1, x, "f64")),
+    };
+    Ok((a, b))
+}
+
+/// Pops an integer argument from the stack.
+fn integral_arg(rt: &mut Runtime) -> Result<i64, String> {
+    let a = rt.stack.pop().expect(TINVOTS);
+    match rt.resolve(&a) {
+        &Variable::F64(a, _) => integral(a).inspect_err(|_| rt.arg_err_index.set(Some(0))),
+        x => Err(rt.expected_arg(0, x, "f64")),
+    }
+}
+
+pub(crate) fn idiv(rt: &mut Runtime) -> Result<Variable, String> {
+    let (a, b) = integral_args(rt)?;
+    if b == 0 {
+        rt.arg_err_index.set(Some(1));
+        return Err("Division by zero".into());
+    }
+    Ok(Variable::f64(a.div_euclid(b) as f64))
+}
+
+pub(crate) fn imod(rt: &mut Runtime) -> Result<Variable, String> {
+    let (a, b) = integral_args(rt)?;
+    if b == 0 {
+        rt.arg_err_index.set(Some(1));
+        return Err("Division by zero".into());
+    }
+    Ok(Variable::f64(a.rem_euclid(b) as f64))
+}
+
+pub(crate) fn gcd(rt: &mut Runtime) -> Result<Variable, String> {
+    let (a, b) = integral_args(rt)?;
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    Ok(Variable::f64(a as f64))
+}
+
+pub(crate) fn popcount(rt: &mut Runtime) -> Result<Variable, String> {
+    let a = integral_arg(rt)?;
+    Ok(Variable::f64(f64::from(a.count_ones())))
+}
+
+pub(crate) fn clz(rt: &mut Runtime) -> Result<Variable, String> {
+    let a = integral_arg(rt)?;
+    Ok(Variable::f64(f64::from(a.leading_zeros())))
+}
+
+pub(crate) fn rotate_left(rt: &mut Runtime) -> Result<Variable, String> {
+    let (a, n) = integral_args(rt)?;
+    // Rotates 32 bits, since `f64` can not represent all 64 bit integers.
+    if !(0..=i64::from(u32::MAX)).contains(&a) {
+        rt.arg_err_index.set(Some(0));
+        return Err(format!("Expected integer in range `[0, 2^32)`, found `{}`", a));
+    }
+    let n = n.rem_euclid(32) as u32;
+    Ok(Variable::f64(f64::from((a as u32).rotate_left(n))))
+}
+
 pub(crate) fn len(a: &Variable) -> Result<Variable, String> {
     match a {
         Variable::Array(ref arr) => Ok(Variable::f64(arr.len() as f64)),
@@ -737,13 +1177,20 @@ pub(crate) fn insert(rt: &mut Runtime) -> Result<(), String> {
     let item = rt.stack.pop().expect(TINVOTS);
     let item = rt.resolve(&item).deep_clone(&rt.stack);
     let index = rt.stack.pop().expect(TINVOTS);
-    let index = match rt.resolve(&index) {
-        &Variable::F64(index, _) => index,
-        x => return Err(rt.expected_arg(1, x, "number")),
-    };
     let v = rt.stack.pop().expect(TINVOTS);
 
     if let Variable::Ref(ind) = v {
+        if let Variable::Map(_) = rt.stack[ind] {
+            let key = key_arg(rt, 1, &index)?;
+            if let Variable::Map(ref mut map) = rt.stack[ind] {
+                Arc::make_mut(map).insert(key, item);
+            }
+            return Ok(());
+        }
+        let index = match rt.resolve(&index) {
+            &Variable::F64(index, _) => index,
+            x => return Err(rt.expected_arg(1, x, "number")),
+        };
         if let Variable::Array(ref arr) = rt.stack[ind] {
             let index = index as usize;
             if index > arr.len() {
@@ -759,18 +1206,35 @@ pub(crate) fn insert(rt: &mut Runtime) -> Result<(), String> {
         if !ok {
             return Err({
                 rt.arg_err_index.set(Some(0));
-                "Expected reference to array".into()
+                "Expected reference to array or map".into()
             });
         }
     } else {
         return Err({
             rt.arg_err_index.set(Some(0));
-            "Expected reference to array".into()
+            "Expected reference to array or map".into()
         });
     }
     Ok(())
 }
 
+pub(crate) fn insert__set(rt: &mut Runtime) -> Result<(), String> {
+    let item = rt.stack.pop().expect(TINVOTS);
+    let key = key_arg(rt, 1, &item)?;
+    let v = rt.stack.pop().expect(TINVOTS);
+
+    if let Variable::Ref(ind) = v {
+        if let Variable::Set(ref mut set) = rt.stack[ind] {
+            Arc::make_mut(set).insert(key);
+            return Ok(());
+        }
+    }
+    Err({
+        rt.arg_err_index.set(Some(0));
+        "Expected reference to set".into()
+    })
+}
+
 pub(crate) fn pop(rt: &mut Runtime) -> Result<Variable, String> {
     let arr = rt.stack.pop().expect(TINVOTS);
     let mut v: Option<Variable> = None;
@@ -807,12 +1271,25 @@ pub(crate) fn pop(rt: &mut Runtime) -> Result<Variable, String> {
 
 pub(crate) fn remove(rt: &mut Runtime) -> Result<Variable, String> {
     let index = rt.stack.pop().expect(TINVOTS);
-    let index = match rt.resolve(&index) {
-        &Variable::F64(index, _) => index,
-        x => return Err(rt.expected_arg(1, x, "number")),
-    };
     let arr = rt.stack.pop().expect(TINVOTS);
     if let Variable::Ref(ind) = arr {
+        match rt.stack[ind] {
+            Variable::Map(_) | Variable::Set(_) => {
+                let key = key_arg(rt, 1, &index)?;
+                return Ok(match rt.stack[ind] {
+                    Variable::Map(ref mut map) => {
+                        Variable::Option(Arc::make_mut(map).remove(&key).map(Box::new))
+                    }
+                    Variable::Set(ref mut set) => Variable::bool(Arc::make_mut(set).remove(&key)),
+                    _ => unreachable!(),
+                });
+            }
+            _ => {}
+        }
+        let index = match rt.resolve(&index) {
+            &Variable::F64(index, _) => index,
+            x => return Err(rt.expected_arg(1, x, "number")),
+        };
         if let Variable::Array(ref arr) = rt.stack[ind] {
             let index = index as usize;
             if index >= arr.len() {
@@ -825,12 +1302,12 @@ pub(crate) fn remove(rt: &mut Runtime) -> Result<Variable, String> {
         };
         Err({
             rt.arg_err_index.set(Some(0));
-            "Expected reference to array".into()
+            "Expected reference to array, map or set".into()
         })
     } else {
         Err({
             rt.arg_err_index.set(Some(0));
-            "Expected reference to array".into()
+            "Expected reference to array, map or set".into()
         })
     }
 }
@@ -958,15 +1435,224 @@ pub(crate) fn read_number(rt: &mut Runtime) -> Result<Variable, String> {
 }
 
 dyon_fn! {fn parse_number(text: Arc<String>) -> Option<f64> {text.trim().parse::<f64>().ok()}}
+
+pub(crate) fn parse_number__radix(rt: &mut Runtime) -> Result<Variable, String> {
+    let radix = rt.stack.pop().expect(TINVOTS);
+    let text = rt.stack.pop().expect(TINVOTS);
+    let radix = match rt.resolve(&radix) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(1, x, "number")),
+    };
+    let text = match rt.resolve(&text) {
+        &Variable::Str(ref text) => text.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    if radix.fract() != 0.0 || !(2.0..=36.0).contains(&radix) {
+        return Err({
+            rt.arg_err_index.set(Some(1));
+            format!("Expected radix from 2 to 36, found `{}`", radix)
+        });
+    }
+    let val = match i64::from_str_radix(text.trim(), radix as u32) {
+        Ok(val) => val,
+        Err(_) => return Ok(Variable::Option(None)),
+    };
+    // Larger integers can not be represented exactly by `f64`.
+    if val.unsigned_abs() > 1 << 53 {
+        rt.arg_err_index.set(Some(0));
+        return Err(format!("Expected integer at most 2^53 in size, found `{}`", text));
+    }
+    Ok(Variable::Option(Some(Box::new(Variable::f64(val as f64)))))
+}
 dyon_fn! {fn trim(v: Arc<String>) -> Arc<String> {Arc::new(v.trim().into())}}
 dyon_fn! {fn trim_left(v: Arc<String>) -> Arc<String> {Arc::new(v.trim_start().into())}}
 dyon_fn! {fn trim_right(v: Arc<String>) -> Arc<String> {Arc::new(v.trim_end().into())}}
-
-pub(crate) fn _str(rt: &mut Runtime) -> Result<Variable, String> {
-    use write::{write_variable, EscapeString};
-
-    let v = rt.stack.pop().expect(TINVOTS);
-    let mut buf: Vec<u8> = vec![];
+dyon_fn! {fn replace(text: Arc<String>, from: Arc<String>, to: Arc<String>) -> String {
+    if from.is_empty() {(*text).clone()} else {text.replace(&**from, &to)}
+}}
+dyon_fn! {fn find(text: Arc<String>, pat: Arc<String>) -> Option<usize> {
+    text.find(&**pat).map(|i| text[..i].chars().count())
+}}
+dyon_fn! {fn rfind(text: Arc<String>, pat: Arc<String>) -> Option<usize> {
+    text.rfind(&**pat).map(|i| text[..i].chars().count())
+}}
+pub(crate) fn repeat(rt: &mut Runtime) -> Result<Variable, String> {
+    let n = rt.stack.pop().expect(TINVOTS);
+    let text = rt.stack.pop().expect(TINVOTS);
+    let n = match rt.resolve(&n) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(1, x, "number")),
+    };
+    let text = match rt.resolve(&text) {
+        &Variable::Str(ref text) => text.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    let n = if n > 0.0 { n as usize } else { 0 };
+    let mut res = match text.len().checked_mul(n).map(string_with_capacity) {
+        Some(Ok(res)) => res,
+        _ => {
+            rt.arg_err_index.set(Some(1));
+            return Err(format!("String repeated `{}` times is too long", n));
+        }
+    };
+    for _ in 0..n {
+        res.push_str(&text);
+    }
+    Ok(Variable::Str(Arc::new(res)))
+}
+dyon_fn! {fn len_chars(text: Arc<String>) -> usize {text.chars().count()}}
+dyon_fn! {fn split_whitespace(text: Arc<String>) -> Vec<String> {
+    text.split_whitespace().map(String::from).collect()
+}}
+dyon_fn! {fn join(list: Vec<Arc<String>>, sep: Arc<String>) -> String {
+    let list: Vec<&str> = list.iter().map(|s| s.as_str()).collect();
+    list.join(&sep)
+}}
+
+/// The longest string in bytes created by `repeat` and the padding functions.
+const MAX_STRING_LEN: usize = 1 << 30;
+
+/// Creates an empty string with room for `len` bytes.
+///
+/// Returns an error if the string would be too long or could not be allocated.
+fn string_with_capacity(len: usize) -> Result<String, String> {
+    let mut res = String::new();
+    if len > MAX_STRING_LEN || res.try_reserve_exact(len).is_err() {
+        return Err(format!("String of {} bytes is too long", len));
+    }
+    Ok(res)
+}
+
+/// Pads a string with `fill` to make it `len` characters long.
+fn pad(rt: &mut Runtime, left: bool, has_fill: bool) -> Result<Variable, String> {
+    let fill: Arc<String> = if has_fill {
+        rt.pop()?
+    } else {
+        Arc::new(" ".into())
+    };
+    let len: f64 = rt.pop()?;
+    let text: Arc<String> = rt.pop()?;
+    let n = text.chars().count();
+    let len = if len > 0.0 { len as usize } else { 0 };
+    if len <= n || fill.is_empty() {
+        return Ok(Variable::Str(text));
+    }
+    // Count the bytes of whole repetitions of `fill` and the characters left over.
+    let k = len - n;
+    let fill_chars = fill.chars().count();
+    let rest: usize = fill.chars().take(k % fill_chars).map(|c| c.len_utf8()).sum();
+    let bytes = (k / fill_chars)
+        .checked_mul(fill.len())
+        .and_then(|b| b.checked_add(rest + text.len()));
+    let mut res = match bytes.map(string_with_capacity) {
+        Some(Ok(res)) => res,
+        _ => {
+            rt.arg_err_index.set(Some(1));
+            return Err(format!("String padded to `{}` characters is too long", len));
+        }
+    };
+    if !left {
+        res.push_str(&text);
+    }
+    res.extend(fill.chars().cycle().take(k));
+    if left {
+        res.push_str(&text);
+    }
+    Ok(Variable::Str(Arc::new(res)))
+}
+
+pub(crate) fn pad_left(rt: &mut Runtime) -> Result<Variable, String> {
+    pad(rt, true, false)
+}
+
+pub(crate) fn pad_left__text_len_fill(rt: &mut Runtime) -> Result<Variable, String> {
+    pad(rt, true, true)
+}
+
+pub(crate) fn pad_right(rt: &mut Runtime) -> Result<Variable, String> {
+    pad(rt, false, false)
+}
+
+pub(crate) fn pad_right__text_len_fill(rt: &mut Runtime) -> Result<Variable, String> {
+    pad(rt, false, true)
+}
+
+pub(crate) fn substring(rt: &mut Runtime) -> Result<Variable, String> {
+    let end = rt.stack.pop().expect(TINVOTS);
+    let start = rt.stack.pop().expect(TINVOTS);
+    let text = rt.stack.pop().expect(TINVOTS);
+    let end = match rt.resolve(&end) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(2, x, "number")),
+    };
+    let start = match rt.resolve(&start) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(1, x, "number")),
+    };
+    let text = match rt.resolve(&text) {
+        &Variable::Str(ref text) => text.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    for (i, val) in [(1, start), (2, end)] {
+        if val.fract() != 0.0 {
+            rt.arg_err_index.set(Some(i));
+            return Err(format!("Expected integer, found `{}`", val));
+        }
+    }
+    let n = text.chars().count();
+    if start < 0.0 || start > end || end > n as f64 {
+        return Err(format!(
+            "Expected `0 <= start <= end <= {}`, found start `{}` and end `{}`",
+            n, start, end
+        ));
+    }
+    let (start, end) = (start as usize, end as usize);
+    Ok(Variable::Str(Arc::new(
+        text.chars().skip(start).take(end - start).collect(),
+    )))
+}
+
+pub(crate) fn char_code(rt: &mut Runtime) -> Result<Variable, String> {
+    let text = rt.stack.pop().expect(TINVOTS);
+    let text = match rt.resolve(&text) {
+        &Variable::Str(ref text) => text.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(Variable::f64(ch as u32 as f64)),
+        _ => Err({
+            rt.arg_err_index.set(Some(0));
+            format!("Expected string with one character, found `{}`", text)
+        }),
+    }
+}
+
+pub(crate) fn from_char_code(rt: &mut Runtime) -> Result<Variable, String> {
+    let code = rt.stack.pop().expect(TINVOTS);
+    let code = match rt.resolve(&code) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(0, x, "number")),
+    };
+    let ch = if code >= 0.0 && code <= u32::MAX as f64 && code.fract() == 0.0 {
+        std::char::from_u32(code as u32)
+    } else {
+        None
+    };
+    match ch {
+        Some(ch) => Ok(Variable::Str(Arc::new(ch.to_string()))),
+        None => Err({
+            rt.arg_err_index.set(Some(0));
+            format!("Expected unicode code point, found `{}`", code)
+        }),
+    }
+}
+
+pub(crate) fn _str(rt: &mut Runtime) -> Result<Variable, String> {
+    use write::{write_variable, EscapeString};
+
+    let v = rt.stack.pop().expect(TINVOTS);
+    let mut buf: Vec<u8> = vec![];
     write_variable(&mut buf, rt, rt.resolve(&v), EscapeString::None, 0).unwrap();
     Ok(Variable::Str(Arc::new(String::from_utf8(buf).unwrap())))
 }
@@ -975,6 +1661,26 @@ dyon_fn! {fn fmt__tab_string(tab: f64, text: Arc<String>) -> String {
     write::fmt(tab as i32, &text)
 }}
 
+pub(crate) fn fmt__number_spec(rt: &mut Runtime) -> Result<Variable, String> {
+    use write::NumberSpec;
+
+    let spec = rt.stack.pop().expect(TINVOTS);
+    let x = rt.stack.pop().expect(TINVOTS);
+    let spec = match rt.resolve(&spec) {
+        &Variable::Str(ref spec) => spec.clone(),
+        x => return Err(rt.expected_arg(1, x, "str")),
+    };
+    let x = match rt.resolve(&x) {
+        &Variable::F64(val, _) => val,
+        x => return Err(rt.expected_arg(0, x, "number")),
+    };
+    let spec = NumberSpec::parse(&spec).map_err(|err| {
+        rt.arg_err_index.set(Some(1));
+        err
+    })?;
+    Ok(Variable::Str(Arc::new(spec.format(x)?)))
+}
+
 pub(crate) fn json_string(rt: &mut Runtime) -> Result<Variable, String> {
     use write::{write_variable, EscapeString};
 
@@ -1047,6 +1753,8 @@ pub(crate) fn _typeof(rt: &mut Runtime) -> Result<Variable, String> {
         Return => RETURN_TYPE.clone(),
         Bool(_, _) => BOOL_TYPE.clone(),
         Object(_) => OBJECT_TYPE.clone(),
+        Map(_) => MAP_TYPE.clone(),
+        Set(_) => SET_TYPE.clone(),
         Array(_) => ARRAY_TYPE.clone(),
         Link(_) => LINK_TYPE.clone(),
         Ref(_) => REF_TYPE.clone(),
@@ -1059,6 +1767,11 @@ pub(crate) fn _typeof(rt: &mut Runtime) -> Result<Variable, String> {
         Closure(_, _) => CLOSURE_TYPE.clone(),
         #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         In(_) => IN_TYPE.clone(),
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Out(_) => OUT_TYPE.clone(),
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Shared(_) => SHARED_TYPE.clone(),
+        Iter(_) => ITER_TYPE.clone(),
     }))
 }
 
@@ -1352,6 +2065,8 @@ pub(crate) fn _call(rt: &mut Runtime) -> Result<(), String> {
                 | FnIndex::Lazy(_, _)
                 | FnIndex::BinOp(_)
                 | FnIndex::UnOp(_) => return Err(format!("Could not find function `{}`", fn_name)),
+                #[cfg(feature = "async")]
+                FnIndex::Async(_) => return Err(format!("Could not find function `{}`", fn_name)),
             }
             // Use empty range instead of `call.source_range` (from when it was intrinsic).
             let call_range = Range::empty(0);
@@ -1425,6 +2140,8 @@ pub(crate) fn call_ret(rt: &mut Runtime) -> Result<Variable, String> {
                 | FnIndex::Lazy(_, _)
                 | FnIndex::BinOp(_)
                 | FnIndex::UnOp(_) => return Err(format!("Could not find function `{}`", fn_name)),
+                #[cfg(feature = "async")]
+                FnIndex::Async(_) => return Err(format!("Could not find function `{}`", fn_name)),
             }
             // Use empty range instead of `call.source_range` (from when it was intrinsic).
             let call_range = Range::empty(0);
@@ -1754,6 +2471,74 @@ pub(crate) fn load_string__file(_: &mut Runtime) -> Result<Variable, String> {
     Err(FILE_SUPPORT_DISABLED.into())
 }
 
+#[cfg(feature = "file")]
+dyon_fn! {fn read_dir(dir: Arc<String>) -> Result<Vec<String>, String> {io::read_dir(&dir)}}
+#[cfg(feature = "file")]
+dyon_fn! {fn exists(path: Arc<String>) -> bool {std::path::Path::new(&**path).exists()}}
+#[cfg(feature = "file")]
+dyon_fn! {fn is_dir(path: Arc<String>) -> bool {std::path::Path::new(&**path).is_dir()}}
+#[cfg(feature = "file")]
+dyon_fn! {fn is_file(path: Arc<String>) -> bool {std::path::Path::new(&**path).is_file()}}
+#[cfg(feature = "file")]
+dyon_fn! {fn create_dir_all(dir: Arc<String>) -> Result<Arc<String>, String> {
+    std::fs::create_dir_all(&**dir)
+        .map(|()| dir.clone())
+        .map_err(|err| io::io_error("create directory", &dir, &err))
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn remove_file(file: Arc<String>) -> Result<Arc<String>, String> {
+    std::fs::remove_file(&**file)
+        .map(|()| file.clone())
+        .map_err(|err| io::io_error("remove", &file, &err))
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn rename(from: Arc<String>, to: Arc<String>) -> Result<Arc<String>, String> {
+    std::fs::rename(&**from, &**to)
+        .map(|()| to.clone())
+        .map_err(|err| io::io_error(&format!("rename `{}` to", from), &to, &err))
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn copy(from: Arc<String>, to: Arc<String>) -> Result<Arc<String>, String> {
+    std::fs::copy(&**from, &**to)
+        .map(|_| to.clone())
+        .map_err(|err| io::io_error(&format!("copy `{}` to", from), &to, &err))
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn file_size(file: Arc<String>) -> Result<f64, String> {
+    std::fs::metadata(&**file)
+        .map(|meta| meta.len() as f64)
+        .map_err(|err| io::io_error("read size of", &file, &err))
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn modified_time(file: Arc<String>) -> Result<f64, String> {io::modified_time(&file)}}
+#[cfg(feature = "file")]
+dyon_fn! {fn glob(pattern: Arc<String>) -> Result<Vec<String>, String> {io::glob(&pattern)}}
+#[cfg(feature = "file")]
+dyon_fn! {fn join_path(a: Arc<String>, b: Arc<String>) -> String {
+    std::path::Path::new(&**a).join(&**b).to_string_lossy().into_owned()
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn parent(path: Arc<String>) -> Option<String> {
+    std::path::Path::new(&**path)
+        .parent()
+        .map(|p| p.to_string_lossy().into_owned())
+        .filter(|p| !p.is_empty())
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn file_stem(path: Arc<String>) -> Option<String> {
+    std::path::Path::new(&**path).file_stem().map(|s| s.to_string_lossy().into_owned())
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn extension(path: Arc<String>) -> Option<String> {
+    std::path::Path::new(&**path).extension().map(|s| s.to_string_lossy().into_owned())
+}}
+#[cfg(feature = "file")]
+dyon_fn! {fn canonicalize(path: Arc<String>) -> Result<String, String> {
+    std::fs::canonicalize(&**path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .map_err(|err| io::io_error("canonicalize", &path, &err))
+}}
+
 dyon_fn! {fn load_string__url(url: Arc<String>) -> Variable {
     Variable::Result(match meta::load_text_file_from_url(&**url) {
         Ok(s) => {
@@ -1795,6 +2580,53 @@ pub(crate) fn join__thread(rt: &mut Runtime) -> Result<Variable, String> {
     }))
 }
 
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn join__thread_timeout(rt: &mut Runtime) -> Result<Variable, String> {
+    use std::time::Duration;
+
+    let timeout = rt.stack.pop().expect(TINVOTS);
+    let timeout = match rt.resolve(&timeout) {
+        &Variable::F64(x, _) => x,
+        x => return Err(rt.expected_arg(1, x, "f64")),
+    };
+    if timeout.is_nan() || timeout < 0.0 {
+        rt.arg_err_index.set(Some(1));
+        return Err(format!("Expected non-negative timeout, found {}", timeout));
+    }
+    let thread = rt.stack.pop().expect(TINVOTS);
+    let done = match rt.resolve(&thread) {
+        &Variable::Thread(ref thr) => {
+            thr.wait_timeout(Duration::from_secs_f64(timeout.min(1.0e9)))?
+        }
+        x => return Err(rt.expected_arg(0, x, "thr")),
+    };
+    if !done {
+        return Ok(Variable::Option(None));
+    }
+    rt.stack.push(thread);
+    let res = join__thread(rt)?;
+    Ok(Variable::Option(Some(Box::new(res))))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn is_done(rt: &mut Runtime) -> Result<Variable, String> {
+    let thread = rt.stack.pop().expect(TINVOTS);
+    Ok(Variable::bool(match rt.resolve(&thread) {
+        &Variable::Thread(ref thr) => thr.is_done()?,
+        x => return Err(rt.expected_arg(0, x, "thr")),
+    }))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn cancel(rt: &mut Runtime) -> Result<(), String> {
+    let thread = rt.stack.pop().expect(TINVOTS);
+    match rt.resolve(&thread) {
+        &Variable::Thread(ref thr) => thr.cancel(),
+        x => return Err(rt.expected_arg(0, x, "thr")),
+    }
+    Ok(())
+}
+
 dyon_fn! {fn load_data__file(file: Arc<String>) -> Variable {
     use Error;
 
@@ -1825,6 +2657,215 @@ dyon_fn! {fn load_data__string(text: Arc<String>) -> Variable {
     Variable::Result(res)
 }}
 
+dyon_fn! {fn load_binary__file(file: Arc<String>) -> Variable {
+    use Error;
+
+    let res = match binary::load_file(&file) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                        "Error loading binary data from file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+pub(crate) fn save__binary_file(rt: &mut Runtime) -> Result<Variable, String> {
+    let file = rt.stack.pop().expect(TINVOTS);
+    let file = match rt.resolve(&file) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(1, x, "str")),
+    };
+    let data = rt.stack.pop().expect(TINVOTS);
+    let res = match binary::save_file(rt.resolve(&data), &file) {
+        Ok(()) => Ok(Box::new(Variable::Str(file))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                "Error when saving binary data to file `{}`:\n{}",
+                file, err
+            ))),
+            trace: vec![],
+        })),
+    };
+    Ok(Variable::Result(res))
+}
+
+/// Converts the result of a CSV function to a Dyon result.
+fn csv_res(res: Result<Variable, String>, msg: String) -> Variable {
+    Variable::Result(match res {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!("{}:\n{}", msg, err))),
+            trace: vec![],
+        })),
+    })
+}
+
+/// Pops CSV options from the stack.
+fn pop_csv_options(rt: &mut Runtime, ind: usize) -> Result<csv::Options, String> {
+    let options = rt.stack.pop().expect(TINVOTS);
+    csv::Options::from_variable(rt.resolve(&options)).map_err(|err| {
+        rt.arg_err_index.set(Some(ind));
+        err
+    })
+}
+
+dyon_fn! {fn load_csv__file(file: Arc<String>) -> Variable {
+    csv_res(csv::load_file(&file, &csv::Options::default()),
+            format!("Error loading CSV from file `{}`", file))
+}}
+
+pub(crate) fn load_csv__file_options(rt: &mut Runtime) -> Result<Variable, String> {
+    let options = pop_csv_options(rt, 1)?;
+    let file = rt.stack.pop().expect(TINVOTS);
+    let file = match rt.resolve(&file) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    Ok(csv_res(
+        csv::load_file(&file, &options),
+        format!("Error loading CSV from file `{}`", file),
+    ))
+}
+
+dyon_fn! {fn load_csv__string(text: Arc<String>) -> Variable {
+    csv_res(csv::parse(&text, &csv::Options::default()),
+            "Error loading CSV from string".into())
+}}
+
+pub(crate) fn load_csv__string_options(rt: &mut Runtime) -> Result<Variable, String> {
+    let options = pop_csv_options(rt, 1)?;
+    let text = rt.stack.pop().expect(TINVOTS);
+    let text = match rt.resolve(&text) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    Ok(csv_res(
+        csv::parse(&text, &options),
+        "Error loading CSV from string".into(),
+    ))
+}
+
+/// Opens a CSV file and reads its rows one by one when iterating.
+#[cfg(feature = "file")]
+fn stream_csv(file: &str, options: &csv::Options) -> Variable {
+    let res = csv::open_file(file, options)
+        .map(|rows| Variable::Iter(Arc::new(Mutex::new(runtime::Generator::from_iter(rows)))));
+    csv_res(res, format!("Error loading CSV from file `{}`", file))
+}
+
+#[cfg(feature = "file")]
+dyon_fn! {fn stream_csv__file(file: Arc<String>) -> Variable {
+    stream_csv(&file, &csv::Options::default())
+}}
+
+#[cfg(feature = "file")]
+pub(crate) fn stream_csv__file_options(rt: &mut Runtime) -> Result<Variable, String> {
+    let options = pop_csv_options(rt, 1)?;
+    let file = rt.stack.pop().expect(TINVOTS);
+    let file = match rt.resolve(&file) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    Ok(stream_csv(&file, &options))
+}
+
+fn save_csv(rt: &mut Runtime, options: csv::Options) -> Result<Variable, String> {
+    let file = rt.stack.pop().expect(TINVOTS);
+    let file = match rt.resolve(&file) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(1, x, "str")),
+    };
+    let rows = rt.stack.pop().expect(TINVOTS);
+    let res = csv::save_file(rt.resolve(&rows), &file, &options)
+        .map(|()| Variable::Str(file.clone()));
+    Ok(csv_res(res, format!("Error when saving CSV to file `{}`", file)))
+}
+
+pub(crate) fn save_csv__rows_file(rt: &mut Runtime) -> Result<Variable, String> {
+    save_csv(rt, csv::Options::default())
+}
+
+pub(crate) fn save_csv__rows_file_options(rt: &mut Runtime) -> Result<Variable, String> {
+    let options = pop_csv_options(rt, 2)?;
+    save_csv(rt, options)
+}
+
+dyon_fn! {fn validate__data_schema(text: Arc<String>, schema: Variable) -> Variable {
+    use Error;
+
+    let res = match schema::validate_data(&text, &schema) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                        "Error validating data:\n{}", err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+#[cfg(feature = "json")]
+dyon_fn! {fn load_json__file(file: Arc<String>) -> Variable {
+    use Error;
+
+    let res = match json::load_file(&file) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                        "Error loading JSON from file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+#[cfg(feature = "json")]
+dyon_fn! {fn load_json__string(text: Arc<String>) -> Variable {
+    use Error;
+
+    let res = match json::load_json(&text) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                        "Error loading JSON from string:\n{}", err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+/// Converts data to a JSON string, with indentation when `pretty` is `true`.
+#[cfg(feature = "json")]
+fn to_json_string(data: &Variable, pretty: bool) -> Variable {
+    use Error;
+
+    let res = json::to_json_value(data).map(|val| {
+        if pretty {
+            serde_json::to_string_pretty(&val).unwrap()
+        } else {
+            val.to_string()
+        }
+    });
+    Variable::Result(match res {
+        Ok(text) => Ok(Box::new(Variable::Str(Arc::new(text)))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(err)),
+            trace: vec![],
+        })),
+    })
+}
+
+#[cfg(feature = "json")]
+dyon_fn! {fn to_json(data: Variable) -> Variable {to_json_string(&data, false)}}
+#[cfg(feature = "json")]
+dyon_fn! {fn to_json__pretty(data: Variable, pretty: bool) -> Variable {
+    to_json_string(&data, pretty)
+}}
+
 pub(crate) fn args_os(_rt: &mut Runtime) -> Result<Variable, String> {
     let mut arr: Vec<Variable> = vec![];
     for arg in ::std::env::args_os() {
@@ -1837,6 +2878,168 @@ pub(crate) fn args_os(_rt: &mut Runtime) -> Result<Variable, String> {
     Ok(Variable::Array(Arc::new(arr)))
 }
 
+/// Returns an environment variable, looking first among those set by `set_env_var`.
+#[cfg(feature = "process")]
+pub(crate) fn env_var(rt: &mut Runtime) -> Result<Variable, String> {
+    let name: Arc<String> = rt.pop()?;
+    let value = rt.env_vars.lock().unwrap().get(&name).cloned();
+    Ok(Variable::Option(
+        value
+            .or_else(|| std::env::var(&**name).ok().map(Arc::new))
+            .map(|x| Box::new(Variable::Str(x))),
+    ))
+}
+
+/// Sets an environment variable of the runtime.
+///
+/// This does not change the environment of the process,
+/// which is not safe while other threads read it.
+#[cfg(feature = "process")]
+pub(crate) fn set_env_var(rt: &mut Runtime) -> Result<(), String> {
+    let value: Arc<String> = rt.pop()?;
+    let name: Arc<String> = rt.pop()?;
+    if name.is_empty() || name.contains(['=', '\0']) {
+        rt.arg_err_index.set(Some(0));
+        return Err(format!("Invalid environment variable name `{}`", name));
+    }
+    if value.contains('\0') {
+        rt.arg_err_index.set(Some(1));
+        return Err("Environment variable value can not contain NUL".into());
+    }
+    rt.env_vars.lock().unwrap().insert(name, value);
+    Ok(())
+}
+
+#[cfg(feature = "process")]
+dyon_fn! {fn current_dir() -> Result<String, String> {
+    std::env::current_dir()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .map_err(|err| format!("Could not get current directory:\n{}", err))
+}}
+
+/// Stops the script with an error, leaving it to the host to exit with the code.
+#[cfg(feature = "process")]
+pub(crate) fn exit(rt: &mut Runtime) -> Result<(), String> {
+    let code = rt.stack.pop().expect(TINVOTS);
+    let code = match rt.resolve(&code) {
+        &Variable::F64(val, _) => val as i32,
+        x => return Err(rt.expected_arg(0, x, "number")),
+    };
+    let code = *rt.exit_code.lock().unwrap().get_or_insert(code);
+    Err(format!("Exited with code `{}`", code))
+}
+
+/// Returns the exit code of a process, or `-1` when it was terminated by a signal.
+#[cfg(feature = "process")]
+fn exit_code(status: std::process::ExitStatus) -> f64 {
+    status.code().map(f64::from).unwrap_or(-1.0)
+}
+
+/// Creates a command with the environment variables set by `set_env_var`.
+#[cfg(feature = "process")]
+fn command(rt: &Runtime, command: &str, args: &[Arc<String>]) -> std::process::Command {
+    let mut res = std::process::Command::new(command);
+    res.args(args.iter().map(|a| &***a))
+        .envs(rt.env_vars.lock().unwrap().iter().map(|(k, v)| (&***k, &***v)));
+    res
+}
+
+#[cfg(feature = "process")]
+pub(crate) fn run_command(rt: &mut Runtime) -> Result<Variable, String> {
+    let args: Vec<Arc<String>> = rt.pop()?;
+    let command: Arc<String> = rt.pop()?;
+    let res = self::command(rt, &command, &args).output();
+    Ok(Variable::Result(match res {
+        Ok(output) => {
+            let mut obj = HashMap::new();
+            obj.insert(Arc::new("status".into()), Variable::f64(exit_code(output.status)));
+            obj.insert(Arc::new("stdout".into()), Variable::Str(Arc::new(
+                String::from_utf8_lossy(&output.stdout).into_owned())));
+            obj.insert(Arc::new("stderr".into()), Variable::Str(Arc::new(
+                String::from_utf8_lossy(&output.stderr).into_owned())));
+            Ok(Box::new(Variable::Object(Arc::new(obj))))
+        }
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                "Could not run command `{}`:\n{}", command, err))),
+            trace: vec![],
+        })),
+    }))
+}
+
+/// Sends the lines of a process output to a channel, as `{kind, text}` objects.
+#[cfg(all(not(target_family = "wasm"), feature = "threading", feature = "process"))]
+fn send_lines<R: std::io::Read + Send + 'static>(
+    read: R,
+    kind: &'static str,
+    tx: crate::Sender,
+) -> std::thread::JoinHandle<()> {
+    use std::io::{BufRead, BufReader};
+
+    std::thread::spawn(move || {
+        let mut read = BufReader::new(read);
+        let mut line = vec![];
+        while let Ok(n) = read.read_until(b'\n', &mut line) {
+            if n == 0 {
+                break;
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+            }
+            let mut obj = HashMap::new();
+            obj.insert(Arc::new("kind".into()), Variable::Str(Arc::new(kind.into())));
+            obj.insert(
+                Arc::new("text".into()),
+                Variable::Str(Arc::new(String::from_utf8_lossy(&line).into_owned())),
+            );
+            if tx.send(Variable::Object(Arc::new(obj))).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    })
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading", feature = "process"))]
+pub(crate) fn spawn_command(rt: &mut Runtime) -> Result<Variable, String> {
+    use std::process::Stdio;
+    use std::sync::mpsc::channel;
+
+    let args: Vec<Arc<String>> = rt.pop()?;
+    let command: Arc<String> = rt.pop()?;
+    let res = self::command(rt, &command, &args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    Ok(Variable::Result(match res {
+        Ok(mut child) => {
+            let (tx, rx) = channel();
+            let tx = crate::Sender::new(tx);
+            let stdout = send_lines(child.stdout.take().unwrap(), "stdout", tx.clone());
+            let stderr = send_lines(child.stderr.take().unwrap(), "stderr", tx.clone());
+            std::thread::spawn(move || {
+                let _ = stdout.join();
+                let _ = stderr.join();
+                let status = child.wait().map(exit_code).unwrap_or(-1.0);
+                let mut obj = HashMap::new();
+                obj.insert(Arc::new("kind".into()), Variable::Str(Arc::new("status".into())));
+                obj.insert(Arc::new("status".into()), Variable::f64(status));
+                let _ = tx.send(Variable::Object(Arc::new(obj)));
+            });
+            Ok(Box::new(Variable::In(Arc::new(Mutex::new(rx)))))
+        }
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                "Could not run command `{}`:\n{}", command, err))),
+            trace: vec![],
+        })),
+    }))
+}
+
 #[cfg(feature = "file")]
 pub(crate) fn save__data_file(rt: &mut Runtime) -> Result<Variable, String> {
     use std::fs::File;
@@ -1878,6 +3081,58 @@ pub(crate) fn save__data_file(_: &mut Runtime) -> Result<Variable, String> {
     Err(FILE_SUPPORT_DISABLED.into())
 }
 
+#[cfg(feature = "file")]
+pub(crate) fn save__data_file_options(rt: &mut Runtime) -> Result<Variable, String> {
+    use std::fs;
+
+    let options = rt.stack.pop().expect(TINVOTS);
+    let options = data::Options::from_variable(rt.resolve(&options)).map_err(|err| {
+        rt.arg_err_index.set(Some(2));
+        err
+    })?;
+    let file = rt.stack.pop().expect(TINVOTS);
+    let file = match rt.resolve(&file) {
+        &Variable::Str(ref t) => t.clone(),
+        x => return Err(rt.expected_arg(1, x, "str")),
+    };
+    let data = rt.stack.pop().expect(TINVOTS);
+
+    // Keep comments and key order from the file that is replaced.
+    let layout = fs::read_to_string(&**file)
+        .ok()
+        .and_then(|text| data::load_layout(&text).ok())
+        .map(|(_, layout)| layout);
+    let text = data::write_data(rt, rt.resolve(&data), &options, layout.as_ref());
+    let res = match fs::write(&**file, text) {
+        Ok(()) => Ok(Box::new(Variable::Str(file))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Str(Arc::new(format!(
+                "Error when writing to file `{}`:\n{}",
+                file, err
+            ))),
+            trace: vec![],
+        })),
+    };
+    Ok(Variable::Result(res))
+}
+
+#[cfg(not(feature = "file"))]
+pub(crate) fn save__data_file_options(_: &mut Runtime) -> Result<Variable, String> {
+    Err(FILE_SUPPORT_DISABLED.into())
+}
+
+pub(crate) fn fmt__data_options(rt: &mut Runtime) -> Result<Variable, String> {
+    let options = rt.stack.pop().expect(TINVOTS);
+    let options = data::Options::from_variable(rt.resolve(&options)).map_err(|err| {
+        rt.arg_err_index.set(Some(1));
+        err
+    })?;
+    let data = rt.stack.pop().expect(TINVOTS);
+    let mut text = data::write_data(rt, rt.resolve(&data), &options, None);
+    text.pop();
+    Ok(Variable::Str(Arc::new(text)))
+}
+
 pub(crate) fn json_from_meta_data(rt: &mut Runtime) -> Result<Variable, String> {
     let meta_data = rt.stack.pop().expect(TINVOTS);
     let json = match rt.resolve(&meta_data) {
@@ -1941,10 +3196,97 @@ pub(crate) fn keys(rt: &mut Runtime) -> Result<Variable, String> {
     let obj = rt.stack.pop().expect(TINVOTS);
     Ok(Variable::Array(Arc::new(match rt.resolve(&obj) {
         &Variable::Object(ref obj) => obj.keys().map(|k| Variable::Str(k.clone())).collect(),
-        x => return Err(rt.expected_arg(0, x, "object")),
+        &Variable::Map(ref map) => map.keys().map(|k| k.to_variable()).collect(),
+        x => return Err(rt.expected_arg(0, x, "object or map")),
     })))
 }
 
+pub(crate) fn values(rt: &mut Runtime) -> Result<Variable, String> {
+    let obj = rt.stack.pop().expect(TINVOTS);
+    let values: Vec<Variable> = match rt.resolve(&obj) {
+        &Variable::Object(ref obj) => obj.values().cloned().collect(),
+        &Variable::Map(ref map) => map.values().cloned().collect(),
+        x => return Err(rt.expected_arg(0, x, "object or map")),
+    };
+    Ok(Variable::Array(Arc::new(
+        values.iter().map(|v| v.deep_clone(&rt.stack)).collect(),
+    )))
+}
+
+/// Converts an argument into a key of a map or set.
+fn key_arg(rt: &Runtime, i: usize, v: &Variable) -> Result<Key, String> {
+    match Key::from_variable(v, &rt.stack) {
+        Some(key) => Ok(key),
+        None => Err(rt.expected_arg(i, rt.resolve(v), "hashable key")),
+    }
+}
+
+pub(crate) fn get(rt: &mut Runtime) -> Result<Variable, String> {
+    let key = rt.stack.pop().expect(TINVOTS);
+    let key = key_arg(rt, 1, &key)?;
+    let map = rt.stack.pop().expect(TINVOTS);
+    Ok(Variable::Option(match rt.resolve(&map) {
+        &Variable::Map(ref map) => map.get(&key).map(|v| Box::new(v.deep_clone(&rt.stack))),
+        x => return Err(rt.expected_arg(0, x, "map")),
+    }))
+}
+
+pub(crate) fn contains(rt: &mut Runtime) -> Result<Variable, String> {
+    let key = rt.stack.pop().expect(TINVOTS);
+    let v = rt.stack.pop().expect(TINVOTS);
+    if let &Variable::Str(ref text) = rt.resolve(&v) {
+        return match rt.resolve(&key) {
+            &Variable::Str(ref pat) => Ok(Variable::bool(text.contains(&***pat))),
+            x => Err(rt.expected_arg(1, x, "str")),
+        };
+    }
+    let key = key_arg(rt, 1, &key)?;
+    Ok(Variable::bool(match rt.resolve(&v) {
+        &Variable::Map(ref map) => map.contains_key(&key),
+        &Variable::Set(ref set) => set.contains(&key),
+        x => return Err(rt.expected_arg(0, x, "map, set or str")),
+    }))
+}
+
+pub(crate) fn union(rt: &mut Runtime) -> Result<Variable, String> {
+    let b = rt.stack.pop().expect(TINVOTS);
+    let a = rt.stack.pop().expect(TINVOTS);
+    Ok(match (rt.resolve(&a), rt.resolve(&b)) {
+        (&Variable::Set(ref a), &Variable::Set(ref b)) => {
+            let mut res = (**a).clone();
+            res.extend(b.iter().cloned());
+            Variable::Set(Arc::new(res))
+        }
+        (&Variable::Map(ref a), &Variable::Map(ref b)) => {
+            let mut res = (**a).clone();
+            res.extend(b.iter().map(|(k, v)| (k.clone(), v.clone())));
+            Variable::Map(Arc::new(res))
+        }
+        (&Variable::Set(_), x) => return Err(rt.expected_arg(1, x, "set")),
+        (&Variable::Map(_), x) => return Err(rt.expected_arg(1, x, "map")),
+        (x, _) => return Err(rt.expected_arg(0, x, "map or set")),
+    })
+}
+
+pub(crate) fn intersection(rt: &mut Runtime) -> Result<Variable, String> {
+    let b = rt.stack.pop().expect(TINVOTS);
+    let a = rt.stack.pop().expect(TINVOTS);
+    Ok(match (rt.resolve(&a), rt.resolve(&b)) {
+        (&Variable::Set(ref a), &Variable::Set(ref b)) => {
+            Variable::Set(Arc::new(a.intersection(b).cloned().collect()))
+        }
+        (&Variable::Map(ref a), &Variable::Map(ref b)) => Variable::Map(Arc::new(
+            a.iter()
+                .filter(|&(k, _)| b.contains_key(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        )),
+        (&Variable::Set(_), x) => return Err(rt.expected_arg(1, x, "set")),
+        (&Variable::Map(_), x) => return Err(rt.expected_arg(1, x, "map")),
+        (x, _) => return Err(rt.expected_arg(0, x, "map or set")),
+    })
+}
+
 pub(crate) fn chars(rt: &mut Runtime) -> Result<Variable, String> {
     let t = rt.stack.pop().expect(TINVOTS);
     let t = match rt.resolve(&t) {
@@ -2007,3 +3349,352 @@ pub(crate) fn next(rt: &mut Runtime) -> Result<Variable, String> {
         x => return Err(rt.expected_arg(0, x, "in")),
     })
 }
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn channel(_rt: &mut Runtime) -> Result<Variable, String> {
+    use std::sync::mpsc::channel;
+
+    let (tx, rx) = channel();
+    Ok(Variable::Array(Arc::new(vec![
+        Variable::Out(Arc::new(Mutex::new(crate::Sender::new(tx)))),
+        Variable::In(Arc::new(Mutex::new(rx))),
+    ])))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn send(rt: &mut Runtime) -> Result<Variable, String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = rt.resolve(&v).deep_clone(&rt.stack);
+    let tx = rt.stack.pop().expect(TINVOTS);
+    Ok(match rt.resolve(&tx) {
+        &Variable::Out(ref mutex) => match mutex.lock() {
+            Ok(x) => Variable::bool(x.send(v).is_ok()),
+            Err(err) => return Err(format!("Can not lock Out mutex:\n{}", err.to_string())),
+        },
+        x => return Err(rt.expected_arg(0, x, "out")),
+    })
+}
+
+/// Counts messages sent to in-types and closed senders, used to wake up `select`.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+static SELECT_NOTIFY: (Mutex<u64>, std::sync::Condvar) = (Mutex::new(0), std::sync::Condvar::new());
+
+/// Wakes up threads waiting in `select`.
+///
+/// This is called after sending a message to an in-type or closing a sender.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn notify_select() {
+    let (ref count, ref cvar) = SELECT_NOTIFY;
+    let mut count = count.lock().unwrap_or_else(|err| err.into_inner());
+    *count = count.wrapping_add(1);
+    drop(count);
+    cvar.notify_all();
+}
+
+/// Waits for a list of channels until one receives a message.
+///
+/// Returns `none()` when all channels are disconnected or when the timeout expires.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+fn select_in(rt: &mut Runtime, ins: &Variable, timeout: Option<f64>) -> Result<Variable, String> {
+    use std::sync::mpsc::TryRecvError;
+    use std::time::{Duration, Instant};
+
+    let ins = match rt.resolve(ins) {
+        &Variable::Array(ref arr) => arr.clone(),
+        x => return Err(rt.expected_arg(0, x, "[in]")),
+    };
+    let mut receivers = Vec::with_capacity(ins.len());
+    for x in ins.iter() {
+        match rt.resolve(x) {
+            &Variable::In(ref mutex) => receivers.push(mutex.clone()),
+            x => return Err(rt.expected_arg(0, x, "[in]")),
+        }
+    }
+    let deadline = timeout
+        .filter(|secs| secs.is_finite())
+        .map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    let (ref count, ref cvar) = SELECT_NOTIFY;
+    loop {
+        // Read the count before checking the channels,
+        // such that messages sent in the meantime are not missed.
+        let last = *count.lock().unwrap_or_else(|err| err.into_inner());
+        let mut disconnected = 0;
+        for (i, mutex) in receivers.iter().enumerate() {
+            match mutex.lock() {
+                Ok(x) => match x.try_recv() {
+                    Ok(x) => {
+                        return Ok(Variable::Option(Some(Box::new(Variable::Array(Arc::new(
+                            vec![Variable::f64(i as f64), x],
+                        ))))))
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => disconnected += 1,
+                },
+                Err(err) => return Err(format!("Can not lock In mutex:\n{}", err.to_string())),
+            }
+        }
+        if disconnected == receivers.len() {
+            return Ok(Variable::Option(None));
+        }
+        let mut guard = count.lock().unwrap_or_else(|err| err.into_inner());
+        while *guard == last {
+            guard = match deadline {
+                None => cvar.wait(guard).unwrap_or_else(|err| err.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(Variable::Option(None));
+                    }
+                    cvar.wait_timeout(guard, deadline - now)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn select(rt: &mut Runtime) -> Result<Variable, String> {
+    let ins = rt.stack.pop().expect(TINVOTS);
+    select_in(rt, &ins, None)
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn select__timeout(rt: &mut Runtime) -> Result<Variable, String> {
+    let timeout = rt.stack.pop().expect(TINVOTS);
+    let timeout = match rt.resolve(&timeout) {
+        &Variable::F64(x, _) => x,
+        x => return Err(rt.expected_arg(1, x, "f64")),
+    };
+    if timeout.is_nan() || timeout < 0.0 {
+        rt.arg_err_index.set(Some(1));
+        return Err(format!("Expected non-negative timeout, found {}", timeout));
+    }
+    let ins = rt.stack.pop().expect(TINVOTS);
+    select_in(rt, &ins, Some(timeout))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn shared(rt: &mut Runtime) -> Result<Variable, String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = rt.resolve(&v).deep_clone(&rt.stack);
+    Ok(Variable::Shared(Arc::new(Mutex::new(v))))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+thread_local! {
+    /// Stores the shared values that are locked by `lock_update` on this thread.
+    static LOCKED_SHARED: std::cell::RefCell<Vec<*const Mutex<Variable>>> =
+        const { std::cell::RefCell::new(vec![]) };
+}
+
+/// Locks a shared value.
+///
+/// Returns an error instead of a deadlock when the value is locked by `lock_update`
+/// on the same thread.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+fn lock_shared<'a>(
+    rt: &Runtime,
+    mutex: &'a Arc<Mutex<Variable>>,
+) -> Result<std::sync::MutexGuard<'a, Variable>, String> {
+    if LOCKED_SHARED.with(|locked| locked.borrow().contains(&Arc::as_ptr(mutex))) {
+        return Err(format!(
+            "{}\nCan not access shared value while it is locked by `lock_update`",
+            rt.stack_trace()
+        ));
+    }
+    mutex
+        .lock()
+        .map_err(|err| format!("Can not lock Shared mutex:\n{}", err))
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn read(rt: &mut Runtime) -> Result<Variable, String> {
+    let cell = rt.stack.pop().expect(TINVOTS);
+    Ok(match rt.resolve(&cell) {
+        &Variable::Shared(ref mutex) => lock_shared(rt, mutex)?.clone(),
+        x => return Err(rt.expected_arg(0, x, "shared")),
+    })
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn lock_update(rt: &mut Runtime) -> Result<(), String> {
+    let f = rt.stack.pop().expect(TINVOTS);
+    let cell = rt.stack.pop().expect(TINVOTS);
+    let mutex = match rt.resolve(&cell) {
+        &Variable::Shared(ref mutex) => mutex.clone(),
+        x => return Err(rt.expected_arg(0, x, "shared")),
+    };
+    // Keep the lock while calling the closure, such that updates are atomic.
+    let mut guard = lock_shared(rt, &mutex)?;
+    let name = Arc::new("lock_update".into());
+    LOCKED_SHARED.with(|locked| locked.borrow_mut().push(Arc::as_ptr(&mutex)));
+    let res = rt.call_closure_values(name, &f, vec![guard.clone()]);
+    LOCKED_SHARED.with(|locked| locked.borrow_mut().pop());
+    match res? {
+        Some(x) => *guard = x,
+        None => return Err(format!("{}\nExpected closure to return a value", rt.stack_trace())),
+    }
+    Ok(())
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+pub(crate) fn fetch_add(rt: &mut Runtime) -> Result<Variable, String> {
+    let b = rt.stack.pop().expect(TINVOTS);
+    let b = match rt.resolve(&b) {
+        &Variable::F64(x, _) => x,
+        x => return Err(rt.expected_arg(1, x, "f64")),
+    };
+    let cell = rt.stack.pop().expect(TINVOTS);
+    Ok(match rt.resolve(&cell) {
+        &Variable::Shared(ref mutex) => match *lock_shared(rt, mutex)? {
+            Variable::F64(ref mut a, _) => {
+                let prev = *a;
+                *a += b;
+                Variable::f64(prev)
+            }
+            ref x => return Err(rt.expected(x, "shared[f64]")),
+        },
+        x => return Err(rt.expected_arg(0, x, "shared")),
+    })
+}
+
+dyon_fn! {fn escape_html(text: Arc<String>) -> Arc<String> {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            c => res.push(c),
+        }
+    }
+    Arc::new(res)
+}}
+
+dyon_fn! {fn template_indent(text: Arc<String>, prefix: Arc<String>) -> Arc<String> {
+    Arc::new(template::indent(&text, &prefix))
+}}
+
+/// Compiles a template and returns the closure that renders it.
+fn load_template(
+    rt: &mut Runtime,
+    src: &str,
+    escape: Option<Variable>,
+    partials: &HashMap<Arc<String>, Arc<String>>,
+) -> Result<Variable, String> {
+    let error = |message: String| {
+        Variable::Result(Err(Box::new(Error {
+            message: Variable::Str(Arc::new(message)),
+            trace: vec![],
+        })))
+    };
+    let tpl = match template::compile(src, escape.is_some(), partials) {
+        Ok(x) => x,
+        Err(err) => return Ok(error(format!("When attempting to compile template:\n{}", err))),
+    };
+    let mut m = Module::empty();
+    m.import_ext_prelude(&rt.module);
+    m.add_str("__indent", template_indent, Dfn::nl(vec![Type::Str; 2], Type::Str));
+    let code = Arc::new(tpl.code.clone());
+    let report = |range, msg: &str| tpl.error(src, partials, range, msg);
+    if let Err(err) = crate::load_str_report("template", code, &mut m, &report) {
+        return Ok(error(format!("When attempting to load template:\n{}", err)));
+    }
+    let m = Arc::new(m);
+
+    // Use the source from calling function.
+    let source = rt.module.functions[rt.call_stack.last().unwrap().index]
+        .source
+        .clone();
+    let name: Arc<String> = Arc::new("main".into());
+    let call_range = Range::empty(0);
+    let call = ast::Call {
+        f_index: m.find_function(&name, 0),
+        args: escape
+            .into_iter()
+            .map(|x| ast::Expression::Variable(Box::new((call_range, x))))
+            .collect(),
+        custom_source: Some(source),
+        info: Box::new(ast::CallInfo {
+            source_range: call_range,
+            alias: None,
+            name,
+        }),
+    };
+    match rt.call(&call, &m)?.0 {
+        Some(f) => Ok(Variable::Result(Ok(Box::new(f)))),
+        None => Err("Expected template closure".into()),
+    }
+}
+
+/// Reads partials from an object of strings.
+fn template_partials(
+    rt: &Runtime,
+    ind: usize,
+    v: &Variable,
+) -> Result<HashMap<Arc<String>, Arc<String>>, String> {
+    let obj = match rt.resolve(v) {
+        &Variable::Object(ref obj) => obj.clone(),
+        x => return Err(rt.expected_arg(ind, x, "object")),
+    };
+    let mut partials = HashMap::new();
+    for (key, val) in obj.iter() {
+        match rt.resolve(val) {
+            &Variable::Str(ref text) => {
+                partials.insert(key.clone(), text.clone());
+            }
+            x => return Err(rt.expected_arg(ind, x, "{str}")),
+        }
+    }
+    Ok(partials)
+}
+
+/// Reads template source and escape closure from the stack.
+fn template_escape(rt: &mut Runtime) -> Result<(Arc<String>, Variable), String> {
+    let escape = rt.stack.pop().expect(TINVOTS);
+    let escape = match rt.resolve(&escape) {
+        x @ &Variable::Closure(_, _) => x.clone(),
+        x => return Err(rt.expected_arg(1, x, "closure")),
+    };
+    let src = rt.stack.pop().expect(TINVOTS);
+    match rt.resolve(&src) {
+        &Variable::Str(ref src) => Ok((src.clone(), escape)),
+        x => Err(rt.expected_arg(0, x, "str")),
+    }
+}
+
+pub(crate) fn template(rt: &mut Runtime) -> Result<Variable, String> {
+    let src = rt.stack.pop().expect(TINVOTS);
+    let src = match rt.resolve(&src) {
+        &Variable::Str(ref src) => src.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    load_template(rt, &src, None, &HashMap::new())
+}
+
+pub(crate) fn template__escape(rt: &mut Runtime) -> Result<Variable, String> {
+    let (src, escape) = template_escape(rt)?;
+    load_template(rt, &src, Some(escape), &HashMap::new())
+}
+
+pub(crate) fn template__partials(rt: &mut Runtime) -> Result<Variable, String> {
+    let partials = rt.stack.pop().expect(TINVOTS);
+    let partials = template_partials(rt, 1, &partials)?;
+    let src = rt.stack.pop().expect(TINVOTS);
+    let src = match rt.resolve(&src) {
+        &Variable::Str(ref src) => src.clone(),
+        x => return Err(rt.expected_arg(0, x, "str")),
+    };
+    load_template(rt, &src, None, &partials)
+}
+
+pub(crate) fn template__escape_partials(rt: &mut Runtime) -> Result<Variable, String> {
+    let partials = rt.stack.pop().expect(TINVOTS);
+    let partials = template_partials(rt, 2, &partials)?;
+    let (src, escape) = template_escape(rt)?;
+    load_template(rt, &src, Some(escape), &partials)
+}
@@ -0,0 +1,205 @@
+//! Validates data against a schema.
+//!
+//! A schema is itself a value, usually written in the Dyon data format:
+//!
+//! - `"any"`, `"bool"`, `"f64"`, `"str"`, `"vec4"`, `"link"`, `"map"`, `"set"`,
+//!   `"opt"`, `"[]"` and `"{}"` matches values of that type
+//! - `[s]` matches arrays where every item matches `s`
+//! - `{a: s, "b?": t}` matches objects with key `a` and optional key `b`,
+//!   where other keys are reported as unknown
+//! - `some(s)` matches `none()` or `some(x)` where `x` matches `s`
+//!
+//! For example, `{units: [{name: "str", speed: "f64", "color?": "vec4"}]}`.
+
+use std::collections::HashMap;
+
+use range::Range;
+
+use super::data::{error, load_data_ranges};
+use crate::Variable;
+
+/// Stores a value that does not match the schema.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// The path of the value, e.g. `units[3].speed`, which is empty for the root.
+    pub path: String,
+    /// Describes what was expected.
+    pub message: String,
+}
+
+/// Validates a value against a schema.
+///
+/// Returns every mismatch, or an error if the schema is invalid.
+pub fn validate(value: &Variable, schema: &Variable) -> Result<Vec<Mismatch>, String> {
+    let mut res = vec![];
+    let mut path = String::new();
+    check(value, schema, &mut path, &mut res)?;
+    Ok(res)
+}
+
+/// Loads data from text and validates it against a schema.
+///
+/// The error lists every mismatch with its path and source range.
+pub fn validate_data(data: &str, schema: &Variable) -> Result<Variable, String> {
+    let (value, ranges) = load_data_ranges(data)?;
+    let mismatches = validate(&value, schema)?;
+    if mismatches.is_empty() {
+        return Ok(value);
+    }
+    let mut res = String::new();
+    for m in &mismatches {
+        let msg = if m.path.is_empty() {
+            m.message.clone()
+        } else {
+            format!("`{}`: {}", m.path, m.message)
+        };
+        res.push_str(&error(range_of(&ranges, &m.path), &msg, data));
+    }
+    Err(res)
+}
+
+/// Returns the range of a value, or of the closest parent with a known range.
+fn range_of(ranges: &HashMap<String, Range>, path: &str) -> Range {
+    let mut path = path;
+    loop {
+        if let Some(&range) = ranges.get(path) {
+            return range;
+        }
+        match path.rfind(['.', '[']) {
+            Some(i) => path = &path[..i],
+            None if !path.is_empty() => path = "",
+            None => return Range::empty(0),
+        }
+    }
+}
+
+/// Returns the Dyon type name of a value.
+fn type_name(value: &Variable) -> String {
+    match *value {
+        Variable::Bool(_, _) => "bool".into(),
+        Variable::F64(_, _) => "f64".into(),
+        Variable::Str(_) => "str".into(),
+        Variable::Vec4(_) => "vec4".into(),
+        Variable::Link(_) => "link".into(),
+        Variable::Map(_) => "map".into(),
+        Variable::Set(_) => "set".into(),
+        Variable::Option(_) => "opt".into(),
+        Variable::Array(_) => "[]".into(),
+        Variable::Object(_) => "{}".into(),
+        ref x => (*x.typeof_var()).clone(),
+    }
+}
+
+/// Describes where in the schema an error occured.
+fn at(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" at `{}`", path)
+    }
+}
+
+fn check(
+    value: &Variable,
+    schema: &Variable,
+    path: &mut String,
+    res: &mut Vec<Mismatch>,
+) -> Result<(), String> {
+    let mut mismatch = |path: &str, message: String| {
+        res.push(Mismatch {
+            path: path.into(),
+            message,
+        })
+    };
+    match *schema {
+        Variable::Str(ref ty) => {
+            let found = type_name(value);
+            let known = matches!(
+                &***ty,
+                "any" | "bool" | "f64" | "str" | "vec4" | "link" | "map" | "set" | "opt" | "[]" | "{}"
+            );
+            if !known {
+                return Err(format!("Unknown type `{}` in schema{}", ty, at(path)));
+            }
+            if **ty != "any" && **ty != found {
+                mismatch(path, format!("Expected `{}`, found `{}`", ty, found));
+            }
+        }
+        Variable::Array(ref items) => {
+            if items.len() != 1 {
+                return Err(format!("Expected one item type in schema{}", at(path)));
+            }
+            match *value {
+                Variable::Array(ref arr) => {
+                    for (i, item) in arr.iter().enumerate() {
+                        let len = path.len();
+                        path.push_str(&format!("[{}]", i));
+                        check(item, &items[0], path, res)?;
+                        path.truncate(len);
+                    }
+                }
+                ref x => mismatch(path, format!("Expected `[]`, found `{}`", type_name(x))),
+            }
+        }
+        Variable::Object(ref fields) => match *value {
+            Variable::Object(ref obj) => {
+                let mut keys: Vec<_> = fields.keys().collect();
+                keys.sort();
+                for key in keys {
+                    let (name, optional) = match key.strip_suffix('?') {
+                        Some(name) => (name, true),
+                        None => (&***key, false),
+                    };
+                    let len = path.len();
+                    if len > 0 {
+                        path.push('.');
+                    }
+                    path.push_str(name);
+                    match obj.get(&name.to_string()) {
+                        Some(val) => check(val, &fields[key], path, res)?,
+                        None if optional => {}
+                        None => {
+                            path.truncate(len);
+                            res.push(Mismatch {
+                                path: path.clone(),
+                                message: format!("Missing key `{}`", name),
+                            });
+                        }
+                    }
+                    path.truncate(len);
+                }
+                let mut unknown: Vec<_> = obj
+                    .keys()
+                    .filter(|k| !fields.contains_key(*k) && !fields.contains_key(&format!("{}?", k)))
+                    .collect();
+                unknown.sort();
+                for key in unknown {
+                    let len = path.len();
+                    if len > 0 {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                    res.push(Mismatch {
+                        path: path.clone(),
+                        message: format!("Unknown key `{}`", key),
+                    });
+                    path.truncate(len);
+                }
+            }
+            ref x => mismatch(path, format!("Expected `{{}}`, found `{}`", type_name(x))),
+        },
+        Variable::Option(Some(ref inner)) => match *value {
+            Variable::Option(None) => {}
+            Variable::Option(Some(ref val)) => check(val, inner, path, res)?,
+            ref x => mismatch(path, format!("Expected `opt`, found `{}`", type_name(x))),
+        },
+        ref x => {
+            return Err(format!(
+                "Expected `str`, `[]`, `{{}}` or `some(_)` in schema{}, found `{}`",
+                at(path),
+                type_name(x)
+            ))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,662 @@
+//! Compiles text templates to Dyon source.
+//!
+//! A template is compiled into a module with a `main` function
+//! that returns a closure `\(any) -> link`.
+//! Each block in the template becomes a function that takes
+//! the escape closure, the data and the loop variables in scope.
+//!
+//! The compiled template remembers where each expression came from,
+//! such that errors in the generated source can be reported in the template.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use piston_meta::json::write_string;
+use piston_meta::ParseErrorHandler;
+use range::Range;
+
+use crate::write::NumberSpec;
+
+/// Stores a part of a template.
+enum Node {
+    /// Text that is copied.
+    Text(String),
+    /// Expression that is converted to a string,
+    /// or formatted as a number when there is a spec, e.g. `{{x:08.3}}`.
+    Expr {
+        expr: String,
+        offset: usize,
+        spec: Option<String>,
+        raw: bool,
+        indent: String,
+    },
+    /// Repeats body for each item in an array.
+    For {
+        name: String,
+        expr: String,
+        offset: usize,
+        body: Vec<Node>,
+    },
+    /// Picks a body depending on a condition.
+    If {
+        cond: String,
+        offset: usize,
+        then: Vec<Node>,
+        other: Vec<Node>,
+    },
+}
+
+/// Stores a tag, e.g. `{{#if x}}`.
+struct Tag {
+    /// The text between `{{` and `}}`, trimmed.
+    content: String,
+    /// The offset of the content in the expanded template.
+    offset: usize,
+    /// The whitespace at the start of the line.
+    indent: String,
+}
+
+impl Tag {
+    /// Returns the offset in the expanded template of a part of the content.
+    fn offset_of(&self, part: &str) -> usize {
+        self.offset + part.as_ptr() as usize - self.content.as_ptr() as usize
+    }
+}
+
+enum Token {
+    Text(String),
+    Tag(Tag),
+}
+
+/// Stores where a byte of the expanded template comes from.
+#[derive(Clone, Copy)]
+struct Origin {
+    /// The index of the partial name, or `None` for the template.
+    partial: Option<usize>,
+    /// The offset in the template or partial.
+    offset: usize,
+}
+
+/// Maps a range of the generated source to the expanded template.
+struct Span {
+    /// The offset in the generated source.
+    code: usize,
+    /// The offset in the expanded template.
+    offset: usize,
+    len: usize,
+}
+
+/// Stores a compiled template.
+pub(crate) struct Template {
+    /// The generated Dyon source.
+    pub code: String,
+    /// The origin of each byte in the expanded template.
+    origins: Vec<Origin>,
+    /// The names of partials that are used.
+    names: Vec<Arc<String>>,
+    /// The expressions in the generated source.
+    spans: Vec<Span>,
+}
+
+impl Template {
+    /// Reports an error in the generated source at the template or partial it came from.
+    ///
+    /// Returns `None` when the error is not in an expression from the template.
+    pub(crate) fn error(
+        &self,
+        src: &str,
+        partials: &HashMap<Arc<String>, Arc<String>>,
+        range: Range,
+        msg: &str,
+    ) -> Option<String> {
+        let span = self
+            .spans
+            .iter()
+            .find(|s| s.code <= range.offset && range.offset <= s.code + s.len)?;
+        let offset = span.offset + range.offset - span.code;
+        let len = range.length.min(span.code + span.len - range.offset);
+        let origin = match self.origins.get(offset) {
+            Some(x) => *x,
+            // The end of an expression at the end of the template.
+            None => {
+                let last = *self.origins.last()?;
+                Origin {
+                    offset: last.offset + 1,
+                    ..last
+                }
+            }
+        };
+        let mut buf: Vec<u8> = vec![];
+        let text = match origin.partial {
+            None => {
+                buf.extend_from_slice(b"In template:\n");
+                src
+            }
+            Some(i) => {
+                let name = &self.names[i];
+                buf.extend_from_slice(format!("In partial `{}`:\n", name).as_bytes());
+                &partials[name][..]
+            }
+        };
+        ParseErrorHandler::new(text)
+            .write_msg(&mut buf, Range::new(origin.offset, len), msg)
+            .unwrap();
+        Some(String::from_utf8(buf).unwrap())
+    }
+}
+
+/// Compiles a template to Dyon source.
+///
+/// When `escape` is `true`, the `main` function takes an escape closure as argument.
+pub(crate) fn compile(
+    src: &str,
+    escape: bool,
+    partials: &HashMap<Arc<String>, Arc<String>>,
+) -> Result<Template, String> {
+    let mut names = vec![];
+    let (src, origins) = expand(src, None, partials, &mut vec![], &mut names)?;
+    let tokens = tokenize(&src)?;
+    let mut pos = 0;
+    let (nodes, end) = parse(&tokens, &mut pos)?;
+    if let Some(end) = end {
+        return Err(format!("Unexpected `{{{{{}}}}}`", end));
+    }
+
+    let mut gen = Generator {
+        fns: vec![],
+        count: 0,
+    };
+    let block = gen.block(&[], &nodes)?;
+    let mut spans = vec![];
+    let mut code = String::new();
+    if escape {
+        code.push_str("fn main(escape: \\(str) -> str) -> \\(any) -> link {\n");
+    } else {
+        code.push_str("fn main() -> \\(any) -> link {\n");
+        code.push_str("    escape := \\(text: str) = clone(text)\n");
+    }
+    code.push_str(&format!(
+        "    return \\(data: any) = __block_{}(grab escape, data)\n}}\n",
+        block
+    ));
+    for &(ref f, ref fn_spans) in &gen.fns {
+        code.push('\n');
+        for span in fn_spans {
+            spans.push(Span {
+                code: code.len() + span.code,
+                ..*span
+            });
+        }
+        code.push_str(f);
+    }
+    Ok(Template {
+        code,
+        origins,
+        names,
+        spans,
+    })
+}
+
+/// Indents every line except the first.
+pub(crate) fn indent(text: &str, prefix: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            res.push('\n');
+            if !line.is_empty() {
+                res.push_str(prefix);
+            }
+        }
+        res.push_str(line);
+    }
+    res
+}
+
+/// Returns the whitespace at the start of a line.
+fn leading_whitespace(line: &str) -> &str {
+    let n = line.len() - line.trim_start_matches([' ', '\t']).len();
+    &line[..n]
+}
+
+/// Returns the start of the line that contains `pos`.
+fn line_start(src: &str, pos: usize) -> usize {
+    src[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0)
+}
+
+/// Returns the position after the end of line when the rest of the line is whitespace.
+fn standalone_end(src: &str, pos: usize) -> Option<usize> {
+    let rest = &src[pos..];
+    let end = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+    if rest[..end].trim().is_empty() {
+        Some(pos + end)
+    } else {
+        None
+    }
+}
+
+/// Returns the position after the `}}` that ends a tag.
+///
+/// Skips string literals, such that e.g. `{{"}}"}}` is one tag.
+fn tag_end(src: &str, start: usize) -> Result<usize, String> {
+    let mut string = false;
+    let mut escape = false;
+    let mut close = false;
+    for (i, ch) in src[start + 2..].char_indices() {
+        if string {
+            match ch {
+                _ if escape => escape = false,
+                '\\' => escape = true,
+                '"' => string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '}' if close => return Ok(start + 2 + i + 1),
+            '}' => {
+                close = true;
+                continue;
+            }
+            '"' => string = true,
+            _ => {}
+        }
+        close = false;
+    }
+    Err("Expected `}}`".into())
+}
+
+/// Inserts partials, e.g. `{{>header}}`.
+///
+/// A partial that stands alone on a line is indented like the tag.
+/// Returns the expanded source with the origin of each byte.
+fn expand(
+    src: &str,
+    partial: Option<usize>,
+    partials: &HashMap<Arc<String>, Arc<String>>,
+    stack: &mut Vec<String>,
+    names: &mut Vec<Arc<String>>,
+) -> Result<(String, Vec<Origin>), String> {
+    let mut res = String::with_capacity(src.len());
+    let mut origins = Vec::with_capacity(src.len());
+    let copy = |res: &mut String, origins: &mut Vec<Origin>, start: usize, end: usize| {
+        res.push_str(&src[start..end]);
+        origins.extend((start..end).map(|offset| Origin { partial, offset }));
+    };
+    let mut pos = 0;
+    while let Some(off) = src[pos..].find("{{") {
+        let start = pos + off;
+        let end = tag_end(src, start)?;
+        let content = src[start + 2..end - 2].trim();
+        if !content.starts_with('>') {
+            copy(&mut res, &mut origins, pos, end);
+            pos = end;
+            continue;
+        }
+
+        let name = content[1..].trim();
+        if stack.iter().any(|n| n == name) {
+            return Err(format!("Recursive partial `{}`", name));
+        }
+        let key = Arc::new(name.into());
+        let text = match partials.get(&key) {
+            None => return Err(format!("Could not find partial `{}`", name)),
+            Some(x) => x,
+        };
+        let index = match names.iter().position(|n| n == &key) {
+            Some(i) => i,
+            None => {
+                names.push(key);
+                names.len() - 1
+            }
+        };
+        stack.push(name.into());
+        let (text, text_origins) = expand(text, Some(index), partials, stack, names)?;
+        stack.pop();
+
+        let ls = line_start(src, start);
+        let prefix = &src[ls..start];
+        match standalone_end(src, end) {
+            Some(line_end) if prefix.trim().is_empty() => {
+                copy(&mut res, &mut origins, pos, ls);
+                let mut i = 0;
+                for line in text.split_inclusive('\n') {
+                    copy(&mut res, &mut origins, ls, start);
+                    res.push_str(line);
+                    origins.extend_from_slice(&text_origins[i..i + line.len()]);
+                    i += line.len();
+                }
+                if !text.ends_with('\n') && src[..line_end].ends_with('\n') {
+                    copy(&mut res, &mut origins, line_end - 1, line_end);
+                }
+                pos = line_end;
+            }
+            _ => {
+                copy(&mut res, &mut origins, pos, start);
+                res.push_str(&text);
+                origins.extend_from_slice(&text_origins);
+                pos = end;
+            }
+        }
+    }
+    copy(&mut res, &mut origins, pos, src.len());
+    Ok((res, origins))
+}
+
+/// Splits template into text and tags.
+///
+/// Block tags that stand alone on a line removes the whole line.
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut pos = 0;
+    while let Some(off) = src[pos..].find("{{") {
+        let start = pos + off;
+        let end = tag_end(src, start)?;
+        let inner = &src[start + 2..end - 2];
+        let content = inner.trim();
+        let ls = line_start(src, start);
+        let prefix = &src[ls..start];
+        let is_block = content.starts_with('#') || content.starts_with('/');
+
+        text.push_str(&src[pos..start]);
+        pos = end;
+        if is_block && prefix.trim().is_empty() {
+            if let Some(line_end) = standalone_end(src, end) {
+                // Remove the whole line.
+                text.truncate(text.len() - prefix.len());
+                pos = line_end;
+            }
+        }
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(Token::Tag(Tag {
+            content: content.into(),
+            offset: start + 2 + inner.len() - inner.trim_start().len(),
+            indent: leading_whitespace(&src[ls..start]).into(),
+        }));
+    }
+    text.push_str(&src[pos..]);
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+/// Parses tokens until the end of a block.
+///
+/// Returns the nodes and the tag that ended the block.
+fn parse(tokens: &[Token], pos: &mut usize) -> Result<(Vec<Node>, Option<String>), String> {
+    let mut nodes = vec![];
+    while *pos < tokens.len() {
+        let tag = match tokens[*pos] {
+            Token::Text(ref text) => {
+                nodes.push(Node::Text(text.clone()));
+                *pos += 1;
+                continue;
+            }
+            Token::Tag(ref tag) => tag,
+        };
+        *pos += 1;
+        let content = &tag.content;
+        if content.starts_with('/') || content == "#else" {
+            return Ok((nodes, Some(content.clone())));
+        } else if let Some(rest) = content.strip_prefix("#for ") {
+            let rest = rest.trim_start();
+            let (name, expr) = match rest.find(" in ") {
+                None => return Err(format!("Expected `in` in `{{{{{}}}}}`", content)),
+                Some(i) => (rest[..i].trim(), rest[i + 4..].trim()),
+            };
+            let (body, end) = parse(tokens, pos)?;
+            match end.as_deref() {
+                Some("/for") => {}
+                _ => return Err(format!("Expected `{{{{/for}}}}` for `{{{{{}}}}}`", content)),
+            }
+            nodes.push(Node::For {
+                name: name.into(),
+                expr: expr.into(),
+                offset: tag.offset_of(expr),
+                body,
+            });
+        } else if let Some(cond) = content.strip_prefix("#if ") {
+            let (then, end) = parse(tokens, pos)?;
+            let other = match end.as_deref() {
+                Some("#else") => {
+                    let (other, end) = parse(tokens, pos)?;
+                    if end.as_deref() != Some("/if") {
+                        return Err(format!("Expected `{{{{/if}}}}` for `{{{{{}}}}}`", content));
+                    }
+                    other
+                }
+                Some("/if") => vec![],
+                _ => return Err(format!("Expected `{{{{/if}}}}` for `{{{{{}}}}}`", content)),
+            };
+            let cond = cond.trim();
+            nodes.push(Node::If {
+                cond: cond.into(),
+                offset: tag.offset_of(cond),
+                then,
+                other,
+            });
+        } else if content.starts_with('#') {
+            return Err(format!("Unknown tag `{{{{{}}}}}`", content));
+        } else if let Some(expr) = content.strip_prefix('&') {
+            let offset = tag.offset_of(expr.trim_start());
+            let (expr, spec) = split_spec(expr)?;
+            nodes.push(Node::Expr {
+                expr,
+                offset,
+                spec,
+                raw: true,
+                indent: tag.indent.clone(),
+            });
+        } else if content.is_empty() {
+            return Err("Expected expression in `{{}}`".into());
+        } else {
+            let (expr, spec) = split_spec(content)?;
+            nodes.push(Node::Expr {
+                expr,
+                offset: tag.offset,
+                spec,
+                raw: false,
+                indent: tag.indent.clone(),
+            });
+        }
+    }
+    Ok((nodes, None))
+}
+
+/// Generates functions for blocks.
+struct Generator {
+    /// The functions with the expressions from the template.
+    fns: Vec<(String, Vec<Span>)>,
+    count: usize,
+}
+
+/// Pushes an expression from the template to the generated source.
+fn push_expr(items: &mut String, spans: &mut Vec<Span>, expr: &str, offset: usize) {
+    spans.push(Span {
+        code: items.len(),
+        offset,
+        len: expr.len(),
+    });
+    items.push_str(expr);
+}
+
+impl Generator {
+    /// Generates a function for a block and returns its number.
+    fn block(&mut self, vars: &[String], nodes: &[Node]) -> Result<usize, String> {
+        let id = self.count;
+        self.count += 1;
+        let mut items = String::new();
+        let mut spans = vec![];
+        for node in nodes {
+            items.push_str("        ");
+            match *node {
+                Node::Text(ref text) => {
+                    let mut buf: Vec<u8> = vec![];
+                    write_string(&mut buf, text).unwrap();
+                    items.push_str(&String::from_utf8(buf).unwrap());
+                }
+                Node::Expr {
+                    ref expr,
+                    offset,
+                    ref spec,
+                    raw,
+                    ref indent,
+                } => {
+                    let (mut before, mut after) = match *spec {
+                        None => ("str(".to_string(), ")".to_string()),
+                        Some(ref spec) => {
+                            let mut buf: Vec<u8> = vec![];
+                            write_string(&mut buf, spec).unwrap();
+                            (
+                                "fmt(number: ".to_string(),
+                                format!(", spec: {})", String::from_utf8(buf).unwrap()),
+                            )
+                        }
+                    };
+                    if !raw {
+                        before = format!("\\escape({}", before);
+                        after.push(')');
+                    }
+                    if !indent.is_empty() {
+                        before = format!("__indent({}", before);
+                        after.push_str(&format!(", {:?})", indent));
+                    }
+                    items.push_str(&before);
+                    push_expr(&mut items, &mut spans, expr, offset);
+                    items.push_str(&after);
+                }
+                Node::For {
+                    ref name,
+                    ref expr,
+                    offset,
+                    ref body,
+                } => {
+                    if !is_ident(name) {
+                        return Err(format!("Expected loop variable, found `{}`", name));
+                    }
+                    if name == "data" || name == "escape" || name.starts_with("__") {
+                        return Err(format!("The name `{}` is reserved", name));
+                    }
+                    if vars.iter().any(|v| v == name) {
+                        return Err(format!("Loop variable `{}` is already declared", name));
+                    }
+                    let mut inner = vars.to_vec();
+                    inner.push(name.clone());
+                    let body = self.block(&inner, body)?;
+                    let f = self.count;
+                    self.count += 1;
+                    self.fns.push((
+                        format!(
+                            "fn __for_{}({}, __list: [any]) -> link {{\n    \
+                                return link __i len(__list) {{ __block_{}({}, __list[__i]) }}\n}}\n",
+                            f,
+                            params(vars),
+                            body,
+                            args(vars)
+                        ),
+                        vec![],
+                    ));
+                    items.push_str(&format!("__for_{}({}, ", f, args(vars)));
+                    push_expr(&mut items, &mut spans, expr, offset);
+                    items.push(')');
+                }
+                Node::If {
+                    ref cond,
+                    offset,
+                    ref then,
+                    ref other,
+                } => {
+                    let then = self.block(vars, then)?;
+                    let other = self.block(vars, other)?;
+                    items.push_str("if ");
+                    push_expr(&mut items, &mut spans, cond, offset);
+                    items.push_str(&format!(
+                        " {{ __block_{}({}) }} else {{ __block_{}({}) }}",
+                        then,
+                        args(vars),
+                        other,
+                        args(vars)
+                    ));
+                }
+            }
+            items.push('\n');
+        }
+        let head = format!(
+            "fn __block_{}({}) -> link {{\n    return link {{\n",
+            id,
+            params(vars)
+        );
+        for span in &mut spans {
+            span.code += head.len();
+        }
+        self.fns.push((format!("{}{}    }}\n}}\n", head, items), spans));
+        Ok(id)
+    }
+}
+
+/// Splits a number format spec from an expression, e.g. `x:08.3`.
+///
+/// Uses the last `:` that is not inside brackets or strings.
+fn split_spec(content: &str) -> Result<(String, Option<String>), String> {
+    let mut depth = 0;
+    let mut string = false;
+    let mut escape = false;
+    let mut colon = None;
+    for (i, ch) in content.char_indices() {
+        if string {
+            match ch {
+                _ if escape => escape = false,
+                '\\' => escape = true,
+                '"' => string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ':' if depth == 0 => colon = Some(i),
+            _ => {}
+        }
+    }
+    match colon {
+        None => Ok((content.trim().into(), None)),
+        Some(i) => {
+            let spec = content[i + 1..].trim();
+            NumberSpec::parse(spec)?;
+            Ok((content[..i].trim().into(), Some(spec.into())))
+        }
+    }
+}
+
+/// Returns `true` if the text is a valid variable name.
+fn is_ident(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Generates parameters of a block function.
+fn params(vars: &[String]) -> String {
+    let mut res = String::from("escape: \\(str) -> str, data: any");
+    for v in vars {
+        res.push_str(&format!(", {}: any", v));
+    }
+    res
+}
+
+/// Generates arguments when calling a block function.
+fn args(vars: &[String]) -> String {
+    let mut res = String::from("escape, data");
+    for v in vars {
+        res.push_str(&format!(", {}", v));
+    }
+    res
+}
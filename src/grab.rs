@@ -61,6 +61,15 @@ pub fn grab_expr(
             ))),
             Flow::Continue,
         )),
+        E::Yield(ref expr) => Ok((
+            Grabbed::Expression(E::Yield(Box::new(
+                match grab_expr(level, rt, expr, side) {
+                    Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                    x => return x,
+                },
+            ))),
+            Flow::Continue,
+        )),
         E::Try(ref expr) => Ok((
             Grabbed::Expression(E::Try(Box::new(match grab_expr(level, rt, expr, side) {
                 Ok((Grabbed::Expression(x), Flow::Continue)) => x,
@@ -68,7 +77,21 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        E::ReturnVoid(_) | E::Break(_) | E::Continue(_) | E::Variable(_) => {
+        E::Break(ref br) => Ok((
+            Grabbed::Expression(E::Break(Box::new(ast::Break {
+                label: br.label.clone(),
+                val: match br.val {
+                    Some(ref val) => match grab_expr(level, rt, val, side) {
+                        Ok((Grabbed::Expression(x), Flow::Continue)) => Some(x),
+                        x => return x,
+                    },
+                    None => None,
+                },
+                source_range: br.source_range,
+            }))),
+            Flow::Continue,
+        )),
+        E::ReturnVoid(_) | E::Continue(_) | E::Variable(_) => {
             Ok((Grabbed::Expression(expr.clone()), Flow::Continue))
         }
         E::Closure(ref closure) => Ok((
@@ -449,6 +472,44 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
+        E::Map(ref map) => Ok((
+            Grabbed::Expression(E::Map(Box::new(ast::Map {
+                key_values: {
+                    let mut new_key_values = vec![];
+                    for key_value in &map.key_values {
+                        new_key_values.push((
+                            match grab_expr(level, rt, &key_value.0, side) {
+                                Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                                x => return x,
+                            },
+                            match grab_expr(level, rt, &key_value.1, side) {
+                                Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                                x => return x,
+                            },
+                        ));
+                    }
+                    new_key_values
+                },
+                source_range: map.source_range,
+            }))),
+            Flow::Continue,
+        )),
+        E::Set(ref set) => Ok((
+            Grabbed::Expression(E::Set(Box::new(ast::Set {
+                items: {
+                    let mut new_items = vec![];
+                    for item in &set.items {
+                        new_items.push(match grab_expr(level, rt, item, side) {
+                            Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                            x => return x,
+                        });
+                    }
+                    new_items
+                },
+                source_range: set.source_range,
+            }))),
+            Flow::Continue,
+        )),
         E::Array(ref arr) => Ok((
             Grabbed::Expression(E::Array(Box::new(ast::Array {
                 items: {
@@ -502,7 +563,22 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        E::WhileLet(ref while_let) => Ok((
+            Grabbed::Expression(E::WhileLet(Box::new(ast::WhileLet {
+                name: while_let.name.clone(),
+                expr: match grab_expr(level, rt, &while_let.expr, side) {
+                    Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                    x => return x,
+                },
+                block: match grab_block(level, rt, &while_let.block, side) {
+                    Ok((Grabbed::Block(x), Flow::Continue)) => x,
+                    x => return x,
+                },
+                label: while_let.label.clone(),
+                source_range: while_let.source_range,
+            }))),
+            Flow::Continue,
+        )),
         E::ForIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::ForIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -519,17 +595,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-        E::ForIn(ref for_in_expr) |
-        E::SumIn(ref for_in_expr) |
-        E::ProdIn(ref for_in_expr) |
-        E::MinIn(ref for_in_expr) |
-        E::MaxIn(ref for_in_expr) |
-        E::SiftIn(ref for_in_expr) |
-        E::AnyIn(ref for_in_expr) |
-        E::AllIn(ref for_in_expr) |
-        E::LinkIn(ref for_in_expr) => match **for_in_expr {},
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SumIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::SumIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -546,7 +611,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::ProdIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::ProdIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -563,7 +627,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MinIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::MinIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -580,7 +643,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MaxIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::MaxIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -597,7 +659,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AnyIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::AnyIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -614,7 +675,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AllIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::AllIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -631,7 +691,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SiftIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::SiftIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -648,7 +707,6 @@ pub fn grab_expr(
             }))),
             Flow::Continue,
         )),
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::LinkIn(ref for_in_expr) => Ok((
             Grabbed::Expression(E::LinkIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
@@ -776,6 +834,7 @@ fn grab_for_n(
                 x => return x,
             },
             label: for_n.label.clone(),
+            par: for_n.par,
             source_range: for_n.source_range,
         }),
         Flow::Continue,
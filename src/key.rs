@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use crate::Variable;
+
+/// Stores a hashable key of a map or set.
+///
+/// Numbers are stored as bits, such that `-0` equals `0` and all NaN equal each other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// Boolean.
+    Bool(bool),
+    /// F64.
+    F64(u64),
+    /// Text.
+    Str(Arc<String>),
+    /// 4D vector.
+    Vec4([u32; 4]),
+    /// Array of keys.
+    Array(Arc<Vec<Key>>),
+}
+
+impl Key {
+    /// Converts a variable into a key.
+    ///
+    /// Returns `None` if the variable is not hashable.
+    pub fn from_variable(v: &Variable, stack: &[Variable]) -> Option<Key> {
+        match *v {
+            Variable::Ref(ind) => Key::from_variable(&stack[ind], stack),
+            Variable::Bool(b, _) => Some(Key::Bool(b)),
+            Variable::F64(x, _) => Some(Key::F64(f64_bits(x))),
+            Variable::Str(ref s) => Some(Key::Str(s.clone())),
+            Variable::Vec4(v) => Some(Key::Vec4([
+                f32_bits(v[0]),
+                f32_bits(v[1]),
+                f32_bits(v[2]),
+                f32_bits(v[3]),
+            ])),
+            Variable::Array(ref arr) => {
+                let mut res = Vec::with_capacity(arr.len());
+                for it in arr.iter() {
+                    res.push(Key::from_variable(it, stack)?);
+                }
+                Some(Key::Array(Arc::new(res)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts key into a variable.
+    pub fn to_variable(&self) -> Variable {
+        match *self {
+            Key::Bool(b) => Variable::bool(b),
+            Key::F64(x) => Variable::f64(f64::from_bits(x)),
+            Key::Str(ref s) => Variable::Str(s.clone()),
+            Key::Vec4(v) => Variable::Vec4([
+                f32::from_bits(v[0]),
+                f32::from_bits(v[1]),
+                f32::from_bits(v[2]),
+                f32::from_bits(v[3]),
+            ]),
+            Key::Array(ref arr) => {
+                Variable::Array(Arc::new(arr.iter().map(|k| k.to_variable()).collect()))
+            }
+        }
+    }
+}
+
+fn f64_bits(x: f64) -> u64 {
+    if x.is_nan() {
+        f64::NAN.to_bits()
+    } else if x == 0.0 {
+        0.0f64.to_bits()
+    } else {
+        x.to_bits()
+    }
+}
+
+fn f32_bits(x: f32) -> u32 {
+    if x.is_nan() {
+        f32::NAN.to_bits()
+    } else if x == 0.0 {
+        0.0f32.to_bits()
+    } else {
+        x.to_bits()
+    }
+}
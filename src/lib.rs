@@ -25,14 +25,17 @@ extern crate vecmath;
 use piston_meta::{parse_errstr, syntax_errstr, MetaData, Syntax};
 use range::Range;
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::{Arc, Mutex};
 #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+use std::sync::atomic::AtomicBool;
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 use threading::JoinHandle;
 
 pub mod ast;
 pub mod embed;
+mod key;
 mod lifetime;
 mod link;
 pub mod macros;
@@ -40,6 +43,8 @@ mod mat4;
 mod module;
 mod prelude;
 pub mod runtime;
+#[cfg(feature = "serde")]
+mod serde_variable;
 #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 pub mod threading;
 mod ty;
@@ -50,6 +55,7 @@ mod dyon_std;
 mod grab;
 
 pub use ast::Lazy;
+pub use key::Key;
 pub use link::Link;
 pub use mat4::Mat4;
 pub use module::Module;
@@ -57,6 +63,11 @@ pub use prelude::{Dfn, Lt, Prelude};
 pub use runtime::Runtime;
 pub use ty::Type;
 pub use vec4::Vec4;
+pub use dyon_std::{binary, csv, schema};
+#[cfg(feature = "json")]
+pub use dyon_std::json::{from_json_value, to_json_value};
+#[cfg(feature = "serde")]
+pub use serde_variable::{from_variable, to_variable};
 
 /// A common error message when there is no value on the stack.
 pub const TINVOTS: &str = "There is no value on the stack";
@@ -80,6 +91,11 @@ lazy_static! {
     pub(crate) static ref POW: Arc<String> = Arc::new("pow".into());
     pub(crate) static ref DOT: Arc<String> = Arc::new("dot".into());
     pub(crate) static ref CROSS: Arc<String> = Arc::new("cross".into());
+    pub(crate) static ref BIT_AND: Arc<String> = Arc::new("bit_and".into());
+    pub(crate) static ref BIT_OR: Arc<String> = Arc::new("bit_or".into());
+    pub(crate) static ref BIT_XOR: Arc<String> = Arc::new("bit_xor".into());
+    pub(crate) static ref SHL: Arc<String> = Arc::new("shl".into());
+    pub(crate) static ref SHR: Arc<String> = Arc::new("shr".into());
     pub(crate) static ref NOT: Arc<String> = Arc::new("not".into());
     pub(crate) static ref NEG: Arc<String> = Arc::new("neg".into());
     pub(crate) static ref NORM: Arc<String> = Arc::new("norm".into());
@@ -102,6 +118,10 @@ pub static LAZY_NO: LazyInvariant = &[];
 pub type Array = Arc<Vec<Variable>>;
 /// Type alias for Dyon objects.
 pub type Object = Arc<HashMap<Arc<String>, Variable>>;
+/// Type alias for Dyon maps.
+pub type Map = Arc<HashMap<Key, Variable>>;
+/// Type alias for Dyon sets.
+pub type Set = Arc<HashSet<Key>>;
 /// Type alias for Rust objects.
 pub type RustObject = Arc<Mutex<dyn Any>>;
 
@@ -121,17 +141,112 @@ pub struct Error {
 pub struct Thread {
     /// The handle of the thread.
     pub handle: Option<Arc<Mutex<JoinHandle<Result<Variable, String>>>>>,
+    /// The state shared with the thread.
+    pub state: Arc<ThreadState>,
+}
+
+/// Stores the state shared between a thread and its handles.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+#[derive(Debug, Default)]
+pub struct ThreadState {
+    /// Set to cancel the thread.
+    pub cancel: AtomicBool,
+    /// Set when the thread has finished, if the thread tells it.
+    finished: Option<(Mutex<bool>, std::sync::Condvar)>,
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+impl ThreadState {
+    /// Creates a state for a thread that calls `set_finished` when it has finished.
+    pub fn with_finished() -> ThreadState {
+        ThreadState {
+            cancel: AtomicBool::new(false),
+            finished: Some((Mutex::new(false), std::sync::Condvar::new())),
+        }
+    }
+
+    /// Marks the thread as finished and wakes up threads waiting to join it.
+    pub fn set_finished(&self) {
+        if let Some((ref done, ref cvar)) = self.finished {
+            *done.lock().unwrap_or_else(|err| err.into_inner()) = true;
+            cvar.notify_all();
+        }
+    }
 }
 
 #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 impl Thread {
     /// Creates a new thread handle.
+    ///
+    /// The thread can not be cancelled through this handle.
+    /// Since the thread does not tell when it has finished,
+    /// joining with a timeout checks the thread every millisecond.
     pub fn new(handle: JoinHandle<Result<Variable, String>>) -> Thread {
+        Thread::with_state(handle, Arc::new(ThreadState::default()))
+    }
+
+    /// Creates a new thread handle with state shared with the thread.
+    ///
+    /// The thread stops with an error when `cancel` is set.
+    pub fn with_state(
+        handle: JoinHandle<Result<Variable, String>>,
+        state: Arc<ThreadState>,
+    ) -> Thread {
         Thread {
             handle: Some(Arc::new(Mutex::new(handle))),
+            state,
+        }
+    }
+
+    /// Waits until the thread has finished or the timeout runs out.
+    ///
+    /// Returns `true` if the thread has finished or the handle has been invalidated.
+    pub fn wait_timeout(&self, timeout: std::time::Duration) -> Result<bool, String> {
+        use std::time::{Duration, Instant};
+
+        match self.state.finished {
+            Some((ref done, ref cvar)) if self.handle.is_some() => {
+                let done = done.lock().unwrap_or_else(|err| err.into_inner());
+                let (done, _) = cvar
+                    .wait_timeout_while(done, timeout, |done| !*done)
+                    .unwrap_or_else(|err| err.into_inner());
+                Ok(*done)
+            }
+            _ => {
+                let deadline = Instant::now() + timeout;
+                while !self.is_done()? {
+                    if Instant::now() >= deadline {
+                        return Ok(false);
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns `true` if the thread has finished or the handle has been invalidated.
+    pub fn is_done(&self) -> Result<bool, String> {
+        match self.handle {
+            None => Ok(true),
+            Some(ref handle) => match handle.lock() {
+                Ok(x) => Ok(x.is_finished()),
+                Err(err) => Err(format!("Can not lock Thread mutex:\n{}", err)),
+            },
         }
     }
 
+    /// Requests the thread to stop.
+    ///
+    /// The thread checks the flag at loop back-edges and calls.
+    /// A thread that is blocked, e.g. in `sleep`, `wait_next` or `select`,
+    /// stops after it is unblocked.
+    pub fn cancel(&self) {
+        use std::sync::atomic::Ordering;
+
+        self.state.cancel.store(true, Ordering::Relaxed);
+    }
+
     /// Removes the thread handle from the stack.
     /// This is to prevent an extra reference when resolving the variable.
     pub fn invalidate_handle(
@@ -144,7 +259,10 @@ impl Thread {
 
                 match replace(
                     &mut rt.stack[ind],
-                    Variable::Thread(Thread { handle: None }),
+                    Variable::Thread(Thread {
+                        handle: None,
+                        state: Arc::new(ThreadState::default()),
+                    }),
                 ) {
                     Variable::Thread(th) => th,
                     x => return Err(rt.expected(&x, "Thread")),
@@ -181,6 +299,37 @@ impl fmt::Debug for Thread {
     }
 }
 
+/// Sends messages to an in-type.
+///
+/// Wakes up threads waiting in `select` when sending a message and when dropped.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+#[derive(Clone, Debug)]
+pub struct Sender(Option<::std::sync::mpsc::Sender<Variable>>);
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+impl Sender {
+    /// Creates a new sender.
+    pub fn new(tx: ::std::sync::mpsc::Sender<Variable>) -> Sender {
+        Sender(Some(tx))
+    }
+
+    /// Sends a message, returning it back if the receiver is disconnected.
+    pub fn send(&self, v: Variable) -> Result<(), ::std::sync::mpsc::SendError<Variable>> {
+        let res = self.0.as_ref().expect("Expected sender").send(v);
+        dyon_std::notify_select();
+        res
+    }
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+impl Drop for Sender {
+    fn drop(&mut self) {
+        // Close the channel before waking up `select`.
+        self.0 = None;
+        dyon_std::notify_select();
+    }
+}
+
 /// Prevents unsafe references from being accessed outside library.
 #[derive(Debug, Clone)]
 pub struct UnsafeRef(*mut Variable);
@@ -221,6 +370,10 @@ pub enum Variable {
     Array(Array),
     /// Object.
     Object(Object),
+    /// Map.
+    Map(Map),
+    /// Set.
+    Set(Set),
     /// Link.
     Link(Box<Link>),
     /// Unsafe reference.
@@ -241,6 +394,14 @@ pub enum Variable {
     /// In-type.
     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     In(Arc<Mutex<::std::sync::mpsc::Receiver<Variable>>>),
+    /// Out-type.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    Out(Arc<Mutex<Sender>>),
+    /// Shared mutable value.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    Shared(Arc<Mutex<Variable>>),
+    /// Generator returned by a function with `iter` return type.
+    Iter(Arc<Mutex<runtime::Generator>>),
 }
 
 /// This is requires because `UnsafeRef(*mut Variable)` can not be sent across threads.
@@ -272,6 +433,8 @@ impl Variable {
             Return => RETURN_TYPE.clone(),
             Bool(_, _) => BOOL_TYPE.clone(),
             Object(_) => OBJECT_TYPE.clone(),
+            Map(_) => MAP_TYPE.clone(),
+            Set(_) => SET_TYPE.clone(),
             Array(_) => ARRAY_TYPE.clone(),
             Link(_) => LINK_TYPE.clone(),
             Ref(_) => REF_TYPE.clone(),
@@ -284,6 +447,11 @@ impl Variable {
             Closure(_, _) => CLOSURE_TYPE.clone(),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             In(_) => IN_TYPE.clone(),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Out(_) => OUT_TYPE.clone(),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Shared(_) => SHARED_TYPE.clone(),
+            Iter(_) => ITER_TYPE.clone(),
         }
     }
 
@@ -304,6 +472,14 @@ impl Variable {
                 }
                 Object(res)
             }
+            Map(ref map) => {
+                let mut res = map.clone();
+                for val in Arc::make_mut(&mut res).values_mut() {
+                    *val = val.deep_clone(stack);
+                }
+                Map(res)
+            }
+            Set(_) => self.clone(),
             Array(ref arr) => {
                 let mut res = arr.clone();
                 for it in Arc::make_mut(&mut res) {
@@ -327,6 +503,13 @@ impl Variable {
             Closure(_, _) => self.clone(),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             In(_) => self.clone(),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Out(_) => self.clone(),
+            // Shared values are not cloned, such that threads can share state.
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Shared(_) => self.clone(),
+            // Generators are not cloned, since they can not be restarted.
+            Iter(_) => self.clone(),
         }
     }
 }
@@ -339,6 +522,8 @@ impl PartialEq for Variable {
             (&Variable::F64(a, _), &Variable::F64(b, _)) => a == b,
             (&Variable::Str(ref a), &Variable::Str(ref b)) => a == b,
             (&Variable::Object(ref a), &Variable::Object(ref b)) => a == b,
+            (&Variable::Map(ref a), &Variable::Map(ref b)) => a == b,
+            (&Variable::Set(ref a), &Variable::Set(ref b)) => a == b,
             (&Variable::Array(ref a), &Variable::Array(ref b)) => a == b,
             (&Variable::Ref(_), _) => false,
             (&Variable::UnsafeRef(_), _) => false,
@@ -365,8 +550,18 @@ pub enum FnIndex {
     BinOp(FnBinOpRef),
     /// Extern unary operator.
     UnOp(FnUnOpRef),
+    /// Extern async function.
+    #[cfg(feature = "async")]
+    Async(FnAsyncRef),
 }
 
+/// Future returned by an external async function.
+///
+/// Resolves to `None` when the function does not return a value.
+#[cfg(feature = "async")]
+pub type FnAsyncFuture =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<Option<Variable>, String>> + Send>>;
+
 /// Refers to an external function.
 #[derive(Clone, Copy)]
 pub enum FnExt {
@@ -378,6 +573,13 @@ pub enum FnExt {
     BinOp(fn(&Variable, &Variable) -> Result<Variable, String>),
     /// External unary operator.
     UnOp(fn(&Variable) -> Result<Variable, String>),
+    /// External async function.
+    ///
+    /// Arguments are popped from the stack before returning the future.
+    /// The script waits for the future to resolve before it continues,
+    /// which is an error on a current-thread Tokio runtime.
+    #[cfg(feature = "async")]
+    Async(fn(&mut Runtime) -> Result<FnAsyncFuture, String>),
 }
 
 impl From<fn(&mut Runtime) -> Result<(), String>> for FnExt {
@@ -404,6 +606,13 @@ impl From<fn(&Variable) -> Result<Variable, String>> for FnExt {
     }
 }
 
+#[cfg(feature = "async")]
+impl From<fn(&mut Runtime) -> Result<FnAsyncFuture, String>> for FnExt {
+    fn from(val: fn(&mut Runtime) -> Result<FnAsyncFuture, String>) -> Self {
+        FnExt::Async(val)
+    }
+}
+
 impl fmt::Debug for FnExt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "FnExt")
@@ -474,6 +683,25 @@ impl fmt::Debug for FnVoidRef {
     }
 }
 
+/// Used to store direct reference to external async function.
+#[cfg(feature = "async")]
+#[derive(Copy)]
+pub struct FnAsyncRef(pub fn(&mut Runtime) -> Result<FnAsyncFuture, String>);
+
+#[cfg(feature = "async")]
+impl Clone for FnAsyncRef {
+    fn clone(&self) -> FnAsyncRef {
+        *self
+    }
+}
+
+#[cfg(feature = "async")]
+impl fmt::Debug for FnAsyncRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FnAsyncRef")
+    }
+}
+
 struct FnExternal {
     namespace: Arc<Vec<Arc<String>>>,
     name: Arc<String>,
@@ -624,6 +852,19 @@ pub(crate) fn check_str(
 /// - d - The data of source file
 /// - module - The module to load the source
 pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(), String> {
+    load_str_report(source, d, module, &|_, _| None)
+}
+
+/// Loads a source from string, reporting errors with a closure.
+///
+/// The closure gets the range and message of a parse, lifetime or type error.
+/// It returns the whole error message, or `None` to report the error in the source.
+pub(crate) fn load_str_report(
+    source: &str,
+    d: Arc<String>,
+    module: &mut Module,
+    report: &dyn Fn(Range, &str) -> Option<String>,
+) -> Result<(), String> {
     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     struct MaybeThread<T>(std::thread::JoinHandle<T>);
 
@@ -659,8 +900,17 @@ pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(),
     let syntax_rules = SYNTAX_RULES.as_ref().map_err(|err| err.clone())?;
 
     let mut data = vec![];
-    parse_errstr(syntax_rules, &d, &mut data)
-        .map_err(|err| format!("In `{}:`\n{}", source, err))?;
+    if let Err(range_err) = piston_meta::parse(syntax_rules, &d, &mut data) {
+        let (range, err) = range_err.decouple();
+        if let Some(msg) = report(range, &format!("Error {}", err)) {
+            return Err(msg);
+        }
+        let mut buf: Vec<u8> = vec![];
+        piston_meta::ParseErrorHandler::new(&d)
+            .write(&mut buf, range.wrap(err))
+            .unwrap();
+        return Err(format!("In `{}:`\n{}", source, String::from_utf8(buf).unwrap()));
+    }
 
     let check_data = data.clone();
     let prelude = Arc::new(Prelude::from_module(module));
@@ -696,6 +946,9 @@ pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(),
             use std::io::Write;
 
             let (range, msg) = err_msg.decouple();
+            if let Some(msg) = report(range, &msg) {
+                return Err(msg);
+            }
 
             let mut buf: Vec<u8> = vec![];
             writeln!(&mut buf, "In `{}`:\n", source).unwrap();
@@ -831,6 +1084,351 @@ mod tests {
         assert_eq!(size_of::<ast::Expression>(), 16);
     }
 
+    #[test]
+    fn shift_overflow() {
+        use super::*;
+        use std::sync::Arc;
+
+        let source = "fn main() { x := 1 << 63 }";
+        let err = run_str("main.dyon", Arc::new(source.into())).unwrap_err();
+        assert!(err.contains("Shift overflows integer range"), "{}", err);
+    }
+
+    #[test]
+    fn string_arguments() {
+        use super::*;
+        use std::sync::Arc;
+
+        for call in ["repeat(\"ab\", 1e20)", "repeat(\"ab\", 1e15)", "pad_left(\"ab\", 1e15)"] {
+            let source = format!("fn main() {{ x := {} }}", call);
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("is too long"), "{}", err);
+        }
+        for args in ["0.5, 2", "0, 1.5"] {
+            let source = format!("fn main() {{ x := substring(\"abc\", {}) }}", args);
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("Expected integer"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn link_overwrite() {
+        use super::*;
+        use std::sync::Arc;
+
+        let mut link = Link::new();
+        link.push(&Variable::f64(1.0)).unwrap();
+        link.push(&Variable::Array(Arc::new(vec![Variable::f64(2.0)]))).unwrap();
+        let mut neck = link.neck();
+        drop(link);
+        // The block is no longer shared, so the array is overwritten in place.
+        neck.push(&Variable::bool(true)).unwrap();
+        assert!(matches!(neck.tip().as_deref(), Some(&Variable::Bool(true, _))));
+        assert!(neck.slices[0].block.others.is_empty());
+        neck.push(&Variable::Vec4([1.0, 2.0, 3.0, 4.0])).unwrap();
+        assert!(matches!(neck.tip().as_deref(), Some(&Variable::Vec4(_))));
+    }
+
+    #[test]
+    fn number_spec_limits() {
+        use super::*;
+        use std::sync::Arc;
+
+        for spec in [".999999999999999", "999999999999999", "1001"] {
+            let source = format!("fn main() {{ x := fmt(number: 1, spec: \"{}\") }}", spec);
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("Expected width and precision at most"), "{}", err);
+        }
+        let source = "fn main() { x := parse_number__radix(\"20000000000001\", 16) }";
+        let err = run_str("main.dyon", Arc::new(source.into())).unwrap_err();
+        assert!(err.contains("Expected integer at most 2^53"), "{}", err);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random_arguments() {
+        use super::*;
+        use std::sync::Arc;
+
+        for call in [
+            "range(0, 1/0)",
+            "weighted_choice([1, 2], [1/0, 1])",
+            "weighted_choice([1, 2], [1e308, 1e308])",
+        ] {
+            let source = format!("fn main() {{ x := {} }}", call);
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("Expected finite"), "{}", err);
+        }
+    }
+
+    #[cfg(feature = "process")]
+    #[test]
+    fn exit_code() {
+        use super::*;
+        use std::sync::Arc;
+
+        let mut module = Module::new();
+        let source = "fn stop() {\n exit(3)\n}\nfn main() {\n stop()\n println(\"after\")\n}";
+        load_str("main.dyon", Arc::new(source.into()), &mut module).unwrap();
+        let mut rt = Runtime::new();
+        let err = rt.run(&Arc::new(module)).unwrap_err();
+        assert!(err.contains("Exited with code `3`"), "{}", err);
+        assert_eq!(rt.exit_code(), Some(3));
+
+        // The exit code is cleared when the runtime is reused.
+        let mut module = Module::new();
+        load_str("main.dyon", Arc::new("fn main() {}".into()), &mut module).unwrap();
+        rt.run(&Arc::new(module)).unwrap();
+        assert_eq!(rt.exit_code(), None);
+    }
+
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    #[test]
+    fn select_timeout() {
+        use super::*;
+        use std::sync::Arc;
+
+        for timeout in ["-1", "0/0"] {
+            let source = format!(
+                "fn main() {{\n [_, rx] := channel()\n x := select__timeout([rx], {})\n}}",
+                timeout
+            );
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("Expected non-negative timeout"), "{}", err);
+            let source = format!(
+                "fn f() -> f64 {{ return 1 }}\n\
+                 fn main() {{\n x := join__thread_timeout(go f(), {})\n}}",
+                timeout
+            );
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("Expected non-negative timeout"), "{}", err);
+        }
+    }
+
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    #[test]
+    fn nested_lock_update() {
+        use super::*;
+        use std::sync::Arc;
+
+        for inner in ["lock_update(grab s, \\(y) = clone(y))", "_ := read(grab s)"] {
+            let source = format!(
+                "fn main() {{\n s := shared(0)\n lock_update(s, \\(x) = {{\n {}\n clone(x)\n }})\n}}",
+                inner
+            );
+            let err = run_str("main.dyon", Arc::new(source)).unwrap_err();
+            assert!(err.contains("while it is locked by `lock_update`"), "{}", err);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn double(rt: &mut super::Runtime) -> Result<super::FnAsyncFuture, String> {
+        let v: f64 = rt.pop()?;
+        Ok(Box::pin(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            Ok(Some(super::Variable::f64(2.0 * v)))
+        }))
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_fn() {
+        use super::*;
+        use std::sync::Arc;
+
+        let mut module = Module::new();
+        module.add_str("double", double, Dfn::nl(vec![Type::F64], Type::F64));
+        let source = r#"
+            fn compute(x: f64) -> f64 {
+                sleep(0.001)
+                return double(x)
+            }
+
+            fn main() {
+                sleep(0.001)
+                if double(2) != 4 { _ := unwrap(err("Expected 4")) }
+                t := go compute(3)
+                if unwrap(join(thread: t)) != 6 { _ := unwrap(err("Expected 6")) }
+            }
+        "#;
+        load_str("main.dyon", Arc::new(source.into()), &mut module).unwrap();
+        let module = Arc::new(module);
+        Runtime::new().run(&module).unwrap_or_else(|err| panic!("{}", err));
+
+        // Inside a multi-threaded Tokio runtime, the script blocks a worker.
+        let mut module = Module::new();
+        module.add_str("double", double, Dfn::nl(vec![Type::F64], Type::F64));
+        let source = "fn main() {\n sleep(0.001)\n if double(2) != 4 { _ := unwrap(err(\"Expected 4\")) }\n}";
+        load_str("main.dyon", Arc::new(source.into()), &mut module).unwrap();
+        let module = Arc::new(module);
+        let tokio_rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = Runtime::new();
+        let (_rt, res) = tokio_rt.block_on(async move {
+            tokio::spawn(async move {
+                let mut rt = rt;
+                let res = rt.run(&module);
+                (rt, res)
+            })
+            .await
+            .unwrap()
+        });
+        res.unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_current_thread() {
+        use super::*;
+        use std::sync::Arc;
+
+        let mut module = Module::new();
+        module.add_str("double", double, Dfn::nl(vec![Type::F64], Type::F64));
+        let source = "fn main() {\n sleep(0.001)\n _ := double(2)\n}";
+        load_str("main.dyon", Arc::new(source.into()), &mut module).unwrap();
+        let module = Arc::new(module);
+        let tokio_rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let rt = Runtime::new();
+        // The Dyon runtime is returned, since it can not be dropped inside a Tokio runtime.
+        let (_rt, res) = tokio_rt.block_on(async move {
+            let mut rt = rt;
+            let res = rt.run(&module);
+            (rt, res)
+        });
+        // Sleeping blocks the thread, but async functions can not be waited for.
+        let err = res.unwrap_err();
+        assert!(err.contains("Can not wait for async function `double`"), "{}", err);
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn data_keeps_comments() {
+        use super::*;
+        use dyon_std::data::{load_layout, write_data, KeyOrder, Options};
+
+        let src = "// Header.\n{\n    b: [\n        1, // one\n        // two\n        2\n    ],\n    a: 3\n}\n";
+        let (v, layout) = load_layout(src).unwrap();
+        let rt = Runtime::new();
+        let options = Options {
+            keys: KeyOrder::Insertion,
+            width: 0,
+            ..Options::default()
+        };
+        assert_eq!(write_data(&rt, &v, &options, Some(&layout)), src);
+        let options = Options {
+            comments: false,
+            ..Options::default()
+        };
+        assert_eq!(write_data(&rt, &v, &options, Some(&layout)), "{a: 3, b: [1, 2]}\n");
+    }
+
+    #[test]
+    fn binary_data() {
+        use super::*;
+        use dyon_std::data::{load_data, write_data, Options};
+
+        let src = "{name: \"a\", pos: [(1, 2), (3, 4)], xs: [1, 2.5], \
+                   m: map {\"a\": 1, [1, 2]: \"a\"}, s: set {\"x\", 2}, \
+                   o: [none(), some(\"a\"), true], l: link {1 \"a\" false}, e: [], n: {}}";
+        let v = load_data(src).unwrap();
+        let bytes = binary::encode(&v).unwrap();
+        // The string `"a"` is stored once.
+        assert_eq!(bytes.windows(2).filter(|w| w == b"\x01a").count(), 1);
+        let res = binary::decode(&bytes).unwrap();
+        let rt = Runtime::new();
+        let options = Options::default();
+        assert_eq!(
+            write_data(&rt, &res, &options, None),
+            write_data(&rt, &v, &options, None)
+        );
+
+        let packed = binary::encode(&load_data("[(1, 2), (3, 4)]").unwrap()).unwrap();
+        assert_eq!(packed.len(), 8 + 1 + 2 + 2 * 16);
+        assert!(binary::decode(&packed[..packed.len() - 1]).is_err());
+        assert!(binary::decode(b"DYBN\x02\x00\x00\x00").is_err());
+        assert!(binary::encode(&Variable::Return).is_err());
+
+        let mut nested = b"DYBN\x01\x00\x00\x00\x00".to_vec();
+        nested.extend(std::iter::repeat(1).take(2_000_000));
+        nested.push(0);
+        let err = binary::decode(&nested).unwrap_err();
+        assert!(err.contains("Nesting deeper than"), "{}", err);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        use super::*;
+
+        let rows = csv::parse(
+            "a,b,c\n\"x, \"\"y\"\"\",2,true\n\" z\",\"4\",\n",
+            &csv::Options {
+                header: true,
+                ..csv::Options::default()
+            },
+        )
+        .unwrap();
+        let text = csv::write(&rows, &csv::Options::default()).unwrap();
+        assert_eq!(text, "a,b,c\n\"x, \"\"y\"\"\",2,true\n\" z\",\"4\",\"\"\n");
+
+        let rows = csv::parse("a\n\"\"\nb\n", &csv::Options::default()).unwrap();
+        let text = csv::write(&rows, &csv::Options::default()).unwrap();
+        assert_eq!(text, "a\n\"\"\nb\n");
+        let header = csv::Options {
+            header: true,
+            ..csv::Options::default()
+        };
+        let err = csv::parse("a,a\n1,2\n", &header).unwrap_err();
+        assert!(err.contains("Duplicate header name `a`"), "{}", err);
+
+        let tsv = csv::Options {
+            delimiter: Some('\t'),
+            ..csv::Options::default()
+        };
+        let rows = csv::parse("1\t\"a\tb\"\n", &tsv).unwrap();
+        assert_eq!(csv::write(&rows, &tsv).unwrap(), "1\t\"a\tb\"\n");
+        assert!(csv::write(&Variable::f64(1.0), &tsv).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_variable() {
+        use super::*;
+        use std::collections::HashMap;
+
+        type Data = (
+            u32,
+            String,
+            Option<bool>,
+            Result<f64, String>,
+            Vec<Vec4>,
+            HashMap<String, Mat4>,
+        );
+
+        let mut m = HashMap::new();
+        m.insert("m".to_string(), Mat4([[1.0, 2.0, 3.0, 4.0]; 4]));
+        let data: Data = (3, "a".into(), Some(true), Err("bad".into()), vec![Vec4([1.0; 4])], m);
+        let v = to_variable(&data).unwrap();
+        if let Variable::Array(ref arr) = v {
+            assert!(matches!(arr[2], Variable::Option(Some(_))));
+            assert!(matches!(arr[3], Variable::Result(Err(_))));
+            assert!(matches!(arr[4], Variable::Array(ref a) if matches!(a[0], Variable::Vec4(_))));
+            assert!(matches!(arr[5], Variable::Object(_)));
+        } else {
+            panic!("Expected array");
+        }
+        let res: Data = from_variable(&v).unwrap();
+        assert_eq!(res.0, 3);
+        assert_eq!(res.1, "a");
+        assert_eq!(res.2, Some(true));
+        assert_eq!(res.3, Err("bad".into()));
+        assert_eq!(res.4[0].0, [1.0; 4]);
+        assert_eq!(res.5["m"].0, [[1.0, 2.0, 3.0, 4.0]; 4]);
+        assert!(from_variable::<u32>(&Variable::f64(0.5)).is_err());
+        assert!(from_variable::<Vec4>(&v).is_err());
+    }
+
     fn run_bench(source: &str) {
         run(source).unwrap_or_else(|err| panic!("{}", err));
     }
@@ -5,6 +5,7 @@ pub enum Kind {
     Use,
     Fn,
     Arg,
+    Default,
     Current,
     Block,
     Expr,
@@ -21,6 +22,10 @@ pub enum Kind {
     ItemExtra,
     Return,
     Object,
+    Map,
+    MapKey,
+    Set,
+    SetItem,
     Array,
     ArrayItem,
     ArrayFill,
@@ -60,6 +65,8 @@ pub enum Kind {
     TrueBlock,
     ElseBlock,
     Loop,
+    While,
+    LetSome,
     Id,
     Break,
     Continue,
@@ -82,6 +89,7 @@ pub enum Kind {
     Res,
     RetType,
     ReturnVoid,
+    Yield,
     Go,
     Swizzle,
     Sw0,
@@ -113,6 +121,7 @@ impl Kind {
             "use" => Kind::Use,
             "fn" => Kind::Fn,
             "arg" => Kind::Arg,
+            "default" => Kind::Default,
             "current" => Kind::Current,
             "block" => Kind::Block,
             "expr" => Kind::Expr,
@@ -130,6 +139,10 @@ impl Kind {
             "item_extra" => Kind::ItemExtra,
             "return" => Kind::Return,
             "object" => Kind::Object,
+            "map" => Kind::Map,
+            "map_key" => Kind::MapKey,
+            "set" => Kind::Set,
+            "set_item" => Kind::SetItem,
             "array" => Kind::Array,
             "array_item" => Kind::ArrayItem,
             "array_fill" => Kind::ArrayFill,
@@ -169,6 +182,8 @@ impl Kind {
             "true_block" => Kind::TrueBlock,
             "else_block" => Kind::ElseBlock,
             "loop" => Kind::Loop,
+            "while" => Kind::While,
+            "let_some" => Kind::LetSome,
             "id" => Kind::Id,
             "break" => Kind::Break,
             "continue" => Kind::Continue,
@@ -191,6 +206,7 @@ impl Kind {
             "res" => Kind::Res,
             "ret_type" => Kind::RetType,
             "return_void" => Kind::ReturnVoid,
+            "yield" => Kind::Yield,
             "go" => Kind::Go,
             "swizzle" => Kind::Swizzle,
             "sw0" => Kind::Sw0,
@@ -237,6 +253,13 @@ impl Kind {
         )
     }
 
+    /// A loop that can be exited with `break`.
+    pub fn is_loop(self) -> bool {
+        use self::Kind::*;
+
+        matches!(self, Loop | For | While) || self.is_decl_loop() || self.is_in_loop()
+    }
+
     /// An un-loop has fixed range and replaces variable in body.
     pub fn is_decl_un_loop(self) -> bool {
         matches!(self, Kind::Vec4UnLoop)
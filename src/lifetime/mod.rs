@@ -10,8 +10,8 @@ use self::range::Range;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use crate::ast::{AssignOp, UseLookup};
-use crate::prelude::{Lt, Prelude};
+use crate::ast::{default_calls, AssignOp, UseLookup, MAX_DEFAULTS};
+use crate::prelude::{Dfn, Lt, Prelude};
 
 use crate::Type;
 
@@ -31,6 +31,31 @@ pub fn check(
     check_core(&mut nodes, data, prelude)
 }
 
+/// Sets the argument lifetime constraints of a call to a prelude function.
+fn prelude_call(node: &mut Node, f: &Dfn, n: usize) -> Result<(), Range<String>> {
+    let required = f.lts.len() - f.defaults;
+    if n < required || n > f.lts.len() {
+        return Err(node.source.wrap(format!(
+            "{}: Expected {} arguments, found {}",
+            node.name().expect("Expected name"),
+            f.lts.len(),
+            n
+        )));
+    }
+    node.lts = f.lts.clone();
+    // Arguments that are left out use default values,
+    // which do not depend on the lifetime of other arguments.
+    node.lts.truncate(n);
+    for lt in &mut node.lts {
+        if let Lt::Arg(ind) = *lt {
+            if ind >= n {
+                *lt = Lt::Default;
+            }
+        }
+    }
+    Ok(())
+}
+
 // Core lifetime and type check.
 pub(crate) fn check_core(
     nodes: &mut Vec<Node>,
@@ -62,10 +87,12 @@ pub(crate) fn check_core(
             nodes.push(Node {
                 kind: nodes[i].kind,
                 names: vec![],
+                label: None,
                 ty: None,
                 declaration: None,
                 alias: None,
                 mutable: false,
+                par: false,
                 try_flag: false,
                 grab_level: 0,
                 source: nodes[i].source,
@@ -125,6 +152,11 @@ pub(crate) fn check_core(
                     Cross => crate::CROSS.clone(),
                     AndAlso => crate::AND_ALSO.clone(),
                     OrElse => crate::OR_ELSE.clone(),
+                    BitAnd => crate::BIT_AND.clone(),
+                    BitOr => crate::BIT_OR.clone(),
+                    BitXor => crate::BIT_XOR.clone(),
+                    Shl => crate::SHL.clone(),
+                    Shr => crate::SHR.clone(),
                     Less => crate::LESS.clone(),
                     LessOrEqual => crate::LESS_OR_EQUAL.clone(),
                     Greater => crate::GREATER.clone(),
@@ -279,13 +311,17 @@ pub(crate) fn check_core(
                 && !n.children.is_empty()
                 && !nodes[n.children[0]].children.is_empty()
         })
-        .map(|(i, n)| {
+        .flat_map(|(i, n)| {
             // Left argument.
             let j = n.children[0];
             let node = &nodes[j];
             // Item in left argument.
             let j = node.children[0];
-            (i, j)
+            // Destructuring declares one local per item.
+            match nodes[j].destructure(nodes) {
+                Some(items) => items.iter().map(|&j| (i, j)).collect(),
+                None => vec![(i, j)],
+            }
         })
         // Filter out assignments to objects or arrays to get locals only.
         .filter(|&(_, j)| !nodes[j].item_ids())
@@ -361,9 +397,18 @@ pub(crate) fn check_core(
         let mut grab = 0;
 
         'search: loop {
+            // Default values are evaluated where the function is called.
+            if nodes[parent].kind == Kind::Default {
+                return Err(nodes[i].source.wrap(format!(
+                    "Default value can not refer to `{}`",
+                    nodes[i].name().expect("Expected name")
+                )));
+            }
+            // The variable of `while let` is only declared inside the block.
             if nodes[parent].kind.is_decl_loop()
                 || nodes[parent].kind.is_decl_un_loop()
                 || nodes[parent].kind.is_in_loop()
+                || (nodes[parent].kind == Kind::While && nodes[child].kind == Kind::Block)
             {
                 let my_name = nodes[i].name().unwrap();
                 for name in &nodes[parent].names {
@@ -390,6 +435,16 @@ pub(crate) fn check_core(
                 }
                 let left = nodes[j].children[0];
                 let item = nodes[left].children[0];
+                // Destructuring declares several items.
+                let item = match nodes[item].destructure(nodes) {
+                    Some(items) => {
+                        match items.iter().find(|&&it| nodes[it].name() == nodes[i].name()) {
+                            Some(&it) => it,
+                            None => continue,
+                        }
+                    }
+                    None => item,
+                };
                 if nodes[item].name() == nodes[i].name() {
                     if nodes[item].item_ids() {
                         continue;
@@ -520,11 +575,16 @@ pub(crate) fn check_core(
         }
     }
 
+    // Stores number of required function arguments with same index as `functions`.
+    // The rest of the arguments have default values.
+    let mut function_required: Vec<usize> = Vec::with_capacity(functions.len());
+
     // Check for duplicate function arguments.
     let mut arg_names: HashSet<Arc<String>> = HashSet::new();
     for &f in &functions {
         arg_names.clear();
         let mut n = 0;
+        let mut required: Option<usize> = None;
         for &i in nodes[f]
             .children
             .iter()
@@ -538,9 +598,29 @@ pub(crate) fn check_core(
             } else {
                 arg_names.insert(name.clone());
             }
+            if nodes[i].find_child_by_kind(nodes, Kind::Default).is_some() {
+                required.get_or_insert(n);
+            } else if required.is_some() {
+                return Err(nodes[i].source.wrap(format!(
+                    "Expected default value for `{}`, \
+                    because it follows an argument with default value",
+                    name
+                )));
+            }
             n += 1;
         }
+        let required = required.unwrap_or(n);
+        // Named calls can leave out any of these arguments,
+        // which increases the number of calls exponentially.
+        if n - required > MAX_DEFAULTS {
+            return Err(nodes[f].source.wrap(format!(
+                "Expected at most {} arguments with default values, found {}",
+                MAX_DEFAULTS,
+                n - required
+            )));
+        }
         function_args.push(n);
+        function_required.push(required);
     }
 
     // Check extra type information.
@@ -618,6 +698,26 @@ pub(crate) fn check_core(
         }
     }
 
+    // Build call name to index map for calls that leave out arguments with default values,
+    // storing the declared positions of arguments in the call.
+    let mut default_lookup: HashMap<Arc<String>, (usize, Vec<usize>)> = HashMap::new();
+    for (i, &f) in functions.iter().enumerate() {
+        if function_required[i] == function_args[i] {
+            continue;
+        }
+        let name = nodes[f].name().expect("Expected name");
+        let args: Vec<(Arc<String>, bool)> = nodes[f]
+            .children
+            .iter()
+            .filter(|&&ch| nodes[ch].kind == Kind::Arg)
+            .map(|&ch| (nodes[ch].name().expect("Expected name").clone(), nodes[ch].mutable))
+            .collect();
+        let name = name.split('(').next().unwrap_or(name);
+        for (call_name, positions) in default_calls(name, &args, function_required[i]) {
+            default_lookup.entry(call_name).or_insert((i, positions));
+        }
+    }
+
     let mut use_lookup: UseLookup = UseLookup::new();
     for node in nodes.iter() {
         if node.kind == Kind::Uses {
@@ -632,6 +732,9 @@ pub(crate) fn check_core(
         }
     }
 
+    // Stores named calls that leave out arguments before other arguments.
+    let mut named_defaults: Vec<(usize, usize, Vec<usize>)> = vec![];
+
     // Link call nodes to functions.
     for &c in &calls {
         let n = {
@@ -665,7 +768,7 @@ pub(crate) fn check_core(
             if let Some(&FnAlias::Loaded(i)) =
                 use_lookup.aliases.get(alias).and_then(|map| map.get(&name))
             {
-                node.lts = prelude.list[i].lts.clone();
+                prelude_call(node, &prelude.list[i], n)?;
                 continue;
             } else {
                 return Err(node
@@ -675,28 +778,29 @@ pub(crate) fn check_core(
         }
         let i = match function_lookup.get(&name) {
             Some(&i) => i,
-            None => {
-                // Check whether it is a prelude function.
-                if let Some(&pf) = prelude.functions.get(&name) {
-                    node.lts = prelude.list[pf].lts.clone();
-                    if node.lts.len() != n {
-                        return Err(node.source.wrap(format!(
-                            "{}: Expected {} arguments, found {}",
-                            name,
-                            node.lts.len(),
-                            n
-                        )));
+            None => match default_lookup.get(&name) {
+                Some(&(i, ref positions)) => {
+                    if positions.iter().enumerate().any(|(j, &p)| j != p) {
+                        named_defaults.push((c, i, positions.clone()));
                     }
-                    continue;
+                    i
                 }
-                let suggestions = suggestions(&**name, &function_lookup, prelude);
-                return Err(node
-                    .source
-                    .wrap(format!("Could not find function `{}`{}", name, suggestions)));
-            }
+                None => {
+                    // Check whether it is a prelude function.
+                    if let Some(&pf) = prelude.functions.get(&name) {
+                        prelude_call(node, &prelude.list[pf], n)?;
+                        continue;
+                    }
+                    let suggestions = suggestions(&**name, &function_lookup, prelude);
+                    return Err(node
+                        .source
+                        .wrap(format!("Could not find function `{}`{}", name, suggestions)));
+                }
+            },
         };
         // Check that number of arguments is the same as in declaration.
-        if function_args[i] != n {
+        // Arguments with default values can be left out.
+        if n < function_required[i] || n > function_args[i] {
             let suggestions = suggestions(&**name, &function_lookup, prelude);
             return Err(node.source.wrap(format!(
                 "{}: Expected {} arguments, found {}{}",
@@ -706,6 +810,67 @@ pub(crate) fn check_core(
         node.declaration = Some(functions[i]);
     }
 
+    // Named calls that leave out arguments before other arguments
+    // get default values as call arguments, such that they match the declaration.
+    for (c, i, positions) in named_defaults {
+        let call_args: Vec<usize> = nodes[c]
+            .children
+            .iter()
+            .cloned()
+            .filter(|&ch| nodes[ch].kind == Kind::CallArg)
+            .collect();
+        for &ch in &call_args {
+            if let Some(sw) = nodes[ch].find_child_by_kind(nodes, Kind::Swizzle) {
+                return Err(nodes[sw].source.wrap(
+                    "Can not use swizzle when leaving out arguments with default values"
+                        .to_string(),
+                ));
+            }
+        }
+        let args: Vec<usize> = nodes[functions[i]]
+            .children
+            .iter()
+            .cloned()
+            .filter(|&ch| nodes[ch].kind == Kind::Arg)
+            .collect();
+        let mut call_args = call_args.into_iter();
+        let mut positions = positions.into_iter().peekable();
+        let mut children = vec![];
+        for (j, &a) in args.iter().enumerate() {
+            if positions.next_if(|&p| p == j).is_some() {
+                children.extend(call_args.next());
+                continue;
+            }
+            let default = nodes[a]
+                .find_child_by_kind(nodes, Kind::Default)
+                .expect("Expected default value");
+            let k = nodes.len();
+            nodes.push(Node {
+                kind: Kind::CallArg,
+                names: vec![],
+                label: None,
+                ty: None,
+                declaration: None,
+                alias: None,
+                mutable: false,
+                par: false,
+                try_flag: false,
+                grab_level: 0,
+                source: nodes[c].source,
+                start: nodes[default].start,
+                end: nodes[default].end,
+                lifetime: None,
+                op: None,
+                binops: vec![],
+                lts: vec![],
+                parent: Some(c),
+                children: nodes[default].children.clone(),
+            });
+            children.push(k);
+        }
+        nodes[c].children = children;
+    }
+
     // Check in-nodes.
     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     for &c in &ins {
@@ -913,9 +1078,9 @@ pub(crate) fn check_core(
     }
 
     // Check that `go` functions does not have lifetime constraints.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     for &c in &calls {
         let call = &nodes[c];
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         if let Some(parent) = call.parent {
             if nodes[parent].kind != Kind::Go {
                 continue;
@@ -923,10 +1088,6 @@ pub(crate) fn check_core(
         } else {
             continue;
         }
-        #[cfg(any(target_family = "wasm", not(feature = "threading")))]
-        if call.parent.is_none() {
-            continue;
-        }
         if let Some(declaration) = call.declaration {
             let function = &nodes[declaration];
             for (i, &a) in function
@@ -937,7 +1098,12 @@ pub(crate) fn check_core(
             {
                 let arg = &nodes[a];
                 if arg.lifetime.is_some() {
-                    return Err(nodes[call.children[i]].source.wrap(
+                    // Report on the call when the argument is left out.
+                    let source = match call.children.get(i) {
+                        Some(&ch) => &nodes[ch].source,
+                        None => &call.source,
+                    };
+                    return Err(source.wrap(
                         "Can not use `go` because this argument has a lifetime constraint"
                             .to_string(),
                     ));
@@ -1022,6 +1188,8 @@ pub(crate) fn check_core(
                 .enumerate()
                 .filter(|&(_, &i)| nodes[i].kind == Kind::Arg)
                 .map(|(i, a)| (map_arg_call_arg_index(i), a))
+                // Skip arguments that are left out, using default values.
+                .filter(|&(i, _)| i < call.children.len())
             {
                 let arg = &nodes[a];
                 if let Some(ref lt) = arg.lifetime {
@@ -1044,6 +1212,9 @@ pub(crate) fn check_core(
                         let (_, ind) = *arg_names
                             .get(&(declaration, lt.clone()))
                             .expect("Expected argument name");
+                        if ind >= call.children.len() {
+                            continue;
+                        }
                         let left = call.children[ind];
                         let right = call.children[i];
                         let lifetime_left = &nodes[left].lifetime(nodes, &arg_names);
@@ -1141,6 +1312,40 @@ pub(crate) fn check_core(
         }
     }
 
+    // Check that `par` loops do not mutate variables declared outside the loop.
+    let mutated_items = mutated_locals.iter().map(|&(_, i)| i).chain(
+        nodes
+            .iter()
+            .filter(|n| n.kind == Kind::CallArg && n.mutable && !n.children.is_empty())
+            .map(|n| n.children[0])
+            .filter(|&i| nodes[i].kind == Kind::Item),
+    );
+    for i in mutated_items {
+        if let Some(decl) = nodes[i].declaration {
+            let mut parent = nodes[i].parent;
+            while let Some(p) = parent {
+                if nodes[p].par {
+                    let mut inside = false;
+                    let mut n = Some(decl);
+                    while let Some(j) = n {
+                        if j == p {
+                            inside = true;
+                            break;
+                        }
+                        n = nodes[j].parent;
+                    }
+                    if !inside {
+                        return Err(nodes[i].source.wrap(format!(
+                            "Can not mutate `{}` declared outside `par` loop",
+                            nodes[i].name().unwrap()
+                        )));
+                    }
+                }
+                parent = nodes[p].parent;
+            }
+        }
+    }
+
     typecheck::run(nodes, prelude, &use_lookup)?;
 
     // Copy refined return types to use in AST.
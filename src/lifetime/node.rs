@@ -16,10 +16,14 @@ pub(crate) struct Node {
     pub alias: Option<Arc<String>>,
     /// The names associated with a node.
     pub names: Vec<Arc<String>>,
+    /// The loop label of a loop, `break` or `continue`.
+    pub label: Option<Arc<String>>,
     /// The type.
     pub ty: Option<Type>,
     /// Whether the argument or call argument is mutable.
     pub mutable: bool,
+    /// Whether the loop runs in parallel.
+    pub par: bool,
     /// Whether there is a `?` operator used on the node.
     pub try_flag: bool,
     /// The grab level.
@@ -75,10 +79,12 @@ impl Node {
         nodes.push(Node {
             kind: Kind::CallArg,
             names: vec![],
+            label: None,
             ty: None,
             declaration: None,
             alias: None,
             mutable: false,
+            par: false,
             try_flag: false,
             grab_level: 0,
             source: nodes[old_left].source,
@@ -95,10 +101,12 @@ impl Node {
         nodes.push(Node {
             kind: Kind::CallArg,
             names: vec![],
+            label: None,
             ty: None,
             declaration: None,
             alias: None,
             mutable: false,
+            par: false,
             try_flag: false,
             grab_level: 0,
             source: nodes[old_right].source,
@@ -169,6 +177,21 @@ impl Node {
         None
     }
 
+    /// Returns the items declared by destructuring, e.g. `[a, b] := ...`.
+    ///
+    /// This is called on the child of a left expression.
+    pub fn destructure(&self, nodes: &[Node]) -> Option<&[usize]> {
+        match self.kind {
+            Kind::Array | Kind::Object | Kind::Vec4
+                if !self.children.is_empty()
+                    && self.children.iter().all(|&ch| nodes[ch].kind == Kind::Item) =>
+            {
+                Some(&self.children)
+            }
+            _ => None,
+        }
+    }
+
     pub fn item_ids(&self) -> bool {
         self.kind == Kind::Item && !self.children.is_empty()
     }
@@ -191,8 +214,8 @@ impl Node {
         match self.kind {
             Pow | Sum | SumIn | Prod | ProdIn | SumVec4 | Min | MinIn | Max | MaxIn | Any
             | AnyIn | All | AllIn | LinkIn | Vec4 | Mat4 | Vec4UnLoop | Swizzle | Assign | For
-            | ForN | ForIn | Link | LinkFor | Closure | CallClosure | Grab | TryExpr | Norm
-            | In => false,
+            | ForN | ForIn | While | Loop | Break | Link | LinkFor | Closure | CallClosure
+            | Grab | TryExpr | Norm | In | Yield => false,
             Add | Mul | Compare => self.children.len() == 1,
             _ => true,
         }
@@ -261,8 +284,11 @@ impl Node {
                 (_, Kind::LinkIn) => {}
                 (_, Kind::LinkItem) => {}
                 (_, Kind::ReturnVoid) => {}
+                (_, Kind::Yield) => {}
                 (_, Kind::Swizzle) => {}
                 (_, Kind::Loop) => {}
+                (_, Kind::While) => {}
+                (_, Kind::LetSome) => {}
                 #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
                 (_, Kind::Go) => {}
                 (_, Kind::For) => {}
@@ -294,6 +320,13 @@ impl Node {
                 (_, Kind::End) => continue,
                 (_, Kind::Assign) => {}
                 (_, Kind::Object) => {}
+                (_, Kind::Map) => {}
+                (_, Kind::MapKey) | (_, Kind::SetItem) => {
+                    // Keys are copied into the map or set,
+                    // so the result does not depend on their lifetime.
+                    continue;
+                }
+                (_, Kind::Set) => {}
                 (_, Kind::KeyValue) => {}
                 (_, Kind::Val) => {}
                 (_, Kind::Add) => {}
@@ -428,17 +461,23 @@ pub(crate) fn convert_meta_data(
                     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
                     Kind::In => Some(Type::In(Box::new(Type::array()))),
                     Kind::Object => Some(Type::object()),
+                    Kind::Map => Some(Type::Map),
+                    Kind::Set => Some(Type::Set),
                     Kind::Sift | Kind::SiftIn => Some(Type::array()),
-                    Kind::Sum | Kind::SumIn | Kind::Prod | Kind::ProdIn => Some(Type::F64),
                     Kind::Swizzle => Some(Type::F64),
                     Kind::Link | Kind::LinkFor => Some(Type::Link),
                     Kind::Any | Kind::AnyIn | Kind::All | Kind::AllIn => {
                         Some(Type::Secret(Box::new(Type::Bool)))
                     }
-                    Kind::Min | Kind::MinIn | Kind::Max | Kind::MaxIn => {
-                        Some(Type::Secret(Box::new(Type::F64)))
-                    }
-                    Kind::For | Kind::ForN => Some(Type::Void),
+                    Kind::Sum
+                    | Kind::SumIn
+                    | Kind::Prod
+                    | Kind::ProdIn
+                    | Kind::Min
+                    | Kind::MinIn
+                    | Kind::Max
+                    | Kind::MaxIn => Some(Type::Secret(Box::new(Type::F64))),
+                    Kind::For | Kind::ForN | Kind::While => Some(Type::Void),
                     Kind::TyArg | Kind::TyRet => {
                         // Parse extra type information.
                         let convert = Convert::new(&data[i..]);
@@ -460,8 +499,10 @@ pub(crate) fn convert_meta_data(
                     kind,
                     alias: None,
                     names: vec![],
+                    label: None,
                     ty,
                     mutable: false,
+                    par: false,
                     try_flag: false,
                     grab_level: 0,
                     source: Range::empty(0),
@@ -516,6 +557,10 @@ pub(crate) fn convert_meta_data(
                         let i = *parents.last().unwrap();
                         nodes[i].lifetime = Some(val.clone());
                     }
+                    "label" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].label = Some(val.clone());
+                    }
                     "text" => {
                         let i = *parents.last().unwrap();
                         nodes[i].ty = Some(Type::Str);
@@ -570,6 +615,10 @@ pub(crate) fn convert_meta_data(
                         let i = *parents.last().unwrap();
                         nodes[i].mutable = _val;
                     }
+                    "par" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].par = _val;
+                    }
                     "try" | "try_item" => {
                         let i = *parents.last().unwrap();
                         nodes[i].try_flag = _val;
@@ -622,6 +671,26 @@ pub(crate) fn convert_meta_data(
                         let i = *parents.last().unwrap();
                         nodes[i].binops.push(BinOp::AndAlso);
                     }
+                    "&" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].binops.push(BinOp::BitAnd);
+                    }
+                    "|" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].binops.push(BinOp::BitOr);
+                    }
+                    "xor" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].binops.push(BinOp::BitXor);
+                    }
+                    "<<" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].binops.push(BinOp::Shl);
+                    }
+                    ">>" => {
+                        let i = *parents.last().unwrap();
+                        nodes[i].binops.push(BinOp::Shr);
+                    }
                     "+" => {
                         let i = *parents.last().unwrap();
                         nodes[i].binops.push(BinOp::Add);
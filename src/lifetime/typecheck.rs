@@ -390,6 +390,35 @@ pub(crate) fn run(
                                         .inner_type(nodes[decl].ty.as_ref().unwrap_or(&Type::Any)),
                                 );
                             }
+                            Kind::While => {
+                                // Infer type from the option expression of `while let`.
+                                let ch = nodes[decl].find_child_by_kind(nodes, Kind::LetSome);
+                                match ch.and_then(|ch| nodes[ch].ty.as_ref()) {
+                                    Some(Type::Option(ref ty)) => {
+                                        this_ty = Some(nodes[i].inner_type(ty));
+                                    }
+                                    Some(_) => this_ty = Some(Type::Any),
+                                    None => {
+                                        todo.push(i);
+                                        continue 'node;
+                                    }
+                                }
+                            }
+                            kind if kind.is_in_loop() => {
+                                // Infer type from the items of the iterated expression.
+                                let ch = nodes[decl]
+                                    .find_child_by_kind(nodes, Kind::Iter)
+                                    .and_then(|it| nodes[it].children.first().cloned());
+                                match ch.and_then(|ch| nodes[ch].ty.as_ref()) {
+                                    Some(ty) => {
+                                        this_ty = Some(nodes[i].inner_type(&in_item_type(ty)));
+                                    }
+                                    None => {
+                                        todo.push(i);
+                                        continue 'node;
+                                    }
+                                }
+                            }
                             _ => {
                                 if let Some(ref ty) = nodes[decl].ty {
                                     this_ty = Some(nodes[i].inner_type(ty));
@@ -411,6 +440,22 @@ pub(crate) fn run(
                                 // Get type from assignment left expression.
                                 this_ty = Some(ty.clone());
                             }
+                        } else if let Some(assign) = destructure_assign(parent, nodes) {
+                            // Get type from the right expression of destructuring.
+                            let right = nodes[assign].find_child_by_kind(nodes, Kind::Right);
+                            match right.and_then(|right| nodes[right].ty.as_ref()) {
+                                Some(ty) => {
+                                    this_ty = Some(match (nodes[parent].kind, ty) {
+                                        (Kind::Array, &Type::Array(ref ty)) => (**ty).clone(),
+                                        (Kind::Vec4, &Type::Vec4) => Type::F64,
+                                        _ => Type::Any,
+                                    });
+                                }
+                                None => {
+                                    todo.push(i);
+                                    continue 'node;
+                                }
+                            }
                         }
                     }
                 }
@@ -418,6 +463,8 @@ pub(crate) fn run(
                 | Kind::Val
                 | Kind::Expr
                 | Kind::Cond
+                | Kind::LetSome
+                | Kind::Default
                 | Kind::Left
                 | Kind::Right
                 | Kind::ElseIfCond
@@ -518,6 +565,33 @@ pub(crate) fn run(
                         }
                     }
                 }
+                Kind::Loop => {
+                    // Infer type from break values.
+                    let mut ty = Type::Unreachable;
+                    for j in 0..nodes.len() {
+                        if nodes[j].kind != Kind::Break || break_loop(j, nodes) != Some(i) {
+                            continue;
+                        }
+                        match nodes[j].find_child_by_kind(nodes, Kind::Val) {
+                            None => {
+                                if ty == Type::Unreachable {
+                                    ty = Type::Void;
+                                }
+                            }
+                            Some(val) => match nodes[val].ty {
+                                None => {
+                                    todo.push(i);
+                                    continue 'node;
+                                }
+                                Some(ref val_ty) => {
+                                    ty = nodes[i].inner_type(val_ty);
+                                    break;
+                                }
+                            },
+                        }
+                    }
+                    this_ty = Some(ty);
+                }
                 Kind::Sift => {
                     // Infer type from body.
                     let ch = if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Block) {
@@ -608,6 +682,7 @@ pub(crate) fn run(
                             ret: ret.unwrap(),
                             ext: vec![],
                             lazy: crate::LAZY_NO,
+                            defaults: 0,
                         })));
                     }
                 }
@@ -712,10 +787,71 @@ pub(crate) fn run(
                 }
             }
             Kind::If => check_if(i, nodes)?,
+            Kind::Break => check_break(i, nodes)?,
+            Kind::Yield => check_yield(i, nodes)?,
+            Kind::While => {
+                if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Cond) {
+                    if let Some(ref ty) = nodes[ch].ty {
+                        if !Type::Bool.goes_with(ty) {
+                            return Err(nodes[ch].source.wrap(format!(
+                                "Type mismatch (#1040):\nExpected `bool`, found `{}`",
+                                ty.description()
+                            )));
+                        }
+                    }
+                }
+                if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::LetSome) {
+                    if let Some(ref ty) = nodes[ch].ty {
+                        if !Type::option().goes_with(ty) {
+                            return Err(nodes[ch].source.wrap(format!(
+                                "Type mismatch (#1050):\nExpected `opt`, found `{}`",
+                                ty.description()
+                            )));
+                        }
+                    }
+                }
+            }
+            Kind::Arg => {
+                if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Default) {
+                    if let (Some(ref arg_ty), Some(ref ty)) = (&nodes[i].ty, &nodes[ch].ty) {
+                        if !arg_ty.goes_with(ty) {
+                            return Err(nodes[ch].source.wrap(format!(
+                                "Type mismatch (#1005):\nExpected `{}`, found `{}`",
+                                arg_ty.description(),
+                                ty.description()
+                            )));
+                        }
+                    }
+                }
+            }
             Kind::Assign => {
                 use crate::ast::AssignOp;
 
                 match nodes[i].op {
+                    Some(AssignOp::Assign) => {
+                        let left = nodes[i].find_child_by_kind(nodes, Kind::Left).unwrap();
+                        let right = nodes[i].find_child_by_kind(nodes, Kind::Right).unwrap();
+                        let shape = match nodes[left].children.first() {
+                            Some(&ch) if destructure_assign(ch, nodes).is_some() => {
+                                match nodes[ch].kind {
+                                    Kind::Array => Type::array(),
+                                    Kind::Object => Type::object(),
+                                    _ => Type::Vec4,
+                                }
+                            }
+                            _ => continue,
+                        };
+                        if let Some(ref right_ty) = nodes[right].ty {
+                            if !shape.goes_with(right_ty) {
+                                return Err(nodes[right].source.wrap(format!(
+                                    "Type mismatch (#1010):\n\
+                                    Can not destructure `{}` into `{}`",
+                                    right_ty.description(),
+                                    shape.description()
+                                )));
+                            }
+                        }
+                    }
                     Some(AssignOp::Add) | Some(AssignOp::Sub) => {
                         let left = nodes[i].find_child_by_kind(nodes, Kind::Left).unwrap();
                         let right = nodes[i].find_child_by_kind(nodes, Kind::Right).unwrap();
@@ -747,6 +883,7 @@ pub(crate) fn run(
                     match nodes[parent].kind {
                         Kind::Fn => match nodes[parent].ty {
                             Some(Type::Void) => &nodes[i].children,
+                            Some(ref ty) if iter_item_type(ty).is_some() => &nodes[i].children,
                             None => continue,
                             _ => &nodes[i].children[0..n - 1],
                         },
@@ -788,6 +925,17 @@ pub(crate) fn run(
     Ok(())
 }
 
+/// Returns the assign node if `n` is the destructuring left expression,
+/// e.g. `[a, b]` in `[a, b] := ...`.
+fn destructure_assign(n: usize, nodes: &[Node]) -> Option<usize> {
+    nodes[n].destructure(nodes)?;
+    let left = nodes[n].parent?;
+    if nodes[left].kind != Kind::Left {
+        return None;
+    }
+    nodes[left].parent
+}
+
 /// Checks all returns recursively in function.
 fn check_fn(
     n: usize,
@@ -810,7 +958,8 @@ fn check_fn(
                 *found_return = true;
             }
             Kind::ReturnVoid => {
-                if !ty.goes_with(&Type::Void) {
+                // Generators stop when returning without a value.
+                if !ty.goes_with(&Type::Void) && iter_item_type(ty).is_none() {
                     return Err(nodes[ch].source.wrap(format!(
                         "Type mismatch (#1300):\nExpected `{}`, found `{}`",
                         ty.description(),
@@ -819,6 +968,9 @@ fn check_fn(
                 }
                 *found_return = true;
             }
+            Kind::Yield => {
+                *found_return = true;
+            }
             Kind::Item => {
                 if nodes[ch].name().as_ref().map(|n| &***n == "return") == Some(true) {
                     if let Some(parent) = nodes[ch].parent {
@@ -851,6 +1003,160 @@ fn check_fn(
     Ok(())
 }
 
+/// Checks that `yield` is used in a generator function with the same item type.
+fn check_yield(n: usize, nodes: &[Node]) -> Result<(), Range<String>> {
+    let mut p = n;
+    let f = loop {
+        p = match nodes[p].parent {
+            None => return Ok(()),
+            Some(p) => p,
+        };
+        match nodes[p].kind {
+            Kind::Closure => {
+                return Err(nodes[n]
+                    .source
+                    .wrap("Can not use `yield` inside a closure".into()))
+            }
+            Kind::Fn => break p,
+            // The runtime can only suspend and resume these expressions.
+            Kind::Expr
+            | Kind::Block
+            | Kind::If
+            | Kind::TrueBlock
+            | Kind::ElseIfBlock
+            | Kind::ElseBlock
+            | Kind::Loop
+            | Kind::While
+            | Kind::For
+            | Kind::ForN
+            | Kind::ForIn => {}
+            _ => {
+                return Err(nodes[n].source.wrap(
+                    "`yield` can only be used in blocks, `if` and `for`, `loop` or `while` loops"
+                        .into(),
+                ))
+            }
+        }
+    };
+    let fn_ty = match nodes[f].ty {
+        None => return Ok(()),
+        Some(ref ty) => ty,
+    };
+    let item_ty = match iter_item_type(fn_ty) {
+        None => {
+            return Err(nodes[n].source.wrap(format!(
+                "Type mismatch (#1275):\n\
+                Expected function returning `iter`, found `{}`",
+                fn_ty.description()
+            )))
+        }
+        Some(ty) => ty,
+    };
+    if let Some(&ch) = nodes[n].children.first() {
+        if let Some(ref ty) = nodes[ch].ty {
+            if !item_ty.goes_with(ty) {
+                return Err(nodes[ch].source.wrap(format!(
+                    "Type mismatch (#1270):\nExpected `{}`, found `{}`",
+                    item_ty.description(),
+                    ty.description()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the type of items received by an in-loop.
+fn in_item_type(ty: &Type) -> Type {
+    match *ty {
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Type::In(ref item_ty) => (**item_ty).clone(),
+        Type::Iter(ref item_ty) => (**item_ty).clone(),
+        _ => Type::Any,
+    }
+}
+
+/// Returns the item type when the type is an iterator.
+fn iter_item_type(ty: &Type) -> Option<&Type> {
+    match *ty {
+        Type::Iter(ref item_ty) => Some(item_ty),
+        _ => None,
+    }
+}
+
+/// Finds the loop that a `break` or `continue` exits.
+fn break_loop(n: usize, nodes: &[Node]) -> Option<usize> {
+    let mut p = n;
+    loop {
+        p = nodes[p].parent?;
+        match nodes[p].kind {
+            Kind::Fn | Kind::Closure => return None,
+            kind if kind.is_loop() => {
+                if nodes[n].label.is_none() || nodes[n].label == nodes[p].label {
+                    return Some(p);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks that the break value matches the type of the loop.
+fn check_break(n: usize, nodes: &[Node]) -> Result<(), Range<String>> {
+    let target = match break_loop(n, nodes) {
+        None => return Ok(()),
+        Some(target) => target,
+    };
+    let val = nodes[n].find_child_by_kind(nodes, Kind::Val);
+    if let Some(val) = val {
+        if nodes[target].kind != Kind::Loop {
+            return Err(nodes[val].source.wrap(
+                "Can not break with value from this loop, expected `loop`".into(),
+            ));
+        }
+        // Only these loops pass on the break value of an outer loop.
+        let mut p = n;
+        while let Some(parent) = nodes[p].parent {
+            if parent == target {
+                break;
+            }
+            match nodes[parent].kind {
+                Kind::Loop | Kind::For | Kind::While => {}
+                kind if kind.is_loop() => {
+                    return Err(nodes[val].source.wrap(
+                        "Can not break with value through this loop, \
+                        expected `loop`, `while` or `for`"
+                            .into(),
+                    ));
+                }
+                _ => {}
+            }
+            p = parent;
+        }
+    }
+    if nodes[target].kind != Kind::Loop {
+        return Ok(());
+    }
+    let loop_ty = match nodes[target].ty {
+        None | Some(Type::Void) | Some(Type::Unreachable) => return Ok(()),
+        Some(ref ty) => ty,
+    };
+    match val {
+        None => Err(nodes[n].source.wrap(format!(
+            "Type mismatch (#1060):\nExpected `{}`, found `void`",
+            loop_ty.description()
+        ))),
+        Some(val) => match nodes[val].ty {
+            Some(ref ty) if !loop_ty.goes_with(ty) => Err(nodes[val].source.wrap(format!(
+                "Type mismatch (#1070):\nExpected `{}`, found `{}`",
+                loop_ty.description(),
+                ty.description()
+            ))),
+            _ => Ok(()),
+        },
+    }
+}
+
 fn check_if(n: usize, nodes: &[Node]) -> Result<(), Range<String>> {
     if let Some(ch) = nodes[n].find_child_by_kind(nodes, Kind::Cond) {
         if let Some(ref cond_ty) = nodes[ch].ty {
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::sync::Arc;
 
@@ -5,38 +6,62 @@ use crate::Variable;
 
 // Do not change this without updating the algorithms!
 const BLOCK_SIZE: usize = 124;
+// The number of bits in a type tag.
+const TY_BITS: usize = 4;
+const TY_MASK: u64 = 0xf;
+const TYS_PER_WORD: usize = 64 / TY_BITS;
 
 const EMPTY: u64 = 0x0;
 const BOOL: u64 = 0x1;
 const F64: u64 = 0x2;
 const STR: u64 = 0x3;
+const OTHER: u64 = 0x4;
 
-/// Stores link memory in chunks of 1024 bytes.
+/// Stores link memory in chunks of 124 values, with a 4 bit type tag for each value.
+///
+/// Values that are `bool`, `f64` or `str` are packed in 8 bytes.
+/// Other values use the `OTHER` type tag and are stored in a side table by position.
 pub struct Block {
     data: [u64; BLOCK_SIZE],
-    tys: [u64; 4],
+    tys: [u64; BLOCK_SIZE.div_ceil(TYS_PER_WORD)],
+    pub(crate) others: BTreeMap<u8, Variable>,
 }
 
 impl Block {
     fn new() -> Block {
         Block {
             data: [0; BLOCK_SIZE],
-            tys: [0; 4],
+            tys: [0; BLOCK_SIZE.div_ceil(TYS_PER_WORD)],
+            others: BTreeMap::new(),
         }
     }
 
+    fn ty(&self, k: usize) -> u64 {
+        let i = k / TYS_PER_WORD;
+        let j = k - i * TYS_PER_WORD;
+        self.tys[i] >> (j * TY_BITS) & TY_MASK
+    }
+
+    fn set_ty(&mut self, k: usize, ty: u64) {
+        let i = k / TYS_PER_WORD;
+        let j = k - i * TYS_PER_WORD;
+        // Reset bits.
+        self.tys[i] &= !(TY_MASK << (j * TY_BITS));
+        // Sets new bits.
+        self.tys[i] |= ty << (j * TY_BITS);
+    }
+
     pub(crate) fn var(&self, ind: u8) -> Variable {
         use std::mem::transmute;
 
         let k = ind as usize;
         assert!(k < BLOCK_SIZE);
-        let i = k / 32;
-        let j = k - i * 32;
-        match self.tys[i] >> (j * 2) & 0x3 {
+        match self.ty(k) {
             EMPTY => panic!("Reading beyond end"),
             BOOL => Variable::bool(self.data[k] != 0),
             F64 => Variable::f64(f64::from_bits(self.data[k])),
             STR => Variable::Str(unsafe { transmute::<&u64, &Arc<String>>(&self.data[k]) }.clone()),
+            OTHER => self.others[&ind].clone(),
             _ => panic!("Invalid type"),
         }
     }
@@ -47,32 +72,30 @@ impl Block {
         let k = pos;
         assert!(k < BLOCK_SIZE);
 
-        let i = k / 32;
-        let j = k - i * 32;
-        match *var {
-            Variable::Bool(val, _) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= BOOL << (j * 2);
-                self.data[k] = val as u64;
+        // Release the value that is overwritten.
+        match self.ty(k) {
+            STR => unsafe { drop(transmute::<usize, Arc<String>>(self.data[k] as usize)) },
+            OTHER => {
+                self.others.remove(&(k as u8));
             }
-            Variable::F64(val, _) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= F64 << (j * 2);
-                self.data[k] = val.to_bits();
+            _ => {}
+        }
+        let (ty, data) = match *var {
+            Variable::Bool(val, _) => (BOOL, val as u64),
+            Variable::F64(val, _) => (F64, val.to_bits()),
+            Variable::Str(ref s) => (STR, unsafe {
+                transmute::<Arc<String>, usize>(s.clone()) as u64
+            }),
+            Variable::Ref(_) | Variable::UnsafeRef(_) | Variable::Return => {
+                panic!("Expected value")
             }
-            Variable::Str(ref s) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= STR << (j * 2);
-                self.data[k] = unsafe { transmute::<Arc<String>, usize>(s.clone()) as u64 };
+            _ => {
+                self.others.insert(k as u8, var.clone());
+                (OTHER, 0)
             }
-            _ => panic!("Expected `str`, `f64`, `bool`"),
-        }
+        };
+        self.set_ty(k, ty);
+        self.data[k] = data;
     }
 }
 
@@ -82,9 +105,7 @@ impl Clone for Block {
 
         let mut data = self.data;
         for k in 0..BLOCK_SIZE {
-            let i = k / 32;
-            let j = k - i * 32;
-            match self.tys[i] >> (j * 2) & 0x3 {
+            match self.ty(k) {
                 EMPTY => break,
                 STR => {
                     // Arc<String>
@@ -100,6 +121,7 @@ impl Clone for Block {
         Block {
             data,
             tys: self.tys,
+            others: self.others.clone(),
         }
     }
 }
@@ -109,9 +131,7 @@ impl Drop for Block {
         use std::mem::transmute;
 
         for k in 0..BLOCK_SIZE {
-            let i = k / 32;
-            let j = k - i * 32;
-            match self.tys[i] >> (j * 2) & 0x3 {
+            match self.ty(k) {
                 EMPTY => break,
                 STR => {
                     // Arc<String>
@@ -253,7 +273,16 @@ impl Link {
         use crate::Variable::*;
 
         match *v {
-            Bool(_, _) | F64(_, _) | Str(_) => {
+            Ref(_) | UnsafeRef(_) | Return => Err("Expected value".into()),
+            Link(ref link) => {
+                for slice in &link.slices {
+                    for i in slice.start..slice.end {
+                        self.push(&slice.block.var(i))?
+                    }
+                }
+                Ok(())
+            }
+            _ => {
                 if !self.slices.is_empty() {
                     let last = self.slices.last_mut().unwrap();
                     if (last.end as usize) < BLOCK_SIZE {
@@ -269,15 +298,6 @@ impl Link {
                 last.end = 1;
                 Ok(())
             }
-            Link(ref link) => {
-                for slice in &link.slices {
-                    for i in slice.start..slice.end {
-                        self.push(&slice.block.var(i))?
-                    }
-                }
-                Ok(())
-            }
-            _ => Err("Expected `bool`, `f64` or `str`".into()),
         }
     }
 }
@@ -6,6 +6,8 @@ pub struct Module {
     pub(crate) functions: Vec<ast::Function>,
     pub(crate) ext_prelude: Vec<FnExternal>,
     pub(crate) register_namespace: Arc<Vec<Arc<String>>>,
+    /// Whether loops record contributions, which is when `contributions` is called.
+    pub(crate) contributions: bool,
 }
 
 impl Default for Module {
@@ -21,6 +23,7 @@ impl Module {
             functions: vec![],
             ext_prelude: vec![],
             register_namespace: Arc::new(vec![]),
+            contributions: false,
         }
     }
 
@@ -47,6 +50,7 @@ impl Module {
         for f in &other.functions {
             self.functions.push(f.clone())
         }
+        self.contributions |= other.contributions;
     }
 
     /// Creates a new module with standard library.
@@ -73,6 +77,7 @@ impl Module {
                     (vec![], vec![Str; 2], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -92,6 +97,7 @@ impl Module {
                     (vec![], vec![Str; 2], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -111,6 +117,7 @@ impl Module {
                     (vec![], vec![Str; 2], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -130,6 +137,7 @@ impl Module {
                     (vec![], vec![Str; 2], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -155,10 +163,13 @@ impl Module {
                     (vec![], vec![Bool; 2], Bool),
                     (vec![], vec![Vec4; 2], Bool),
                     (vec![], vec![Type::object(), Type::object()], Bool),
+                    (vec![], vec![Type::Map, Type::Map], Bool),
+                    (vec![], vec![Type::Set, Type::Set], Bool),
                     (vec![], vec![Type::array(), Type::array()], Bool),
                     (vec![], vec![Type::option(), Type::option()], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -184,10 +195,13 @@ impl Module {
                     (vec![], vec![Bool; 2], Bool),
                     (vec![], vec![Vec4; 2], Bool),
                     (vec![], vec![Type::object(), Type::object()], Bool),
+                    (vec![], vec![Type::Map, Type::Map], Bool),
+                    (vec![], vec![Type::Set, Type::Set], Bool),
                     (vec![], vec![Type::array(), Type::array()], Bool),
                     (vec![], vec![Type::option(), Type::option()], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add(
@@ -206,6 +220,7 @@ impl Module {
                     (vec![], vec![Bool; 2], Bool),
                 ],
                 lazy: LAZY_AND,
+                defaults: 0,
             },
         );
         m.add(
@@ -224,6 +239,7 @@ impl Module {
                     (vec![], vec![Bool; 2], Bool),
                 ],
                 lazy: LAZY_OR,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -234,6 +250,11 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     Type::all_ext(vec![F64, F64], F64),
                     Type::all_ext(vec![Vec4, Vec4], Vec4),
                     Type::all_ext(vec![Vec4, F64], Vec4),
@@ -246,6 +267,7 @@ impl Module {
                     Type::all_ext(vec![Link, Link], Link),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -256,6 +278,11 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     Type::all_ext(vec![F64, F64], F64),
                     Type::all_ext(vec![Vec4, Vec4], Vec4),
                     Type::all_ext(vec![Vec4, F64], Vec4),
@@ -266,6 +293,7 @@ impl Module {
                     Type::all_ext(vec![Bool, Bool], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -276,6 +304,11 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     (vec![], vec![F64, F64], F64),
                     (vec![], vec![Vec4, Vec4], Vec4),
                     (vec![], vec![Vec4, F64], Vec4),
@@ -287,6 +320,7 @@ impl Module {
                     Type::all_ext(vec![Bool, Bool], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -297,12 +331,18 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     (vec![], vec![F64, F64], F64),
                     (vec![], vec![Vec4, Vec4], Vec4),
                     (vec![], vec![Vec4, F64], Vec4),
                     (vec![], vec![F64, Vec4], Vec4),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -313,12 +353,18 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     (vec![], vec![F64, F64], F64),
                     (vec![], vec![Vec4, Vec4], Vec4),
                     (vec![], vec![Vec4, F64], Vec4),
                     (vec![], vec![F64, Vec4], Vec4),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -329,6 +375,11 @@ impl Module {
                 tys: vec![Any; 2],
                 ret: Any,
                 ext: vec![
+                    (
+                        vec![],
+                        vec![Secret(Box::new(F64)), F64],
+                        Secret(Box::new(F64)),
+                    ),
                     (vec![], vec![F64, F64], F64),
                     (vec![], vec![Vec4, Vec4], Vec4),
                     (vec![], vec![Vec4, F64], Vec4),
@@ -336,6 +387,78 @@ impl Module {
                     Type::all_ext(vec![Bool, Bool], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_binop(
+            crate::BIT_AND.clone(),
+            bit_and,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    Type::all_ext(vec![F64, F64], F64),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_binop(
+            crate::BIT_OR.clone(),
+            bit_or,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    Type::all_ext(vec![F64, F64], F64),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_binop(
+            crate::BIT_XOR.clone(),
+            bit_xor,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    Type::all_ext(vec![F64, F64], F64),
+                    Type::all_ext(vec![Bool, Bool], Bool),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_binop(
+            crate::SHL.clone(),
+            shl,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    Type::all_ext(vec![F64, F64], F64),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_binop(
+            crate::SHR.clone(),
+            shr,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    Type::all_ext(vec![F64, F64], F64),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_unop(
@@ -354,6 +477,7 @@ impl Module {
                     (vec![], vec![Bool], Bool),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_unop(
@@ -369,6 +493,7 @@ impl Module {
                     (vec![], vec![Mat4], Mat4),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_binop(
@@ -384,6 +509,7 @@ impl Module {
                     (vec![], vec![F64, Vec4], F64),
                 ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_str("cross", cross, Dfn::nl(vec![Vec4, Vec4], Vec4));
@@ -456,7 +582,73 @@ impl Module {
         m.add_str("sleep", sleep, Dfn::nl(vec![F64], Void));
         #[cfg(feature = "rand")]
         m.add_str("random", random, Dfn::nl(vec![], F64));
+        #[cfg(feature = "rand")]
+        m.add_str("seed", seed, Dfn::nl(vec![F64], Void));
+        #[cfg(feature = "rand")]
+        m.add_str("rng", rng, Dfn::nl(vec![F64], Any));
+        #[cfg(feature = "rand")]
+        m.add_str("random__rng", random__rng, Dfn::nl(vec![Any], F64));
+        #[cfg(feature = "rand")]
+        m.add_str("range", range, Dfn::nl(vec![F64; 2], F64));
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "range__rng_min_max",
+            range__rng_min_max,
+            Dfn::nl(vec![Any, F64, F64], F64),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str("normal", normal, Dfn::nl(vec![F64; 2], F64));
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "normal__rng_mean_std",
+            normal__rng_mean_std,
+            Dfn::nl(vec![Any, F64, F64], F64),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str("shuffle(mut)", shuffle, Dfn::nl(vec![Type::array()], Void));
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "shuffle__rng_array(_,mut)",
+            shuffle__rng_array,
+            Dfn::nl(vec![Any, Type::array()], Void),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "choose",
+            choose,
+            Dfn::nl(vec![Type::array()], Type::Option(Box::new(Any))),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "choose__rng_array",
+            choose__rng_array,
+            Dfn::nl(vec![Any, Type::array()], Type::Option(Box::new(Any))),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "weighted_choice",
+            weighted_choice,
+            Dfn::nl(
+                vec![Type::array(), Type::Array(Box::new(F64))],
+                Type::Option(Box::new(Any)),
+            ),
+        );
+        #[cfg(feature = "rand")]
+        m.add_str(
+            "weighted_choice__rng_array_weights",
+            weighted_choice__rng_array_weights,
+            Dfn::nl(
+                vec![Any, Type::array(), Type::Array(Box::new(F64))],
+                Type::Option(Box::new(Any)),
+            ),
+        );
         m.add_str("tau", tau, Dfn::nl(vec![], F64));
+        m.add_str("idiv", idiv, Dfn::nl(vec![F64; 2], F64));
+        m.add_str("imod", imod, Dfn::nl(vec![F64; 2], F64));
+        m.add_str("gcd", gcd, Dfn::nl(vec![F64; 2], F64));
+        m.add_str("popcount", popcount, Dfn::nl(vec![F64], F64));
+        m.add_str("clz", clz, Dfn::nl(vec![F64], F64));
+        m.add_str("rotate_left", rotate_left, Dfn::nl(vec![F64; 2], F64));
         #[cfg(feature = "stdio")]
         m.add_str("read_line", read_line, Dfn::nl(vec![], Str));
         #[cfg(feature = "stdio")]
@@ -466,13 +658,46 @@ impl Module {
             parse_number,
             Dfn::nl(vec![Str], Option(Box::new(Type::F64))),
         );
+        m.add_str(
+            "parse_number__radix",
+            parse_number__radix,
+            Dfn::nl(vec![Str, F64], Option(Box::new(Type::F64))),
+        );
         m.add_str("trim", trim, Dfn::nl(vec![Str], Str));
         m.add_str("trim_left", trim_left, Dfn::nl(vec![Str], Str));
         m.add_str("trim_right", trim_right, Dfn::nl(vec![Str], Str));
+        m.add_str("replace", replace, Dfn::nl(vec![Str; 3], Str));
+        m.add_str("find", find, Dfn::nl(vec![Str; 2], Type::Option(Box::new(F64))));
+        m.add_str("rfind", rfind, Dfn::nl(vec![Str; 2], Type::Option(Box::new(F64))));
+        m.add_str("substring", substring, Dfn::nl(vec![Str, F64, F64], Str));
+        m.add_str("repeat", repeat, Dfn::nl(vec![Str, F64], Str));
+        m.add_str("pad_left", pad_left, Dfn::nl(vec![Str, F64], Str));
+        m.add_str(
+            "pad_left__text_len_fill",
+            pad_left__text_len_fill,
+            Dfn::nl(vec![Str, F64, Str], Str),
+        );
+        m.add_str("pad_right", pad_right, Dfn::nl(vec![Str, F64], Str));
+        m.add_str(
+            "pad_right__text_len_fill",
+            pad_right__text_len_fill,
+            Dfn::nl(vec![Str, F64, Str], Str),
+        );
+        m.add_str("join", join, Dfn::nl(vec![Type::Array(Box::new(Str)), Str], Str));
+        m.add_str(
+            "split_whitespace",
+            split_whitespace,
+            Dfn::nl(vec![Str], Type::Array(Box::new(Str))),
+        );
+        m.add_str("char_code", char_code, Dfn::nl(vec![Str], F64));
+        m.add_str("from_char_code", from_char_code, Dfn::nl(vec![F64], Str));
+        m.add_str("len_chars", len_chars, Dfn::nl(vec![Str], F64));
         m.add_str("str", _str, Dfn::nl(vec![Any], Str));
         m.add_str("fmt__tab_string", fmt__tab_string, Dfn::nl(vec![F64, Str], Str));
+        m.add_str("fmt__number_spec", fmt__number_spec, Dfn::nl(vec![F64, Str], Str));
         m.add_str("json_string", json_string, Dfn::nl(vec![Str], Str));
         m.add_str("str__color", str__color, Dfn::nl(vec![Vec4], Str));
+        m.add_str("escape_html", escape_html, Dfn::nl(vec![Str], Str));
         m.add_str(
             "srgb_to_linear__color",
             srgb_to_linear__color,
@@ -527,6 +752,50 @@ impl Module {
             load_string__file,
             Dfn::nl(vec![Str], Type::Result(Box::new(Str))),
         );
+        #[cfg(feature = "file")]
+        m.add_str(
+            "read_dir",
+            read_dir,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Type::Array(Box::new(Str))))),
+        );
+        #[cfg(feature = "file")]
+        m.add_str("exists", exists, Dfn::nl(vec![Str], Bool));
+        #[cfg(feature = "file")]
+        m.add_str("is_dir", is_dir, Dfn::nl(vec![Str], Bool));
+        #[cfg(feature = "file")]
+        m.add_str("is_file", is_file, Dfn::nl(vec![Str], Bool));
+        #[cfg(feature = "file")]
+        m.add_str(
+            "create_dir_all",
+            create_dir_all,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Str))),
+        );
+        #[cfg(feature = "file")]
+        m.add_str("remove_file", remove_file, Dfn::nl(vec![Str], Type::Result(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("rename", rename, Dfn::nl(vec![Str; 2], Type::Result(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("copy", copy, Dfn::nl(vec![Str; 2], Type::Result(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("file_size", file_size, Dfn::nl(vec![Str], Type::Result(Box::new(F64))));
+        #[cfg(feature = "file")]
+        m.add_str("modified_time", modified_time, Dfn::nl(vec![Str], Type::Result(Box::new(F64))));
+        #[cfg(feature = "file")]
+        m.add_str(
+            "glob",
+            glob,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Type::Array(Box::new(Str))))),
+        );
+        #[cfg(feature = "file")]
+        m.add_str("join_path", join_path, Dfn::nl(vec![Str; 2], Str));
+        #[cfg(feature = "file")]
+        m.add_str("parent", parent, Dfn::nl(vec![Str], Type::Option(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("file_stem", file_stem, Dfn::nl(vec![Str], Type::Option(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("extension", extension, Dfn::nl(vec![Str], Type::Option(Box::new(Str))));
+        #[cfg(feature = "file")]
+        m.add_str("canonicalize", canonicalize, Dfn::nl(vec![Str], Type::Result(Box::new(Str))));
         m.add_str(
             "load_string__url",
             load_string__url,
@@ -538,6 +807,41 @@ impl Module {
             join__thread,
             Dfn::nl(vec![Type::thread()], Type::Result(Box::new(Any))),
         );
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str(
+            "join__thread_timeout",
+            join__thread_timeout,
+            Dfn::nl(
+                vec![Type::thread(), F64],
+                Type::Option(Box::new(Type::Result(Box::new(Any)))),
+            ),
+        );
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("is_done", is_done, Dfn::nl(vec![Type::thread()], Bool));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("cancel", cancel, Dfn::nl(vec![Type::thread()], Void));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("shared", shared, Dfn::nl(vec![Any], Type::shared()));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("read", read, Dfn::nl(vec![Type::shared()], Any));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str(
+            "lock_update",
+            lock_update,
+            Dfn::nl(
+                vec![
+                    Type::shared(),
+                    Type::Closure(Box::new(Dfn::nl(vec![Any], Any))),
+                ],
+                Void,
+            ),
+        );
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str(
+            "fetch_add",
+            fetch_add,
+            Dfn::nl(vec![Type::Shared(Box::new(F64)), F64], F64),
+        );
         m.add_str(
             "load_data__file",
             load_data__file,
@@ -548,11 +852,117 @@ impl Module {
             load_data__string,
             Dfn::nl(vec![Str], Type::Result(Box::new(Any))),
         );
+        m.add_str(
+            "load_binary__file",
+            load_binary__file,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Any))),
+        );
+        m.add_str(
+            "save__binary_file",
+            save__binary_file,
+            Dfn::nl(vec![Any, Str], Type::Result(Box::new(Str))),
+        );
+        m.add_str(
+            "load_csv__file",
+            load_csv__file,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Type::array()))),
+        );
+        m.add_str(
+            "load_csv__file_options",
+            load_csv__file_options,
+            Dfn::nl(vec![Str, Type::Object], Type::Result(Box::new(Type::array()))),
+        );
+        m.add_str(
+            "load_csv__string",
+            load_csv__string,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Type::array()))),
+        );
+        m.add_str(
+            "load_csv__string_options",
+            load_csv__string_options,
+            Dfn::nl(vec![Str, Type::Object], Type::Result(Box::new(Type::array()))),
+        );
+        #[cfg(feature = "file")]
+        m.add_str(
+            "stream_csv__file",
+            stream_csv__file,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Type::iter()))),
+        );
+        #[cfg(feature = "file")]
+        m.add_str(
+            "stream_csv__file_options",
+            stream_csv__file_options,
+            Dfn::nl(vec![Str, Type::Object], Type::Result(Box::new(Type::iter()))),
+        );
+        m.add_str(
+            "save_csv__rows_file",
+            save_csv__rows_file,
+            Dfn::nl(vec![Type::array(), Str], Type::Result(Box::new(Str))),
+        );
+        m.add_str(
+            "save_csv__rows_file_options",
+            save_csv__rows_file_options,
+            Dfn::nl(
+                vec![Type::array(), Str, Type::Object],
+                Type::Result(Box::new(Str)),
+            ),
+        );
+        m.add_str(
+            "validate__data_schema",
+            validate__data_schema,
+            Dfn::nl(vec![Str, Any], Type::Result(Box::new(Any))),
+        );
+        #[cfg(feature = "json")]
+        m.add_str(
+            "load_json__file",
+            load_json__file,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Any))),
+        );
+        #[cfg(feature = "json")]
+        m.add_str(
+            "load_json__string",
+            load_json__string,
+            Dfn::nl(vec![Str], Type::Result(Box::new(Any))),
+        );
+        #[cfg(feature = "json")]
+        m.add_str("to_json", to_json, Dfn::nl(vec![Any], Type::Result(Box::new(Str))));
+        #[cfg(feature = "json")]
+        m.add_str(
+            "to_json__pretty",
+            to_json__pretty,
+            Dfn::nl(vec![Any, Bool], Type::Result(Box::new(Str))),
+        );
         m.add_str(
             "args_os",
             args_os,
             Dfn::nl(vec![], Type::Array(Box::new(Str))),
         );
+        #[cfg(feature = "process")]
+        m.add_str("env_var", env_var, Dfn::nl(vec![Str], Type::Option(Box::new(Str))));
+        #[cfg(feature = "process")]
+        m.add_str("set_env_var", set_env_var, Dfn::nl(vec![Str; 2], Void));
+        #[cfg(feature = "process")]
+        m.add_str("current_dir", current_dir, Dfn::nl(vec![], Type::Result(Box::new(Str))));
+        #[cfg(feature = "process")]
+        m.add_str("exit", exit, Dfn::nl(vec![F64], Void));
+        #[cfg(feature = "process")]
+        m.add_str(
+            "run_command",
+            run_command,
+            Dfn::nl(
+                vec![Str, Type::Array(Box::new(Str))],
+                Type::Result(Box::new(Type::Object)),
+            ),
+        );
+        #[cfg(all(not(target_family = "wasm"), feature = "threading", feature = "process"))]
+        m.add_str(
+            "spawn_command",
+            spawn_command,
+            Dfn::nl(
+                vec![Str, Type::Array(Box::new(Str))],
+                Type::Result(Box::new(Type::in_ty())),
+            ),
+        );
         m.add_str("now", now, Dfn::nl(vec![], F64));
         m.add_str("is_nan", is_nan, Dfn::nl(vec![F64], Bool));
         #[cfg(feature = "dynload")]
@@ -576,6 +986,40 @@ impl Module {
                 Type::Result(Box::new(Type::Array(Box::new(Type::Object)))),
             ),
         );
+        let template_ty = Type::Result(Box::new(Type::Closure(Box::new(Dfn::nl(
+            vec![Any],
+            Link,
+        )))));
+        m.add_str(
+            "template",
+            template,
+            Dfn::nl(vec![Str], template_ty.clone()),
+        );
+        m.add_str(
+            "template__escape",
+            template__escape,
+            Dfn::nl(
+                vec![Str, Type::Closure(Box::new(Dfn::nl(vec![Str], Str)))],
+                template_ty.clone(),
+            ),
+        );
+        m.add_str(
+            "template__partials",
+            template__partials,
+            Dfn::nl(vec![Str, Object], template_ty.clone()),
+        );
+        m.add_str(
+            "template__escape_partials",
+            template__escape_partials,
+            Dfn::nl(
+                vec![
+                    Str,
+                    Type::Closure(Box::new(Dfn::nl(vec![Str], Str))),
+                    Object,
+                ],
+                template_ty.clone(),
+            ),
+        );
         m.add_str("call", _call, Dfn::nl(vec![Any, Str, Type::array()], Void));
         m.add_str(
             "call_ret",
@@ -603,6 +1047,11 @@ impl Module {
             _where,
             Dfn::nl(vec![Type::Secret(Box::new(F64))], Type::array()),
         );
+        m.add_str(
+            "contributions",
+            contributions,
+            Dfn::nl(vec![Type::Secret(Box::new(F64))], Type::array()),
+        );
         m.add_str(
             "explain_why",
             explain_why,
@@ -628,6 +1077,7 @@ impl Module {
                 ret: Void,
                 ext: vec![],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_str(
@@ -639,6 +1089,7 @@ impl Module {
                 ret: Void,
                 ext: vec![],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_str("push(mut,_)", push, Dfn::nl(vec![Type::array(), Any], Void));
@@ -647,12 +1098,17 @@ impl Module {
             insert,
             Dfn {
                 lts: vec![Lt::Default; 3],
-                tys: vec![Type::array(), F64, Any],
+                tys: vec![Any; 3],
                 ret: Void,
-                ext: vec![],
+                ext: vec![
+                    (vec![], vec![Type::array(), F64, Any], Void),
+                    (vec![], vec![Type::Map, Any, Any], Void),
+                ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
+        m.add_str("insert(mut,_)", insert__set, Dfn::nl(vec![Type::Set, Any], Void));
         m.add_str(
             "pop(mut)",
             pop,
@@ -662,6 +1118,7 @@ impl Module {
                 ret: Any,
                 ext: vec![],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_str(
@@ -669,10 +1126,15 @@ impl Module {
             remove,
             Dfn {
                 lts: vec![Lt::Return, Lt::Default],
-                tys: vec![Type::array(), F64],
+                tys: vec![Any; 2],
                 ret: Any,
-                ext: vec![],
+                ext: vec![
+                    (vec![], vec![Type::array(), F64], Any),
+                    (vec![], vec![Type::Map, Any], Type::option()),
+                    (vec![], vec![Type::Set, Any], Bool),
+                ],
                 lazy: LAZY_NO,
+                defaults: 0,
             },
         );
         m.add_str("reverse(mut)", reverse, Dfn::nl(vec![Type::array()], Void));
@@ -691,6 +1153,7 @@ impl Module {
                 ret: Any,
                 ext: vec![],
                 lazy: LAZY_UNWRAP_OR,
+                defaults: 0,
             },
         );
         m.add_str("unwrap_err", unwrap_err, Dfn::nl(vec![Any], Any));
@@ -707,6 +1170,16 @@ impl Module {
             save__data_file,
             Dfn::nl(vec![Any, Str], Type::Result(Box::new(Str))),
         );
+        m.add_str(
+            "save__data_file_options",
+            save__data_file_options,
+            Dfn::nl(vec![Any, Str, Type::Object], Type::Result(Box::new(Str))),
+        );
+        m.add_str(
+            "fmt__data_options",
+            fmt__data_options,
+            Dfn::nl(vec![Any, Type::Object], Str),
+        );
         m.add_str(
             "json_from_meta_data",
             json_from_meta_data,
@@ -721,7 +1194,79 @@ impl Module {
         m.add_str(
             "keys",
             keys,
-            Dfn::nl(vec![Object], Type::Array(Box::new(Str))),
+            Dfn {
+                lts: vec![Lt::Default],
+                tys: vec![Any],
+                ret: Type::array(),
+                ext: vec![
+                    (vec![], vec![Object], Type::Array(Box::new(Str))),
+                    (vec![], vec![Type::Map], Type::array()),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_str(
+            "values",
+            values,
+            Dfn {
+                lts: vec![Lt::Default],
+                tys: vec![Any],
+                ret: Type::array(),
+                ext: vec![
+                    (vec![], vec![Object], Type::array()),
+                    (vec![], vec![Type::Map], Type::array()),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_str("get", get, Dfn::nl(vec![Type::Map, Any], Type::option()));
+        m.add_str(
+            "contains",
+            contains,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Bool,
+                ext: vec![
+                    (vec![], vec![Type::Map, Any], Bool),
+                    (vec![], vec![Type::Set, Any], Bool),
+                    (vec![], vec![Str, Str], Bool),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_str(
+            "union",
+            union,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    (vec![], vec![Type::Map, Type::Map], Type::Map),
+                    (vec![], vec![Type::Set, Type::Set], Type::Set),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
+        );
+        m.add_str(
+            "intersection",
+            intersection,
+            Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Any; 2],
+                ret: Any,
+                ext: vec![
+                    (vec![], vec![Type::Map, Type::Map], Type::Map),
+                    (vec![], vec![Type::Set, Type::Set], Type::Set),
+                ],
+                lazy: LAZY_NO,
+                defaults: 0,
+            },
         );
         m.add_str(
             "chars",
@@ -732,6 +1277,22 @@ impl Module {
         m.add_str("wait_next", wait_next, Dfn::nl(vec![Type::in_ty()], Any));
         #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         m.add_str("next", next, Dfn::nl(vec![Type::in_ty()], Type::option()));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("channel", channel, Dfn::nl(vec![], Type::array()));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str("send", send, Dfn::nl(vec![Type::out_ty(), Any], Bool));
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str(
+            "select",
+            select,
+            Dfn::nl(vec![Type::Array(Box::new(Type::in_ty()))], Type::option()),
+        );
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        m.add_str(
+            "select__timeout",
+            select__timeout,
+            Dfn::nl(vec![Type::Array(Box::new(Type::in_ty())), F64], Type::option()),
+        );
 
         m.no_ns();
         m
@@ -771,6 +1332,8 @@ impl Module {
                     FnExt::BinOp(ff) => FnIndex::BinOp(FnBinOpRef(ff)),
                     FnExt::UnOp(ff) => FnIndex::UnOp(FnUnOpRef(ff)),
                     FnExt::Void(ff) => FnIndex::Void(FnVoidRef(ff)),
+                    #[cfg(feature = "async")]
+                    FnExt::Async(ff) => FnIndex::Async(crate::FnAsyncRef(ff)),
                 };
             }
         }
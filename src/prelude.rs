@@ -41,6 +41,10 @@ pub struct Dfn {
     pub ext: Vec<(Vec<Arc<String>>, Vec<Type>, Type)>,
     /// Stores lazy invariants.
     pub lazy: &'static [&'static [Lazy]],
+    /// Number of trailing arguments with default values.
+    ///
+    /// These arguments can be left out when calling the function.
+    pub defaults: usize,
 }
 
 impl Dfn {
@@ -52,6 +56,7 @@ impl Dfn {
             ret,
             ext: vec![],
             lazy: crate::LAZY_NO,
+            defaults: 0,
         }
     }
 
@@ -83,6 +88,7 @@ impl Dfn {
             ret: f.ret.clone(),
             ext: vec![],
             lazy: crate::LAZY_NO,
+            defaults: f.args.iter().filter(|arg| arg.default.is_some()).count(),
         }
     }
 
@@ -90,6 +96,31 @@ impl Dfn {
     pub fn returns(&self) -> bool {
         self.ret != Type::Void
     }
+
+    /// Returns the signature of a call that passes the arguments at declared positions,
+    /// where the rest of the arguments use default values.
+    ///
+    /// This is used for loaded functions, which have no extra type information.
+    fn with_positions(&self, positions: &[usize]) -> Dfn {
+        Dfn {
+            lts: positions
+                .iter()
+                .map(|&i| match self.lts[i] {
+                    Lt::Arg(j) => match positions.iter().position(|&k| k == j) {
+                        Some(j) => Lt::Arg(j),
+                        // Default values do not depend on the lifetime of other arguments.
+                        None => Lt::Default,
+                    },
+                    lt => lt,
+                })
+                .collect(),
+            tys: positions.iter().map(|&i| self.tys[i].clone()).collect(),
+            ret: self.ret.clone(),
+            ext: vec![],
+            lazy: crate::LAZY_NO,
+            defaults: 0,
+        }
+    }
 }
 
 /// Stores a prelude, used to load standard intrinsics and type check new modules.
@@ -97,6 +128,8 @@ pub struct Prelude {
     pub(crate) functions: HashMap<Arc<String>, usize>,
     pub(crate) list: Vec<Dfn>,
     pub(crate) namespaces: Vec<(Arc<Vec<Arc<String>>>, Arc<String>)>,
+    /// Stores the calls that leave out arguments with default values for each function.
+    pub(crate) defaults: HashMap<usize, Vec<usize>>,
 }
 
 impl Default for Prelude {
@@ -120,6 +153,7 @@ impl Prelude {
             functions: HashMap::new(),
             list: vec![],
             namespaces: vec![],
+            defaults: HashMap::new(),
         }
     }
 
@@ -129,9 +163,27 @@ impl Prelude {
         for f in &*module.ext_prelude {
             prelude.insert(f.namespace.clone(), f.name.clone(), f.p.clone());
         }
+        let offset = prelude.list.len();
         for f in &module.functions {
             prelude.insert(f.namespace.clone(), f.name.clone(), Dfn::new(f));
         }
+        // Calls that leave out arguments with default values are checked
+        // against the arguments that are passed, unless shadowed by a function.
+        for (i, f) in module.functions.iter().enumerate() {
+            let i = offset + i;
+            let mut calls = vec![];
+            for (name, positions) in f.default_calls() {
+                if prelude.functions.contains_key(&name) {
+                    continue;
+                }
+                calls.push(prelude.list.len());
+                let dfn = prelude.list[i].with_positions(&positions);
+                prelude.insert(f.namespace.clone(), name, dfn);
+            }
+            if !calls.is_empty() {
+                prelude.defaults.insert(i, calls);
+            }
+        }
         prelude
     }
 }
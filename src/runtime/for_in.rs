@@ -1,33 +1,67 @@
 use super::*;
 
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+
+/// Iterates over an in-type, a generator, or over the items of a map or set.
+pub(crate) enum Iter {
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    In(Arc<Mutex<Receiver<Variable>>>),
+    Gen(Arc<Mutex<Generator>>),
+    Items(std::vec::IntoIter<Variable>),
+}
+
+impl Iter {
+    /// Returns the next value, or `None` if there are no more values.
+    fn next(&mut self) -> Result<Option<Variable>, String> {
+        match *self {
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Iter::In(ref val) => match val.lock() {
+                Ok(x) => Ok(x.try_recv().ok()),
+                Err(err) => Err(format!("Can not lock In mutex:\n{}", err)),
+            },
+            Iter::Gen(ref gen) => match gen.lock() {
+                Ok(mut x) => x.resume(),
+                Err(err) => Err(format!("Can not lock Iter mutex:\n{}", err)),
+            },
+            Iter::Items(ref mut items) => Ok(items.next()),
+        }
+    }
+}
+
 macro_rules! iter(
     ($rt:ident, $for_in_expr:ident) => {{
         let iter = match $rt.expression(&$for_in_expr.iter, Side::Right)? {
             (x, Flow::Return) => { return Ok((x, Flow::Return)); }
             (Some(x), Flow::Continue) => x,
             _ => return Err($rt.module.error($for_in_expr.iter.source_range(),
-                &format!("{}\nExpected in-type from for iter",
+                &format!("{}\nExpected in-type, iter, map or set from for iter",
                     $rt.stack_trace()), $rt))
         };
         match $rt.resolve(&iter) {
-            &Variable::In(ref val) => val.clone(),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            &Variable::In(ref val) => Iter::In(val.clone()),
+            &Variable::Iter(ref gen) => Iter::Gen(gen.clone()),
+            &Variable::Map(ref map) => Iter::Items(
+                map.keys().map(|k| k.to_variable()).collect::<Vec<_>>().into_iter()
+            ),
+            &Variable::Set(ref set) => Iter::Items(
+                set.iter().map(|k| k.to_variable()).collect::<Vec<_>>().into_iter()
+            ),
             x => return Err($rt.module.error($for_in_expr.iter.source_range(),
-                            &$rt.expected(x, "in"), $rt))
+                            &$rt.expected(x, "in, iter, map or set"), $rt))
         }
     }};
 );
 
+/// Gets the first value, or returns the value of the loop when there are no values.
 macro_rules! iter_val(
-    ($iter:ident, $rt:ident, $for_in_expr:ident) => {
-        match $iter.lock() {
-            Ok(x) => match x.try_recv() {
-                Ok(x) => x,
-                Err(_) => return Ok((None, Flow::Continue)),
-            },
-            Err(err) => {
-                return Err($rt.module.error($for_in_expr.source_range,
-                &format!("Can not lock In mutex:\n{}", err.to_string()), $rt));
-            }
+    ($iter:ident, $rt:ident, $for_in_expr:ident, $empty:expr) => {
+        match $iter.next() {
+            Ok(Some(x)) => x,
+            Ok(None) => return Ok(($empty, Flow::Continue)),
+            Err(err) => return Err($rt.module.error($for_in_expr.source_range, &err, $rt)),
         }
     };
 );
@@ -63,18 +97,14 @@ macro_rules! continue_(
 );
 
 macro_rules! iter_val_inc(
-    ($iter:ident, $rt:ident, $for_in_expr:ident) => {
-        match $iter.lock() {
-            Ok(x) => match x.try_recv() {
-                Ok(x) => x,
-                Err(_) => break,
-            },
-            Err(err) => {
-                return Err($rt.module.error($for_in_expr.source_range,
-                &format!("Can not lock In mutex:\n{}", err.to_string()), $rt));
-            }
+    ($iter:ident, $rt:ident, $for_in_expr:ident) => {{
+        $rt.check_cancel($for_in_expr.source_range)?;
+        match $iter.next() {
+            Ok(Some(x)) => x,
+            Ok(None) => break,
+            Err(err) => return Err($rt.module.error($for_in_expr.source_range, &err, $rt)),
         }
-    };
+    }};
 );
 
 impl Runtime {
@@ -82,25 +112,37 @@ impl Runtime {
         &mut self,
         for_in_expr: &ast::ForIn,
     ) -> Result<(Option<Variable>, Flow), String> {
-        let prev_st = self.stack.len();
-        let prev_lc = self.local_stack.len();
-
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
-
-        // Initialize counter.
-        self.local_stack
-            .push((for_in_expr.name.clone(), self.stack.len()));
-        self.stack.push(iter_val);
-
-        let st = self.stack.len();
-        let lc = self.local_stack.len();
+        let (stack, mut iter) = match self.resume_frame() {
+            None => {
+                let prev_st = self.stack.len();
+                let prev_lc = self.local_stack.len();
+
+                let mut iter = iter!(self, for_in_expr);
+                let iter_val = iter_val!(iter, self, for_in_expr, None);
+
+                // Initialize counter.
+                self.local_stack
+                    .push((for_in_expr.name.clone(), self.stack.len()));
+                self.stack.push(iter_val);
+
+                let st = self.stack.len();
+                let lc = self.local_stack.len();
+                (LoopStack { prev_st, prev_lc, st, lc }, iter)
+            }
+            Some(Resume::ForIn(stack, iter)) => (stack, iter),
+            Some(_) => return self.err(for_in_expr.source_range, WRONG_RESUME),
+        };
+        let LoopStack { prev_st, prev_lc, st, lc } = stack;
         let mut flow = Flow::Continue;
         loop {
             match self.block(&for_in_expr.block)? {
                 (x, Flow::Return) => {
                     return Ok((x, Flow::Return));
                 }
+                (x, Flow::Yield) => {
+                    self.suspend_frame(Resume::ForIn(stack, iter));
+                    return Ok((x, Flow::Yield));
+                }
                 (_, Flow::Continue) => {}
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
@@ -122,10 +164,11 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let iter_val = iter_val!(iter, self, for_in_expr, Some(Variable::f64(0.0)));
 
         let mut sum = 0.0;
+        let mut sec = if self.module.contributions { Some(vec![]) } else { None };
 
         // Initialize counter.
         self.local_stack
@@ -139,7 +182,13 @@ impl Runtime {
             match self.block(&for_in_expr.block)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => sum += val,
+                        &Variable::F64(val, ref val_sec) => {
+                            sum += val;
+                            if let Some(ref mut sec) = sec {
+                                let ind = self.stack[st - 1].clone();
+                                for_n::contribute(sec, ind, val, val_sec.as_deref());
+                            }
+                        }
                         x => {
                             return Err(self.module.error(
                                 for_in_expr.block.source_range,
@@ -155,6 +204,7 @@ impl Runtime {
                 (_, Flow::Continue) => {}
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -163,7 +213,7 @@ impl Runtime {
         }
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(sum)), flow))
+        Ok((Some(Variable::F64(sum, sec.map(Box::new))), flow))
     }
 
     pub(crate) fn prod_in_expr(
@@ -173,10 +223,11 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let iter_val = iter_val!(iter, self, for_in_expr, Some(Variable::f64(1.0)));
 
         let mut prod = 1.0;
+        let mut sec = if self.module.contributions { Some(vec![]) } else { None };
 
         // Initialize counter.
         self.local_stack
@@ -190,7 +241,13 @@ impl Runtime {
             match self.block(&for_in_expr.block)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => prod *= val,
+                        &Variable::F64(val, ref val_sec) => {
+                            prod *= val;
+                            if let Some(ref mut sec) = sec {
+                                let ind = self.stack[st - 1].clone();
+                                for_n::contribute(sec, ind, val, val_sec.as_deref());
+                            }
+                        }
                         x => {
                             return Err(self.module.error(
                                 for_in_expr.block.source_range,
@@ -206,6 +263,7 @@ impl Runtime {
                 (_, Flow::Continue) => {}
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -214,7 +272,7 @@ impl Runtime {
         }
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(prod)), flow))
+        Ok((Some(Variable::F64(prod, sec.map(Box::new))), flow))
     }
 
     pub(crate) fn min_in_expr(
@@ -224,8 +282,9 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let empty = Some(Variable::f64(::std::f64::NAN));
+        let iter_val = iter_val!(iter, self, for_in_expr, empty);
 
         let mut min = ::std::f64::NAN;
         let mut sec = None;
@@ -274,6 +333,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -292,8 +352,9 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let empty = Some(Variable::f64(::std::f64::NAN));
+        let iter_val = iter_val!(iter, self, for_in_expr, empty);
 
         let mut max = ::std::f64::NAN;
         let mut sec = None;
@@ -342,6 +403,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -360,8 +422,8 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let iter_val = iter_val!(iter, self, for_in_expr, Some(Variable::bool(false)));
 
         let mut any = false;
         let mut sec = None;
@@ -412,6 +474,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -430,8 +493,8 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let iter_val = iter_val!(iter, self, for_in_expr, Some(Variable::bool(true)));
 
         let mut all = true;
         let mut sec = None;
@@ -482,6 +545,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -507,8 +571,8 @@ impl Runtime {
             let prev_st = rt.stack.len();
             let prev_lc = rt.local_stack.len();
 
-            let iter = iter!(rt, for_in_expr);
-            let iter_val = iter_val!(iter, rt, for_in_expr);
+            let mut iter = iter!(rt, for_in_expr);
+            let iter_val = iter_val!(iter, rt, for_in_expr, None);
 
             // Initialize counter.
             rt.local_stack
@@ -525,16 +589,19 @@ impl Runtime {
                         // Evaluate link items directly.
                         'inner: for item in &link.items {
                             match rt.expression(item, Side::Right)? {
-                                (Some(ref x), Flow::Continue) => match res.push(rt.resolve(x)) {
-                                    Err(err) => {
-                                        return Err(rt.module.error(
-                                            for_in_expr.source_range,
-                                            &format!("{}\n{}", rt.stack_trace(), err),
-                                            rt,
-                                        ))
+                                (Some(ref x), Flow::Continue) => {
+                                    let v = rt.resolve(x).deep_clone(&rt.stack);
+                                    match res.push(&v) {
+                                        Err(err) => {
+                                            return Err(rt.module.error(
+                                                for_in_expr.source_range,
+                                                &format!("{}\n{}", rt.stack_trace(), err),
+                                                rt,
+                                            ))
+                                        }
+                                        Ok(()) => {}
                                     }
-                                    Ok(()) => {}
-                                },
+                                }
                                 (x, Flow::Return) => {
                                     return Ok((x, Flow::Return));
                                 }
@@ -570,6 +637,9 @@ impl Runtime {
                                         break 'inner;
                                     }
                                 },
+                                (_, Flow::Yield) => {
+                                    return rt.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED)
+                                }
                             }
                         }
                     }
@@ -635,8 +705,9 @@ impl Runtime {
         let prev_lc = self.local_stack.len();
         let mut res: Vec<Variable> = vec![];
 
-        let iter = iter!(self, for_in_expr);
-        let iter_val = iter_val!(iter, self, for_in_expr);
+        let mut iter = iter!(self, for_in_expr);
+        let empty = Some(Variable::Array(Arc::new(vec![])));
+        let iter_val = iter_val!(iter, self, for_in_expr, empty);
 
         // Initialize counter.
         self.local_stack
@@ -648,6 +719,9 @@ impl Runtime {
         let mut flow = Flow::Continue;
         loop {
             match self.block(&for_in_expr.block)? {
+                (Some(x), Flow::Continue) if self.module.contributions => {
+                    res.push(for_n::sift_item(x, self.stack[st - 1].clone()))
+                }
                 (Some(x), Flow::Continue) => res.push(x),
                 (x, Flow::Return) => {
                     return Ok((x, Flow::Return));
@@ -661,6 +735,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_in_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_in_expr, flow),
+                (_, Flow::Yield) => return self.err(for_in_expr.source_range, YIELD_NOT_SUPPORTED),
             }
 
             self.stack[st - 1] = iter_val_inc!(iter, self, for_in_expr);
@@ -95,6 +95,7 @@ macro_rules! continue_(
 
 macro_rules! inc(
     ($rt:ident, $for_n_expr:ident, $st:ident) => {{
+        $rt.check_cancel($for_n_expr.source_range)?;
         let error = if let Variable::F64(ref mut val, _) = $rt.stack[$st - 1] {
             *val += 1.0;
             false
@@ -106,17 +107,273 @@ macro_rules! inc(
     }};
 );
 
+/// Adds the contribution from an iteration of a `sum` or `prod` loop.
+///
+/// Each contribution is stored as `[i, value]`, where `i` is the index or the item of an in-loop.
+/// Contributions from a nested `sum` or `prod` loop are prefixed with the index.
+pub(crate) fn contribute(
+    sec: &mut Vec<Variable>,
+    ind: Variable,
+    val: f64,
+    val_sec: Option<&Vec<Variable>>,
+) {
+    if let Some(arr) = val_sec {
+        if arr.iter().all(|x| matches!(*x, Variable::Array(_))) {
+            for x in arr.iter() {
+                if let Variable::Array(ref x) = *x {
+                    let mut item = Vec::with_capacity(x.len() + 1);
+                    item.push(ind.clone());
+                    item.extend(x.iter().cloned());
+                    sec.push(Variable::Array(Arc::new(item)));
+                }
+            }
+            return;
+        }
+    }
+    sec.push(Variable::Array(Arc::new(vec![ind, Variable::f64(val)])));
+}
+
+/// Records the index of an item in a `sift` loop.
+///
+/// The index, or the item of an in-loop, is stored in the secret of `bool` and `f64` items.
+pub(crate) fn sift_item(x: Variable, ind: Variable) -> Variable {
+    let index = |val_sec: Option<Box<Vec<Variable>>>| match val_sec {
+        None => Some(Box::new(vec![ind])),
+        Some(mut arr) => {
+            arr.push(ind);
+            Some(arr)
+        }
+    };
+    match x {
+        Variable::F64(val, val_sec) => Variable::F64(val, index(val_sec)),
+        Variable::Bool(val, val_sec) => Variable::Bool(val, index(val_sec)),
+        x => x,
+    }
+}
+
+/// The reduction used by a `par` loop.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Par {
+    Sum,
+    Prod,
+    Min,
+    Max,
+    Sift,
+    Any,
+    All,
+}
+
+/// The values of a range of iterations in a `par` loop, stored with the index.
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+type ParValues = Vec<(f64, Option<Variable>)>;
+
 impl Runtime {
-    pub(crate) fn for_n_expr(
+    /// Evaluates a `par` loop on the shared pool of worker threads.
+    ///
+    /// Each job evaluates a contiguous range of indices on a copy of the stack.
+    /// The values are reduced in index order, so the result does not depend on scheduling.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub(crate) fn par_n_expr(
         &mut self,
         for_n_expr: &ast::ForN,
+        par: Par,
     ) -> Result<(Option<Variable>, Flow), String> {
-        let prev_st = self.stack.len();
-        let prev_lc = self.local_stack.len();
+        use super::pool;
 
         let start = start!(self, for_n_expr);
         let end = end!(self, for_n_expr);
 
+        let n = if end > start { (end - start).ceil() as usize } else { 0 };
+        let threads = pool::workers().min(n).max(1);
+        // Any and all stop at the first value that decides the result.
+        let stop = match par {
+            Par::Any => Some(true),
+            Par::All => Some(false),
+            _ => None,
+        };
+        // Each iteration gets a generator from this seed and its index,
+        // such that random numbers do not depend on the number of threads.
+        #[cfg(feature = "rand")]
+        let seed: u64 = {
+            use rand::Rng;
+
+            self.rng.gen()
+        };
+        let mut jobs: Vec<Box<dyn FnOnce() -> Result<ParValues, String> + Send>> =
+            Vec::with_capacity(threads);
+        for k in 0..threads {
+            let range = k * n / threads..(k + 1) * n / threads;
+            // Replaced for each iteration.
+            #[cfg(feature = "rand")]
+            let rng = {
+                use rand::SeedableRng;
+
+                rand::rngs::StdRng::seed_from_u64(seed)
+            };
+            let last_call = self.call_stack.last().expect(CSIE);
+            let new_rt = Runtime {
+                module: self.module.clone(),
+                stack: self.stack.clone(),
+                local_stack: self.local_stack.clone(),
+                current_stack: self.current_stack.clone(),
+                #[cfg(feature = "dynload")]
+                module_resolver: self.module_resolver,
+                call_stack: vec![Call {
+                    fn_name: last_call.fn_name.clone(),
+                    index: last_call.index,
+                    file: last_call.file.clone(),
+                    stack_len: last_call.stack_len,
+                    local_len: last_call.local_len,
+                    current_len: last_call.current_len,
+                }],
+                #[cfg(feature = "rand")]
+                rng,
+                arg_err_index: Cell::new(None),
+                #[cfg(feature = "async")]
+                tokio_runtime: self.tokio_runtime.clone(),
+                #[cfg(feature = "process")]
+                exit_code: self.exit_code.clone(),
+                #[cfg(feature = "process")]
+                env_vars: self.env_vars.clone(),
+                thread: self.thread.clone(),
+                resume: None,
+            };
+            let for_n_expr = for_n_expr.clone();
+            jobs.push(Box::new(move || {
+                let mut new_rt = new_rt;
+                new_rt.par_n_range(
+                    &for_n_expr,
+                    start,
+                    range,
+                    stop,
+                    #[cfg(feature = "rand")]
+                    seed,
+                )
+            }));
+        }
+        let mut chunks = Vec::with_capacity(threads);
+        for chunk in pool::run(jobs) {
+            chunks.push(match chunk {
+                Some(x) => x,
+                None => {
+                    return self.err(for_n_expr.source_range, "Thread did not exit successfully")
+                }
+            });
+        }
+
+        let expected = |rt: &Runtime, x: Option<Variable>, ty: &str| -> String {
+            match x {
+                Some(ref x) => rt.module.error(
+                    for_n_expr.block.source_range,
+                    &rt.expected(x, ty),
+                    rt,
+                ),
+                None => rt.module.error(
+                    for_n_expr.block.source_range,
+                    &format!("Expected `{}`", ty),
+                    rt,
+                ),
+            }
+        };
+        let mut num = match par {
+            Par::Prod => 1.0,
+            Par::Min | Par::Max => ::std::f64::NAN,
+            _ => 0.0,
+        };
+        let mut found = false;
+        let mut sec = None;
+        let mut contributions = if self.module.contributions {
+            Some(vec![])
+        } else {
+            None
+        };
+        let mut res: Vec<Variable> = vec![];
+        'chunks: for chunk in chunks {
+            for (ind, x) in chunk? {
+                match par {
+                    Par::Sift => match x {
+                        Some(x) if self.module.contributions => res.push(sift_item(x, Variable::f64(ind))),
+                        Some(x) => res.push(x),
+                        None => return Err(expected(self, None, "variable")),
+                    },
+                    Par::Any | Par::All => match x {
+                        Some(Variable::Bool(val, ref val_sec)) => {
+                            if Some(val) == stop {
+                                found = true;
+                                sec = match *val_sec {
+                                    None => Some(Box::new(vec![Variable::f64(ind)])),
+                                    Some(ref arr) => {
+                                        let mut arr = arr.clone();
+                                        arr.push(Variable::f64(ind));
+                                        Some(arr)
+                                    }
+                                };
+                                break 'chunks;
+                            }
+                        }
+                        x => return Err(expected(self, x, "boolean")),
+                    },
+                    Par::Sum | Par::Prod | Par::Min | Par::Max => match x {
+                        Some(Variable::F64(val, ref val_sec)) => match par {
+                            Par::Sum => {
+                                num += val;
+                                if let Some(ref mut contributions) = contributions {
+                                    contribute(contributions, Variable::f64(ind), val, val_sec.as_deref());
+                                }
+                            }
+                            Par::Prod => {
+                                num *= val;
+                                if let Some(ref mut contributions) = contributions {
+                                    contribute(contributions, Variable::f64(ind), val, val_sec.as_deref());
+                                }
+                            }
+                            _ => {
+                                if num.is_nan()
+                                    || (par == Par::Min && num > val)
+                                    || (par == Par::Max && num < val)
+                                {
+                                    num = val;
+                                    sec = match *val_sec {
+                                        None => Some(Box::new(vec![Variable::f64(ind)])),
+                                        Some(ref arr) => {
+                                            let mut arr = arr.clone();
+                                            arr.push(Variable::f64(ind));
+                                            Some(arr)
+                                        }
+                                    };
+                                }
+                            }
+                        },
+                        x => return Err(expected(self, x, "number")),
+                    },
+                }
+            }
+        }
+        Ok((
+            Some(match par {
+                Par::Sum | Par::Prod => Variable::F64(num, contributions.map(Box::new)),
+                Par::Min | Par::Max => Variable::F64(num, sec),
+                Par::Sift => Variable::Array(Arc::new(res)),
+                Par::Any => Variable::Bool(found, sec),
+                Par::All => Variable::Bool(!found, sec),
+            }),
+            Flow::Continue,
+        ))
+    }
+
+    /// Evaluates a range of iterations of a `par` loop.
+    ///
+    /// Stops after the first boolean value equal to `stop`.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    fn par_n_range(
+        &mut self,
+        for_n_expr: &ast::ForN,
+        start: f64,
+        range: std::ops::Range<usize>,
+        stop: Option<bool>,
+        #[cfg(feature = "rand")] seed: u64,
+    ) -> Result<ParValues, String> {
         // Initialize counter.
         self.local_stack
             .push((for_n_expr.name.clone(), self.stack.len()));
@@ -124,13 +381,98 @@ impl Runtime {
 
         let st = self.stack.len();
         let lc = self.local_stack.len();
+        let mut res = vec![];
+        for k in range {
+            self.check_cancel(for_n_expr.source_range)?;
+            let ind = start + k as f64;
+            self.stack[st - 1] = Variable::f64(ind);
+            #[cfg(feature = "rand")]
+            {
+                use rand::SeedableRng;
+
+                self.rng = rand::rngs::StdRng::seed_from_u64(seed.wrapping_add(k as u64));
+            }
+            match self.block(&for_n_expr.block)? {
+                (x, Flow::Continue) => {
+                    let x = x.map(|x| self.resolve(&x).deep_clone(&self.stack));
+                    let done = match (stop, &x) {
+                        (Some(stop), &Some(Variable::Bool(val, _))) => val == stop,
+                        _ => false,
+                    };
+                    res.push((ind, x));
+                    if done {
+                        break;
+                    }
+                }
+                (_, Flow::ContinueLoop(ref x)) if x.is_none() || *x == for_n_expr.label => {}
+                (_, Flow::Return) => {
+                    return Err(self.module.error(
+                        for_n_expr.source_range,
+                        &format!("{}\nCan not return from `par` loop", self.stack_trace()),
+                        self,
+                    ));
+                }
+                (_, Flow::Break(_)) | (_, Flow::ContinueLoop(_)) => {
+                    return Err(self.module.error(
+                        for_n_expr.source_range,
+                        &format!("{}\nCan not break out of `par` loop", self.stack_trace()),
+                        self,
+                    ));
+                }
+                (_, Flow::Yield) => {
+                    return Err(self.module.error(
+                        for_n_expr.source_range,
+                        &format!("{}\n{}", self.stack_trace(), YIELD_NOT_SUPPORTED),
+                        self,
+                    ));
+                }
+            }
+            self.stack.truncate(st);
+            self.local_stack.truncate(lc);
+        }
+        Ok(res)
+    }
+
+    pub(crate) fn for_n_expr(
+        &mut self,
+        for_n_expr: &ast::ForN,
+    ) -> Result<(Option<Variable>, Flow), String> {
+        let (stack, end, mut resumed) = match self.resume_frame() {
+            None => {
+                let prev_st = self.stack.len();
+                let prev_lc = self.local_stack.len();
+
+                let start = start!(self, for_n_expr);
+                let end = end!(self, for_n_expr);
+
+                // Initialize counter.
+                self.local_stack
+                    .push((for_n_expr.name.clone(), self.stack.len()));
+                self.stack.push(Variable::f64(start));
+
+                let st = self.stack.len();
+                let lc = self.local_stack.len();
+                (LoopStack { prev_st, prev_lc, st, lc }, end, false)
+            }
+            Some(Resume::ForN(stack, end)) => (stack, end, true),
+            Some(_) => return self.err(for_n_expr.source_range, WRONG_RESUME),
+        };
+        let LoopStack { prev_st, prev_lc, st, lc } = stack;
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end);
+            // Continue in the block when resuming a generator.
+            if !resumed {
+                cond!(self, for_n_expr, st, end);
+            }
+            resumed = false;
             match self.block(&for_n_expr.block)? {
                 (x, Flow::Return) => {
                     return Ok((x, Flow::Return));
                 }
+                (x, Flow::Yield) => {
+                    self.suspend_frame(Resume::ForN(stack, end));
+                    return Ok((x, Flow::Yield));
+                }
                 (_, Flow::Continue) => {}
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
@@ -151,6 +493,7 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
         let mut sum = 0.0;
+        let mut sec = if self.module.contributions { Some(vec![]) } else { None };
 
         let start = start!(self, for_n_expr);
         let end = end!(self, for_n_expr);
@@ -164,11 +507,16 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end);
+            let ind = cond!(self, for_n_expr, st, end);
             match self.block(&for_n_expr.block)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => sum += val,
+                        &Variable::F64(val, ref val_sec) => {
+                            sum += val;
+                            if let Some(ref mut sec) = sec {
+                                contribute(sec, Variable::f64(ind), val, val_sec.as_deref());
+                            }
+                        }
                         x => {
                             return Err(self.module.error(
                                 for_n_expr.block.source_range,
@@ -190,6 +538,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -197,7 +546,7 @@ impl Runtime {
         }
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(sum)), flow))
+        Ok((Some(Variable::F64(sum, sec.map(Box::new))), flow))
     }
 
     pub(crate) fn prod_n_expr(
@@ -207,6 +556,7 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
         let mut prod = 1.0;
+        let mut sec = if self.module.contributions { Some(vec![]) } else { None };
 
         let start = start!(self, for_n_expr);
         let end = end!(self, for_n_expr);
@@ -220,11 +570,16 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end);
+            let ind = cond!(self, for_n_expr, st, end);
             match self.block(&for_n_expr.block)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => prod *= val,
+                        &Variable::F64(val, ref val_sec) => {
+                            prod *= val;
+                            if let Some(ref mut sec) = sec {
+                                contribute(sec, Variable::f64(ind), val, val_sec.as_deref());
+                            }
+                        }
                         x => {
                             return Err(self.module.error(
                                 for_n_expr.block.source_range,
@@ -246,6 +601,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -253,7 +609,7 @@ impl Runtime {
         }
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(prod)), flow))
+        Ok((Some(Variable::F64(prod, sec.map(Box::new))), flow))
     }
 
     pub(crate) fn min_n_expr(
@@ -314,6 +670,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -383,6 +740,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -453,6 +811,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -523,6 +882,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -567,16 +927,19 @@ impl Runtime {
                         // Evaluate link items directly.
                         'inner: for item in &link.items {
                             match rt.expression(item, Side::Right)? {
-                                (Some(ref x), Flow::Continue) => match res.push(rt.resolve(x)) {
-                                    Err(err) => {
-                                        return Err(rt.module.error(
-                                            for_n_expr.source_range,
-                                            &format!("{}\n{}", rt.stack_trace(), err),
-                                            rt,
-                                        ))
+                                (Some(ref x), Flow::Continue) => {
+                                    let v = rt.resolve(x).deep_clone(&rt.stack);
+                                    match res.push(&v) {
+                                        Err(err) => {
+                                            return Err(rt.module.error(
+                                                for_n_expr.source_range,
+                                                &format!("{}\n{}", rt.stack_trace(), err),
+                                                rt,
+                                            ))
+                                        }
+                                        Ok(()) => {}
                                     }
-                                    Ok(()) => {}
-                                },
+                                }
                                 (x, Flow::Return) => {
                                     return Ok((x, Flow::Return));
                                 }
@@ -600,6 +963,9 @@ impl Runtime {
                                         break 'inner;
                                     }
                                 },
+                                (_, Flow::Yield) => {
+                                    return rt.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED)
+                                }
                             }
                         }
                     }
@@ -665,8 +1031,11 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end);
+            let ind = cond!(self, for_n_expr, st, end);
             match self.block(&for_n_expr.block)? {
+                (Some(x), Flow::Continue) if self.module.contributions => {
+                    res.push(sift_item(x, Variable::f64(ind)))
+                }
                 (Some(x), Flow::Continue) => res.push(x),
                 (x, Flow::Return) => {
                     return Ok((x, Flow::Return));
@@ -680,6 +1049,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -740,6 +1110,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
@@ -800,6 +1171,7 @@ impl Runtime {
                 }
                 (_, Flow::Break(x)) => break_!(x, for_n_expr, flow),
                 (_, Flow::ContinueLoop(x)) => continue_!(x, for_n_expr, flow),
+                (_, Flow::Yield) => return self.err(for_n_expr.source_range, YIELD_NOT_SUPPORTED),
             }
             inc!(self, for_n_expr, st);
             self.stack.truncate(st);
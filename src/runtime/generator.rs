@@ -0,0 +1,242 @@
+//! Generator functions.
+//!
+//! A generator has its own runtime with the stack of the generator function,
+//! but runs on the thread of the consumer when the next value is requested.
+//!
+//! When the generator yields, the expressions on the path to `yield` keep their
+//! part of the stack and save where they were in a `Resume` frame.
+//! The next time the generator is resumed, each expression continues
+//! from the saved frame instead of starting over.
+
+use super::*;
+
+use std::sync::Mutex;
+
+/// Each expression on the path to `yield` pops the frame it pushed when suspended.
+pub(crate) const WRONG_RESUME: &str = "Resumed generator at the wrong expression";
+/// The type checker only allows `yield` in blocks, `if` and loops that can be resumed.
+pub(crate) const YIELD_NOT_SUPPORTED: &str = "Can not yield from this expression";
+
+/// Stack lengths of a loop, before and after initializing the loop.
+#[derive(Clone, Copy)]
+pub(crate) struct LoopStack {
+    pub prev_st: usize,
+    pub prev_lc: usize,
+    pub st: usize,
+    pub lc: usize,
+}
+
+/// Stores where an expression was suspended by `yield`.
+///
+/// Frames are pushed from the `yield` and outwards,
+/// such that the outermost expression pops its frame first when resuming.
+pub(crate) enum Resume {
+    /// The `yield` expression.
+    Yield,
+    /// Index of the suspended expression in a block, with the stack lengths of the block.
+    Block {
+        index: usize,
+        st: usize,
+        lc: usize,
+        cu: usize,
+    },
+    /// Index of the branch of an if expression.
+    If(usize),
+    /// For loop, also used by `loop` and `while`.
+    For(LoopStack),
+    /// While-let loop.
+    WhileLet(LoopStack),
+    /// For-n loop, with the end of the range.
+    ForN(LoopStack, f64),
+    /// For-in loop, with the remaining values.
+    ForIn(LoopStack, for_in::Iter),
+}
+
+/// Where a generator takes its values from.
+enum State {
+    /// Body of a generator function, walked by its own runtime.
+    Fn { rt: Box<Runtime>, f_index: usize },
+    /// Iterator from an external function.
+    #[cfg(feature = "file")]
+    Iter(Box<dyn Iterator<Item = Result<Variable, String>> + Send>),
+    /// The generator has finished.
+    Done,
+}
+
+/// Stores the state of a generator.
+pub struct Generator {
+    state: State,
+}
+
+impl std::fmt::Debug for Generator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let done = matches!(self.state, State::Done);
+        f.debug_struct("Generator").field("done", &done).finish()
+    }
+}
+
+impl Generator {
+    /// Creates a generator that takes values from an iterator.
+    ///
+    /// The iterator is advanced only when the next value is requested.
+    #[cfg(feature = "file")]
+    pub(crate) fn from_iter<I>(iter: I) -> Generator
+    where
+        I: Iterator<Item = Result<Variable, String>> + Send + 'static,
+    {
+        Generator {
+            state: State::Iter(Box::new(iter)),
+        }
+    }
+
+    /// Resumes the generator and returns the next value,
+    /// or `None` if the generator has finished.
+    pub fn resume(&mut self) -> Result<Option<Variable>, String> {
+        let res = match self.state {
+            State::Done => return Ok(None),
+            State::Fn { ref mut rt, f_index } => {
+                let module = rt.module.clone();
+                let f = &module.functions[f_index];
+                match rt.block(&f.block) {
+                    Ok((x, Flow::Yield)) => return Ok(x),
+                    Ok((_, Flow::Break(_))) => Err(module.error(
+                        f.block.source_range,
+                        &format!("{}\nCan not break from function", rt.stack_trace()),
+                        rt,
+                    )),
+                    Ok((_, Flow::ContinueLoop(_))) => Err(module.error(
+                        f.block.source_range,
+                        &format!("{}\nCan not continue from function", rt.stack_trace()),
+                        rt,
+                    )),
+                    Ok(_) => Ok(None),
+                    Err(err) => Err(err),
+                }
+            }
+            #[cfg(feature = "file")]
+            State::Iter(ref mut iter) => match iter.next() {
+                Some(Ok(x)) => return Ok(Some(x)),
+                Some(Err(err)) => Err(err),
+                None => Ok(None),
+            },
+        };
+        self.state = State::Done;
+        res
+    }
+}
+
+impl Runtime {
+    /// Pops the frame of the expression to resume, when resuming a generator.
+    ///
+    /// This is called by every block, `if` and loop, so when there are no saved frames
+    /// it only checks the length and leaves popping to a separate cold function.
+    #[inline(always)]
+    pub(crate) fn resume_frame(&mut self) -> Option<Resume> {
+        match self.resume {
+            Some(ref frames) if !frames.is_empty() => self.pop_resume_frame(),
+            _ => None,
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn pop_resume_frame(&mut self) -> Option<Resume> {
+        self.resume.as_mut().and_then(|frames| frames.pop())
+    }
+
+    /// Saves the frame of an expression suspended by `yield`.
+    pub(crate) fn suspend_frame(&mut self, frame: Resume) {
+        if let Some(ref mut frames) = self.resume {
+            frames.push(frame);
+        }
+    }
+
+    /// Creates a generator from a loaded function.
+    ///
+    /// The arguments are on the stack after `st`, with the return slot right before.
+    /// They are removed from the stack and moved to the stack of the generator.
+    pub(crate) fn generator(
+        &mut self,
+        f_index: usize,
+        info: &ast::CallInfo,
+        st: usize,
+        lc: usize,
+    ) -> FlowResult {
+        // The return slot becomes the first item on the new stack.
+        let mut stack = vec![Variable::Return];
+        for i in st..self.stack.len() {
+            stack.push(self.stack[i].deep_clone(&self.stack));
+        }
+        let mut local_stack: Vec<(Arc<String>, usize)> = self.local_stack[lc..]
+            .iter()
+            .map(|&(ref name, ind)| (name.clone(), ind + 1 - st))
+            .collect();
+        self.stack.truncate(st - 1);
+        self.local_stack.truncate(lc);
+
+        let f = &self.module.functions[f_index];
+        local_stack.push((RETURN_TYPE.clone(), 0));
+        for (i, arg) in f.args.iter().enumerate() {
+            local_stack.push((arg.name.clone(), 1 + i));
+        }
+
+        let rt = Runtime {
+            module: self.module.clone(),
+            stack,
+            local_stack,
+            current_stack: vec![],
+            #[cfg(feature = "dynload")]
+            module_resolver: self.module_resolver,
+            call_stack: vec![Call {
+                fn_name: info.name.clone(),
+                index: f_index,
+                file: Some(f.file.clone()),
+                stack_len: 1,
+                local_len: 0,
+                current_len: 0,
+            }],
+            #[cfg(feature = "rand")]
+            rng: self.fork_rng(),
+            arg_err_index: Cell::new(None),
+            #[cfg(feature = "async")]
+            tokio_runtime: self.tokio_runtime.clone(),
+            #[cfg(feature = "process")]
+            exit_code: self.exit_code.clone(),
+            #[cfg(feature = "process")]
+            env_vars: self.env_vars.clone(),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            thread: self.thread.clone(),
+            resume: Some(vec![]),
+        };
+        Ok((
+            Some(Variable::Iter(Arc::new(Mutex::new(Generator {
+                state: State::Fn {
+                    rt: Box::new(rt),
+                    f_index,
+                },
+            })))),
+            Flow::Continue,
+        ))
+    }
+
+    /// Suspends the generator with a value, or continues after `yield` when resuming.
+    pub(crate) fn yield_expr(&mut self, expr: &ast::Expression) -> FlowResult {
+        match self.resume_frame() {
+            None => {}
+            Some(Resume::Yield) => return Ok((None, Flow::Continue)),
+            Some(_) => return self.err(expr.source_range(), WRONG_RESUME),
+        }
+        // Values can only be yielded from the frame of the generator function.
+        if self.resume.is_none() || self.call_stack.len() != 1 {
+            return self.err(expr.source_range(), "Can not yield outside a generator function");
+        }
+        let x = match self.expression(expr, Side::Right)? {
+            (Some(x), Flow::Continue) => x,
+            (x, Flow::Return) => return Ok((x, Flow::Return)),
+            _ => return self.err(expr.source_range(), "Expected something"),
+        };
+        let x = self.resolve(&x).deep_clone(&self.stack);
+        self.suspend_frame(Resume::Yield);
+        Ok((Some(x), Flow::Yield))
+    }
+}
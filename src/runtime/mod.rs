@@ -4,13 +4,16 @@
 use rand;
 use range::Range;
 use std::cell::Cell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+use crate::ThreadState;
 
 use crate::{
     ast,
     embed,
     FnIndex,
+    Key,
     Module,
     UnsafeRef,
     Variable,
@@ -18,9 +21,17 @@ use crate::{
     CSIE,
 };
 
-#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 mod for_in;
 mod for_n;
+mod generator;
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+mod pool;
+
+pub use self::generator::Generator;
+use self::generator::{LoopStack, Resume, WRONG_RESUME, YIELD_NOT_SUPPORTED};
+
+#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+use self::for_n::Par;
 
 type FlowResult = Result<(Option<Variable>, Flow), String>;
 
@@ -44,6 +55,11 @@ pub enum Flow {
     Break(Option<Arc<String>>),
     /// Continue loop, with optional label.
     ContinueLoop(Option<Arc<String>>),
+    /// Suspend generator after yielding a value.
+    ///
+    /// Added with generators. Code outside this crate that matches `Flow` exhaustively
+    /// must handle this variant, so it requires a new major version.
+    Yield,
 }
 
 /// Stores function calls.
@@ -69,6 +85,8 @@ lazy_static! {
     pub(crate) static ref RETURN_TYPE: Arc<String> = Arc::new("return".into());
     pub(crate) static ref BOOL_TYPE: Arc<String> = Arc::new("boolean".into());
     pub(crate) static ref OBJECT_TYPE: Arc<String> = Arc::new("object".into());
+    pub(crate) static ref MAP_TYPE: Arc<String> = Arc::new("map".into());
+    pub(crate) static ref SET_TYPE: Arc<String> = Arc::new("set".into());
     pub(crate) static ref LINK_TYPE: Arc<String> = Arc::new("link".into());
     pub(crate) static ref ARRAY_TYPE: Arc<String> = Arc::new("array".into());
     pub(crate) static ref UNSAFE_REF_TYPE: Arc<String> = Arc::new("unsafe_ref".into());
@@ -79,6 +97,9 @@ lazy_static! {
     pub(crate) static ref THREAD_TYPE: Arc<String> = Arc::new("thread".into());
     pub(crate) static ref CLOSURE_TYPE: Arc<String> = Arc::new("closure".into());
     pub(crate) static ref IN_TYPE: Arc<String> = Arc::new("in".into());
+    pub(crate) static ref OUT_TYPE: Arc<String> = Arc::new("out".into());
+    pub(crate) static ref SHARED_TYPE: Arc<String> = Arc::new("shared".into());
+    pub(crate) static ref ITER_TYPE: Arc<String> = Arc::new("iter".into());
     pub(crate) static ref MAIN: Arc<String> = Arc::new("main".into());
 }
 
@@ -122,6 +143,17 @@ pub struct Runtime {
     /// Tokio runtime handle.
     #[cfg(feature = "async")]
     pub tokio_runtime: Arc<tokio::runtime::Runtime>,
+    /// Exit code set by `exit`, shared with `par` loops and generators.
+    #[cfg(feature = "process")]
+    pub(crate) exit_code: Arc<std::sync::Mutex<Option<i32>>>,
+    /// Environment variables set by `set_env_var`, shared with threads, `par` loops and generators.
+    #[cfg(feature = "process")]
+    pub(crate) env_vars: Arc<std::sync::Mutex<HashMap<Arc<String>, Arc<String>>>>,
+    /// State of the thread running this runtime, set when it is cancelled.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub(crate) thread: Arc<ThreadState>,
+    /// Frames to resume, when running a generator function.
+    pub(crate) resume: Option<Vec<Resume>>,
 }
 
 impl Default for Runtime {
@@ -259,7 +291,7 @@ fn item_lookup(
                                         }
                                         _ => break,
                                     };
-                                    let v = match (*arr).get_mut(id as usize) {
+                                    let v = match (&mut (*arr)).get_mut(id as usize) {
                                         None => {
                                             return Err(module.error_fnindex(
                                                 prop.source_range(),
@@ -382,9 +414,67 @@ impl Runtime {
             arg_err_index: Cell::new(None),
             #[cfg(feature = "async")]
             tokio_runtime: Arc::new(tokio::runtime::Runtime::new().unwrap()),
+            #[cfg(feature = "process")]
+            exit_code: Arc::new(std::sync::Mutex::new(None)),
+            #[cfg(feature = "process")]
+            env_vars: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            thread: Arc::new(ThreadState::default()),
+            resume: None,
         }
     }
 
+    /// Seeds the random number generator, to make runs reproducible.
+    ///
+    /// Threads started by `go` and generators get generators derived from this one.
+    /// Each iteration of a `par` loop gets a generator derived from this one and its index.
+    #[cfg(feature = "rand")]
+    pub fn set_seed(&mut self, seed: u64) {
+        use rand::SeedableRng;
+
+        self.rng = rand::rngs::StdRng::seed_from_u64(seed);
+    }
+
+    /// Creates a new random number generator derived from the runtime's generator.
+    #[cfg(feature = "rand")]
+    pub(crate) fn fork_rng(&mut self) -> rand::rngs::StdRng {
+        use rand::{Rng, SeedableRng};
+
+        rand::rngs::StdRng::seed_from_u64(self.rng.gen())
+    }
+
+    /// Returns the exit code if the script stopped by calling `exit`.
+    ///
+    /// The script stops with an error, such that the host decides how to exit.
+    #[cfg(feature = "process")]
+    pub fn exit_code(&self) -> Option<i32> {
+        *self.exit_code.lock().unwrap()
+    }
+
+    /// Returns an error if the thread running this runtime has been cancelled.
+    ///
+    /// This is checked at loop back-edges and calls.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub(crate) fn check_cancel(&self, range: Range) -> Result<(), String> {
+        use std::sync::atomic::Ordering;
+
+        if self.thread.cancel.load(Ordering::Relaxed) {
+            Err(self.module.error(
+                range,
+                &format!("{}\nThread was cancelled", self.stack_trace()),
+                self,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Threads can not be cancelled without threading support.
+    #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
+    pub(crate) fn check_cancel(&self, _range: Range) -> Result<(), String> {
+        Ok(())
+    }
+
     /// Pops variable from stack.
     pub fn pop<T: embed::PopVariable>(&mut self) -> Result<T, String> {
         let v = self.stack.pop().unwrap_or_else(|| panic!("{}", TINVOTS));
@@ -577,6 +667,8 @@ impl Runtime {
         match *expr {
             Link(ref link) => self.link(link),
             Object(ref obj) => self.object(obj),
+            Map(ref map) => self.map(map),
+            Set(ref set) => self.set(set),
             Array(ref arr) => self.array(arr),
             ArrayFill(ref array_fill) => self.array_fill(array_fill),
             Block(ref block) => self.block(block),
@@ -591,7 +683,21 @@ impl Runtime {
                 Ok((Some(x), Flow::Return))
             }
             ReturnVoid(_) => Ok((None, Flow::Return)),
-            Break(ref b) => Ok((None, Flow::Break(b.label.clone()))),
+            Yield(ref expr) => self.yield_expr(expr),
+            Break(ref b) => {
+                // The break value is cloned, since the loop might truncate the stack.
+                let x = match b.val {
+                    Some(ref val) => match self.expression(val, Side::Right)? {
+                        (Some(x), Flow::Continue) => {
+                            Some(self.resolve(&x).deep_clone(&self.stack))
+                        }
+                        (x, Flow::Return) => return Ok((x, Flow::Return)),
+                        _ => return self.err(val.source_range(), "Expected something"),
+                    },
+                    None => None,
+                };
+                Ok((x, Flow::Break(b.label.clone())))
+            }
             Continue(ref b) => Ok((None, Flow::ContinueLoop(b.label.clone()))),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             Go(ref go) => self.go(go),
@@ -621,44 +727,54 @@ impl Runtime {
             Vec4(ref vec4) => self.vec4(vec4, side),
             Mat4(ref mat4) => self.mat4(mat4, side),
             For(ref for_expr) => self.for_expr(for_expr),
+            WhileLet(ref while_let) => self.while_let_expr(while_let),
             ForN(ref for_n_expr) => self.for_n_expr(for_n_expr),
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             ForIn(ref for_in_expr) => self.for_in_expr(for_in_expr),
-            #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-            ForIn(ref for_in_expr) |
-            SumIn(ref for_in_expr) |
-            ProdIn(ref for_in_expr) |
-            MinIn(ref for_in_expr) |
-            MaxIn(ref for_in_expr) |
-            SiftIn(ref for_in_expr) |
-            AnyIn(ref for_in_expr) |
-            AllIn(ref for_in_expr) |
-            LinkIn(ref for_in_expr) => match **for_in_expr {},
-            Sum(ref for_n_expr) => self.sum_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Sum(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Sum)
+            }
+            Sum(ref for_n_expr) => self.sum_n_expr(for_n_expr),
             SumIn(ref sum_in_expr) => self.sum_in_expr(sum_in_expr),
             SumVec4(ref for_n_expr) => self.sum_vec4_n_expr(for_n_expr),
-            Prod(ref for_n_expr) => self.prod_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Prod(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Prod)
+            }
+            Prod(ref for_n_expr) => self.prod_n_expr(for_n_expr),
             ProdIn(ref for_in_expr) => self.prod_in_expr(for_in_expr),
             ProdVec4(ref for_n_expr) => self.prod_vec4_n_expr(for_n_expr),
-            Min(ref for_n_expr) => self.min_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Min(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Min)
+            }
+            Min(ref for_n_expr) => self.min_n_expr(for_n_expr),
             MinIn(ref for_in_expr) => self.min_in_expr(for_in_expr),
-            Max(ref for_n_expr) => self.max_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Max(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Max)
+            }
+            Max(ref for_n_expr) => self.max_n_expr(for_n_expr),
             MaxIn(ref for_in_expr) => self.max_in_expr(for_in_expr),
-            Sift(ref for_n_expr) => self.sift_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Sift(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Sift)
+            }
+            Sift(ref for_n_expr) => self.sift_n_expr(for_n_expr),
             SiftIn(ref for_in_expr) => self.sift_in_expr(for_in_expr),
-            Any(ref for_n_expr) => self.any_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Any(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::Any)
+            }
+            Any(ref for_n_expr) => self.any_n_expr(for_n_expr),
             AnyIn(ref for_in_expr) => self.any_in_expr(for_in_expr),
-            All(ref for_n_expr) => self.all_n_expr(for_n_expr),
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            All(ref for_n_expr) if for_n_expr.par => {
+                self.par_n_expr(for_n_expr, Par::All)
+            }
+            All(ref for_n_expr) => self.all_n_expr(for_n_expr),
             AllIn(ref for_in_expr) => self.all_in_expr(for_in_expr),
             LinkFor(ref for_n_expr) => self.link_for_n_expr(for_n_expr),
-            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             LinkIn(ref for_in_expr) => self.link_for_in_expr(for_in_expr),
             If(ref if_expr) => self.if_expr(if_expr),
             Variable(ref range_var) => Ok((Some(range_var.1.clone()), Flow::Continue)),
@@ -885,6 +1001,10 @@ impl Runtime {
     pub fn run(&mut self, module: &Arc<Module>) -> Result<(), String> {
         use std::mem::replace;
 
+        #[cfg(feature = "process")]
+        {
+            *self.exit_code.lock().unwrap() = None;
+        }
         let old_module = replace(&mut self.module, module.clone());
         let name: Arc<String> = MAIN.clone();
         let call = ast::Call {
@@ -933,12 +1053,29 @@ impl Runtime {
 
     fn block(&mut self, block: &ast::Block) -> FlowResult {
         let mut expect = None;
-        let st = self.stack.len();
-        let lc = self.local_stack.len();
-        let cu = self.current_stack.len();
-        for e in &block.expressions {
+        let (start, st, lc, cu) = match self.resume_frame() {
+            None => (
+                0,
+                self.stack.len(),
+                self.local_stack.len(),
+                self.current_stack.len(),
+            ),
+            Some(Resume::Block { index, st, lc, cu }) => (index, st, lc, cu),
+            Some(_) => return self.err(block.source_range, WRONG_RESUME),
+        };
+        for (i, e) in block.expressions.iter().enumerate().skip(start) {
             expect = match self.expression(e, Side::Right)? {
                 (x, Flow::Continue) => x,
+                (x, Flow::Yield) => {
+                    // Keep the stack until the generator is resumed.
+                    self.suspend_frame(Resume::Block {
+                        index: i,
+                        st,
+                        lc,
+                        cu,
+                    });
+                    return Ok((x, Flow::Yield));
+                }
                 x => {
                     self.stack.truncate(st);
                     self.local_stack.truncate(lc);
@@ -995,6 +1132,7 @@ impl Runtime {
         stack.reverse();
 
         let last_call = self.call_stack.last().expect(CSIE);
+        let state = Arc::new(ThreadState::with_finished());
         let new_rt = Runtime {
             module: self.module.clone(),
             stack,
@@ -1011,12 +1149,29 @@ impl Runtime {
                 local_len: 0,
                 current_len: 0,
             }],
-            rng: self.rng.clone(),
+            rng: self.fork_rng(),
             arg_err_index: Cell::new(None),
             #[cfg(feature = "async")]
             tokio_runtime: self.tokio_runtime.clone(),
+            #[cfg(feature = "process")]
+            exit_code: Arc::new(std::sync::Mutex::new(None)),
+            #[cfg(feature = "process")]
+            env_vars: self.env_vars.clone(),
+            thread: state.clone(),
+            resume: None,
         };
+        /// Tells that the thread has finished, also when it panics.
+        struct SetFinished(Arc<ThreadState>);
+
+        impl Drop for SetFinished {
+            fn drop(&mut self) {
+                self.0.set_finished();
+            }
+        }
+
+        let set_finished = SetFinished(state.clone());
         let handle: JoinHandle<Result<Variable, String>> = spawn!(self.tokio_runtime,
+            let _set_finished = set_finished;
             let mut new_rt = new_rt;
             let fake_call = fake_call;
             let loader = false;
@@ -1027,7 +1182,7 @@ impl Runtime {
             }
             .deep_clone(&new_rt.stack))
         );
-        Ok((Some(Variable::Thread(Thread::new(handle))), Flow::Continue))
+        Ok((Some(Variable::Thread(Thread::with_state(handle, state))), Flow::Continue))
     }
 
     /// Call closure.
@@ -1070,9 +1225,6 @@ impl Runtime {
             // The stack value should remain, but the local should not.
             self.stack.push(Variable::Return);
         }
-        let st = self.stack.len();
-        let lc = self.local_stack.len();
-        let cu = self.current_stack.len();
         for arg in &call.args {
             match self.expression(arg, Side::Right)? {
                 (Some(x), Flow::Continue) => self.stack.push(x),
@@ -1090,6 +1242,30 @@ impl Runtime {
             };
         }
 
+        self.call_closure_body(&call.item.name, &f, &env, Some(call.source_range))
+    }
+
+    /// Runs the body of a closure, after the arguments are pushed on the stack.
+    ///
+    /// Errors that are not from the closure body are reported at `range`,
+    /// or returned without range when `None`.
+    fn call_closure_body(
+        &mut self,
+        name: &Arc<String>,
+        f: &ast::Closure,
+        env: &crate::ClosureEnvironment,
+        range: Option<Range>,
+    ) -> FlowResult {
+        let err = |rt: &Runtime, msg: String| -> FlowResult {
+            Err(match range {
+                Some(range) => rt.module.error(range, &msg, rt),
+                None => msg,
+            })
+        };
+        let st = self.stack.len() - f.args.len();
+        let lc = self.local_stack.len();
+        let cu = self.current_stack.len();
+
         // Look for variable in current stack.
         if !f.currents.is_empty() {
             for current in &f.currents {
@@ -1105,21 +1281,20 @@ impl Runtime {
                         .push((current.name.clone(), self.stack.len()));
                     self.stack.push(Variable::Ref(ind));
                 } else {
-                    return Err(self.module.error(
-                        call.source_range,
-                        &format!(
+                    return err(
+                        self,
+                        format!(
                             "{}\nCould not find current variable `{}`",
                             self.stack_trace(),
                             current.name
                         ),
-                        self,
-                    ));
+                    );
                 }
             }
         }
 
         self.push_fn(
-            call.item.name.clone(),
+            name.clone(),
             env.relative,
             Some(f.file.clone()),
             st,
@@ -1136,47 +1311,32 @@ impl Runtime {
         }
         let (x, flow) = self.expression_module(&f.expr, Side::Right, &env.module)?;
         match flow {
-            Flow::Break(None) => return self.err(call.source_range, "Can not break from function"),
-            Flow::ContinueLoop(None) => {
-                return self.err(call.source_range, "Can not continue from function")
+            Flow::Break(None) => {
+                return err(self, format!("{}\nCan not break from function", self.stack_trace()))
             }
-            Flow::Break(Some(ref label)) => {
-                return Err(self.module.error(
-                    call.source_range,
-                    &format!(
-                        "{}\nThere is no loop labeled `{}`",
-                        self.stack_trace(),
-                        label
-                    ),
-                    self,
-                ))
+            Flow::ContinueLoop(None) => {
+                return err(self, format!("{}\nCan not continue from function", self.stack_trace()))
             }
-            Flow::ContinueLoop(Some(ref label)) => {
-                return Err(self.module.error(
-                    call.source_range,
-                    &format!(
-                        "{}\nThere is no loop labeled `{}`",
-                        self.stack_trace(),
-                        label
-                    ),
+            Flow::Break(Some(ref label)) | Flow::ContinueLoop(Some(ref label)) => {
+                return err(
                     self,
-                ))
+                    format!("{}\nThere is no loop labeled `{}`", self.stack_trace(), label),
+                )
             }
             _ => {}
         }
-        self.pop_fn(call.item.name.clone());
+        self.pop_fn(name.clone());
         match (f.returns(), x) {
             (true, None) => {
                 match self.stack.pop().expect(TINVOTS) {
-                    Variable::Return => Err(self.module.error(
-                        call.source_range,
-                        &format!(
+                    Variable::Return => err(
+                        self,
+                        format!(
                             "{}\nFunction `{}` did not return a value",
                             self.stack_trace(),
-                            call.item.name
+                            name
                         ),
-                        self,
-                    )),
+                    ),
                     x => {
                         // This happens when return is only
                         // assigned to `return = x`.
@@ -1184,28 +1344,26 @@ impl Runtime {
                     }
                 }
             }
-            (false, Some(_)) => Err(self.module.error(
-                call.source_range,
-                &format!(
+            (false, Some(_)) => err(
+                self,
+                format!(
                     "{}\nFunction `{}` should not return a value",
                     self.stack_trace(),
-                    call.item.name
+                    name
                 ),
-                self,
-            )),
+            ),
             (true, Some(Variable::Return)) => {
                 // TODO: Could return the last value on the stack.
                 //       Requires .pop_fn delayed after.
-                Err(self.module.error(
-                    call.source_range,
-                    &format!(
+                err(
+                    self,
+                    format!(
                         "{}\nFunction `{}` did not return a value. \
                     Did you forget a `return`?",
                         self.stack_trace(),
-                        call.item.name
+                        name
                     ),
-                    self,
-                ))
+                )
             }
             (returns, b) => {
                 if returns {
@@ -1216,6 +1374,37 @@ impl Runtime {
         }
     }
 
+    /// Calls a closure with argument values from an external function.
+    ///
+    /// Errors that are not from the closure body are returned without range,
+    /// such that they are reported at the call to the external function.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub(crate) fn call_closure_values(
+        &mut self,
+        name: Arc<String>,
+        closure: &Variable,
+        args: Vec<Variable>,
+    ) -> Result<Option<Variable>, String> {
+        let (f, env) = match self.resolve(closure) {
+            &Variable::Closure(ref f, ref env) => (f.clone(), env.clone()),
+            x => return Err(self.expected(x, "closure")),
+        };
+        if args.len() != f.args.len() {
+            return Err(format!(
+                "{}\nExpected {} arguments but found {}",
+                self.stack_trace(),
+                f.args.len(),
+                args.len()
+            ));
+        }
+        if f.returns() {
+            self.stack.push(Variable::Return);
+        }
+        self.stack.extend(args);
+        let (x, _) = self.call_closure_body(&name, &f, &env, None)?;
+        Ok(x.map(|x| self.resolve(&x).deep_clone(&self.stack)))
+    }
+
     /// Called from the outside, e.g. a loader script by `call` or `call_ret` intrinsic.
     pub fn call(&mut self, call: &ast::Call, module: &Arc<Module>) -> FlowResult {
         use std::mem::replace;
@@ -1293,6 +1482,63 @@ impl Runtime {
         ))
     }
 
+    /// Calls an external async function and waits for the result.
+    ///
+    /// The script does not suspend, but blocks its thread until the future resolves.
+    /// On a worker thread of a multi-threaded Tokio runtime, other tasks are moved
+    /// to other workers while waiting.
+    /// Outside a Tokio runtime, the future runs on the Tokio runtime of the Dyon runtime.
+    /// A current-thread runtime can not be blocked without stopping the tasks
+    /// the future might wait for, so this returns an error instead.
+    #[cfg(feature = "async")]
+    fn call_async(
+        &mut self,
+        args: &[ast::Expression],
+        fun: crate::FnAsyncRef,
+        info: &ast::CallInfo,
+    ) -> FlowResult {
+        for arg in args {
+            match self.expression(arg, Side::Right)? {
+                (Some(x), Flow::Continue) => self.stack.push(x),
+                (x, Flow::Return) => {
+                    return Ok((x, Flow::Return));
+                }
+                _ => {
+                    return self.err(
+                        arg.source_range(),
+                        "Expected something. \
+                                Expression did not return a value.",
+                    )
+                }
+            };
+        }
+        let fut = (fun.0)(self).map_err(|err| {
+            let range = if let Some(ind) = self.arg_err_index.get() {
+                self.arg_err_index.set(None);
+                args[ind].source_range()
+            } else {
+                info.source_range
+            };
+            self.module.error(range, &err, self)
+        })?;
+        use tokio::runtime::{Handle, RuntimeFlavor};
+
+        let res = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(fut))
+            }
+            Ok(_) => Err(format!(
+                "Can not wait for async function `{}` on a current-thread Tokio runtime",
+                info.name
+            )),
+            Err(_) => self.tokio_runtime.block_on(fut),
+        };
+        match res {
+            Ok(x) => Ok((x, Flow::Continue)),
+            Err(err) => Err(self.module.error(info.source_range, &err, self)),
+        }
+    }
+
     fn call_binop(
         &mut self,
         left_expr: &ast::Expression,
@@ -1463,6 +1709,7 @@ impl Runtime {
     ) -> FlowResult {
         use std::sync::atomic::Ordering;
 
+        self.check_cancel(info.source_range)?;
         let relative = if loader {
             0
         } else {
@@ -1584,6 +1831,13 @@ impl Runtime {
                 f.senders.0.store(false, Ordering::Relaxed);
             }
             drop(channels);
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            crate::dyon_std::notify_select();
+        }
+
+        // Generator functions run the body when the first value is requested.
+        if let crate::Type::Iter(_) = f.ret {
+            return self.generator(new_index, info, st, lc);
         }
 
         self.push_fn(
@@ -1708,6 +1962,8 @@ impl Runtime {
     fn call_internal(&mut self, call: &ast::Call, loader: bool) -> FlowResult {
         match call.f_index {
             FnIndex::Void(f) => self.call_void(&call.args, f, &call.info),
+            #[cfg(feature = "async")]
+            FnIndex::Async(f) => self.call_async(&call.args, f, &call.info),
             FnIndex::Return(f) => self.call_return(&call.args, f, &call.info),
             FnIndex::Lazy(f, lazy_inv) => self.call_lazy(&call.args, f, lazy_inv, &call.info),
             FnIndex::BinOp(f) => self.call_binop(&call.args[0], &call.args[1], f, &call.info),
@@ -1848,7 +2104,7 @@ impl Runtime {
                             return Ok((res, flow));
                         }
                     };
-                    match new_link.push(self.resolve(&v)) {
+                    match new_link.push(&self.resolve(&v).deep_clone(&self.stack)) {
                         Err(err) => {
                             return Err(self.module.error(
                                 item.source_range(),
@@ -1892,6 +2148,64 @@ impl Runtime {
         Ok((Some(Variable::Object(Arc::new(object))), Flow::Continue))
     }
 
+    fn map(&mut self, map: &ast::Map) -> FlowResult {
+        let mut res: HashMap<_, _> = HashMap::new();
+        for &(ref key_expr, ref expr) in &map.key_values {
+            let key = match self.expression(key_expr, Side::Right)? {
+                (Some(x), Flow::Continue) => x,
+                (x, Flow::Return) => {
+                    return Ok((x, Flow::Return));
+                }
+                _ => return self.err(key_expr.source_range(), "Expected something"),
+            };
+            let key = match Key::from_variable(&key, &self.stack) {
+                Some(key) => key,
+                None => {
+                    return Err(self.module.error(
+                        key_expr.source_range(),
+                        &self.expected(self.resolve(&key), "hashable key"),
+                        self,
+                    ))
+                }
+            };
+            let x = match self.expression(expr, Side::Right)? {
+                (Some(x), Flow::Continue) => x,
+                (x, Flow::Return) => {
+                    return Ok((x, Flow::Return));
+                }
+                _ => return self.err(expr.source_range(), "Expected something"),
+            };
+            if res.insert(key, x).is_some() {
+                return self.err(key_expr.source_range(), "Duplicate key in map");
+            }
+        }
+        Ok((Some(Variable::Map(Arc::new(res))), Flow::Continue))
+    }
+
+    fn set(&mut self, set: &ast::Set) -> FlowResult {
+        let mut res: HashSet<_> = HashSet::new();
+        for item in &set.items {
+            let x = match self.expression(item, Side::Right)? {
+                (Some(x), Flow::Continue) => x,
+                (x, Flow::Return) => return Ok((x, Flow::Return)),
+                _ => return self.err(item.source_range(), "Expected something"),
+            };
+            match Key::from_variable(&x, &self.stack) {
+                Some(key) => {
+                    res.insert(key);
+                }
+                None => {
+                    return Err(self.module.error(
+                        item.source_range(),
+                        &self.expected(self.resolve(&x), "hashable key"),
+                        self,
+                    ))
+                }
+            }
+        }
+        Ok((Some(Variable::Set(Arc::new(res))), Flow::Continue))
+    }
+
     fn array(&mut self, arr: &ast::Array) -> FlowResult {
         let mut array: Vec<Variable> = Vec::new();
         for item in &arr.items {
@@ -2343,10 +2657,85 @@ impl Runtime {
                     }
                     Ok((None, Flow::Continue))
                 }
+                Expression::Array(_) | Expression::Object(_) | Expression::Vec4(_) => {
+                    self.destructure(left, right)
+                }
                 _ => self.err(left.source_range(), "Expected item"),
             }
         }
     }
+    // Declares new locals from the items of an array, object or vec4.
+    fn destructure(&mut self, left: &ast::Expression, right: &ast::Expression) -> FlowResult {
+        use crate::ast::Expression;
+
+        let x = match self.expression(right, Side::Right)? {
+            (x, Flow::Return) => return Ok((x, Flow::Return)),
+            (Some(x), Flow::Continue) => x,
+            _ => return self.err(right.source_range(), "Expected something from the right side"),
+        };
+        let vals: Vec<(&ast::Expression, Variable)> = match (left, self.resolve(&x)) {
+            (&Expression::Array(ref arr), &Variable::Array(ref v)) => {
+                if arr.items.len() != v.len() {
+                    return self.err(
+                        left.source_range(),
+                        &format!(
+                            "Expected array of length {}, found length {}",
+                            arr.items.len(),
+                            v.len()
+                        ),
+                    );
+                }
+                arr.items.iter().zip(v.iter().cloned()).collect()
+            }
+            (&Expression::Object(ref obj), &Variable::Object(ref v)) => {
+                let mut vals = vec![];
+                for &(ref key, ref it) in &obj.key_values {
+                    match v.get(key) {
+                        Some(v) => vals.push((it, v.clone())),
+                        None => {
+                            return self.err(
+                                left.source_range(),
+                                &format!("Object has no key `{}`", key),
+                            )
+                        }
+                    }
+                }
+                vals
+            }
+            (&Expression::Vec4(ref vec4), &Variable::Vec4(v)) => vec4
+                .args
+                .iter()
+                .zip(v.iter())
+                .map(|(it, &v)| (it, Variable::f64(f64::from(v))))
+                .collect(),
+            (left, x) => {
+                let ty = match *left {
+                    Expression::Array(_) => "array",
+                    Expression::Object(_) => "object",
+                    _ => "vec4",
+                };
+                return Err(self.module.error(
+                    left.source_range(),
+                    &self.expected(x, ty),
+                    self,
+                ));
+            }
+        };
+        for (item, v) in vals {
+            let item = match *item {
+                Expression::Item(ref item) => item,
+                _ => return self.err(item.source_range(), "Expected item"),
+            };
+            let v = match v {
+                // Use a shallow clone of a reference.
+                Variable::Ref(ind) => self.stack[ind].clone(),
+                x => x,
+            };
+            self.local_stack.push((item.name.clone(), self.stack.len()));
+            self.stack.push(v);
+        }
+        Ok((None, Flow::Continue))
+    }
     // `insert` is true for `:=` and false for `=`.
     // This works only on objects, but does not have to check since it is
     // ignored for arrays.
@@ -2694,6 +3083,22 @@ impl Runtime {
         Ok((Some(v), Flow::Continue))
     }
     fn if_expr(&mut self, if_expr: &ast::If) -> FlowResult {
+        match self.resume_frame() {
+            None => {}
+            Some(Resume::If(branch)) => {
+                // The true block has index 0, followed by the else-if blocks and the else block.
+                let n = if_expr.else_if_blocks.len();
+                let block = if branch == 0 {
+                    &if_expr.true_block
+                } else if branch <= n {
+                    &if_expr.else_if_blocks[branch - 1]
+                } else {
+                    if_expr.else_block.as_ref().expect(TINVOTS)
+                };
+                return self.if_block(block, branch);
+            }
+            Some(_) => return self.err(if_expr.source_range, WRONG_RESUME),
+        }
         let cond = match self.expression(&if_expr.cond, Side::Right)? {
             (Some(x), Flow::Continue) => x,
             (x, Flow::Return) => {
@@ -2716,12 +3121,13 @@ impl Runtime {
             }
         };
         if val {
-            return self.block(&if_expr.true_block);
+            return self.if_block(&if_expr.true_block, 0);
         }
-        for (cond, body) in if_expr
+        for (i, (cond, body)) in if_expr
             .else_if_conds
             .iter()
             .zip(if_expr.else_if_blocks.iter())
+            .enumerate()
         {
             let else_if_cond = match self.expression(cond, Side::Right)? {
                 (Some(x), Flow::Continue) => x,
@@ -2733,57 +3139,87 @@ impl Runtime {
             match *self.resolve(&else_if_cond) {
                 Variable::Bool(false, _) => {}
                 Variable::Bool(true, _) => {
-                    return self.block(body);
+                    return self.if_block(body, 1 + i);
                 }
                 _ => return self.err(cond.source_range(), "Expected bool from else if condition"),
             }
         }
         if let Some(ref block) = if_expr.else_block {
-            self.block(block)
+            self.if_block(block, 1 + if_expr.else_if_blocks.len())
         } else {
             Ok((None, Flow::Continue))
         }
     }
-    fn for_expr(&mut self, for_expr: &ast::For) -> FlowResult {
-        let prev_st = self.stack.len();
-        let prev_lc = self.local_stack.len();
-        match self.expression(&for_expr.init, Side::Right)? {
-            (None, Flow::Continue) => {}
-            (x, Flow::Return) => {
-                return Ok((x, Flow::Return));
+
+    /// Runs a branch of an if expression, saving the branch when suspended by `yield`.
+    fn if_block(&mut self, block: &ast::Block, branch: usize) -> FlowResult {
+        match self.block(block)? {
+            (x, Flow::Yield) => {
+                self.suspend_frame(Resume::If(branch));
+                Ok((x, Flow::Yield))
             }
-            _ => {
-                return self.err(
-                    for_expr.init.source_range(),
-                    "Expected nothing from for init",
-                )
+            x => Ok(x),
+        }
+    }
+    fn for_expr(&mut self, for_expr: &ast::For) -> FlowResult {
+        let (stack, mut resumed) = match self.resume_frame() {
+            None => {
+                let prev_st = self.stack.len();
+                let prev_lc = self.local_stack.len();
+                match self.expression(&for_expr.init, Side::Right)? {
+                    (None, Flow::Continue) => {}
+                    (x, Flow::Return) => {
+                        return Ok((x, Flow::Return));
+                    }
+                    _ => {
+                        return self.err(
+                            for_expr.init.source_range(),
+                            "Expected nothing from for init",
+                        )
+                    }
+                };
+                let st = self.stack.len();
+                let lc = self.local_stack.len();
+                (LoopStack { prev_st, prev_lc, st, lc }, false)
             }
+            Some(Resume::For(stack)) => (stack, true),
+            Some(_) => return self.err(for_expr.source_range, WRONG_RESUME),
         };
-        let st = self.stack.len();
-        let lc = self.local_stack.len();
+        let LoopStack { prev_st, prev_lc, st, lc } = stack;
         let mut flow = Flow::Continue;
+        let mut res = None;
         loop {
-            let val = match self.expression(&for_expr.cond, Side::Right)? {
-                (Some(x), Flow::Continue) => x,
-                (x, Flow::Return) => return Ok((x, Flow::Return)),
-                _ => {
-                    return self.err(
-                        for_expr.cond.source_range(),
-                        "Expected bool from for condition",
-                    )
+            // Continue in the block when resuming a generator.
+            if !resumed {
+                self.check_cancel(for_expr.source_range)?;
+                let val = match self.expression(&for_expr.cond, Side::Right)? {
+                    (Some(x), Flow::Continue) => x,
+                    (x, Flow::Return) => return Ok((x, Flow::Return)),
+                    _ => {
+                        return self.err(
+                            for_expr.cond.source_range(),
+                            "Expected bool from for condition",
+                        )
+                    }
+                };
+                let val = match val {
+                    Variable::Bool(val, _) => val,
+                    _ => return self.err(for_expr.cond.source_range(), "Expected bool"),
+                };
+                if !val {
+                    break;
                 }
-            };
-            let val = match val {
-                Variable::Bool(val, _) => val,
-                _ => return self.err(for_expr.cond.source_range(), "Expected bool"),
-            };
-            if !val {
-                break;
             }
+            resumed = false;
             match self.block(&for_expr.block)? {
                 (x, Flow::Return) => return Ok((x, Flow::Return)),
+                (x, Flow::Yield) => {
+                    self.suspend_frame(Resume::For(stack));
+                    return Ok((x, Flow::Yield));
+                }
                 (_, Flow::Continue) => {}
-                (_, Flow::Break(x)) => {
+                (val, Flow::Break(x)) => {
+                    res = val;
                     if let Some(label) = x {
                         let same = if let Some(ref for_label) = for_expr.label {
                             &label == for_label
@@ -2836,7 +3272,92 @@ impl Runtime {
         }
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((None, flow))
+        Ok((res, flow))
+    }
+
+    fn while_let_expr(&mut self, while_let: &ast::WhileLet) -> FlowResult {
+        let (stack, mut resumed) = match self.resume_frame() {
+            None => {
+                let st = self.stack.len();
+                let lc = self.local_stack.len();
+                (LoopStack { prev_st: st, prev_lc: lc, st, lc }, false)
+            }
+            Some(Resume::WhileLet(stack)) => (stack, true),
+            Some(_) => return self.err(while_let.source_range, WRONG_RESUME),
+        };
+        let (prev_st, prev_lc) = (stack.prev_st, stack.prev_lc);
+        let mut flow = Flow::Continue;
+        let mut res = None;
+        loop {
+            // Continue in the block when resuming a generator.
+            if !resumed {
+                self.check_cancel(while_let.source_range)?;
+                let val = match self.expression(&while_let.expr, Side::Right)? {
+                    (Some(x), Flow::Continue) => x,
+                    (x, Flow::Return) => return Ok((x, Flow::Return)),
+                    _ => {
+                        return self.err(
+                            while_let.expr.source_range(),
+                            "Expected option from while let",
+                        )
+                    }
+                };
+                let val = match *self.resolve(&val) {
+                    Variable::Option(None) => break,
+                    Variable::Option(Some(ref v)) => (**v).clone(),
+                    ref x => {
+                        return Err(self.module.error(
+                            while_let.expr.source_range(),
+                            &self.expected(x, "option"),
+                            self,
+                        ))
+                    }
+                };
+                self.local_stack.push((while_let.name.clone(), self.stack.len()));
+                self.stack.push(val);
+            }
+            resumed = false;
+            match self.block(&while_let.block)? {
+                (x, Flow::Return) => return Ok((x, Flow::Return)),
+                (x, Flow::Yield) => {
+                    self.suspend_frame(Resume::WhileLet(stack));
+                    return Ok((x, Flow::Yield));
+                }
+                (_, Flow::Continue) => {}
+                (val, Flow::Break(x)) => {
+                    res = val;
+                    if let Some(label) = x {
+                        let same = if let Some(ref while_label) = while_let.label {
+                            &label == while_label
+                        } else {
+                            false
+                        };
+                        if !same {
+                            flow = Flow::Break(Some(label))
+                        }
+                    }
+                    break;
+                }
+                (_, Flow::ContinueLoop(x)) => {
+                    if let Some(label) = x {
+                        let same = if let Some(ref while_label) = while_let.label {
+                            &label == while_label
+                        } else {
+                            false
+                        };
+                        if !same {
+                            flow = Flow::ContinueLoop(Some(label));
+                            break;
+                        }
+                    }
+                }
+            }
+            self.stack.truncate(prev_st);
+            self.local_stack.truncate(prev_lc);
+        }
+        self.stack.truncate(prev_st);
+        self.local_stack.truncate(prev_lc);
+        Ok((res, flow))
     }
     fn vec4(&mut self, vec4: &ast::Vec4, side: Side) -> FlowResult {
         let st = self.stack.len();
@@ -0,0 +1,104 @@
+//! Shared pool of worker threads for `par` loops.
+//!
+//! The workers are started the first time the pool is used and are reused by all loops.
+//! A thread waiting for its jobs runs queued jobs itself,
+//! such that nested `par` loops never wait for workers that are waiting for them.
+
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+    jobs: Mutex<VecDeque<Job>>,
+    cvar: Condvar,
+    workers: usize,
+}
+
+lazy_static! {
+    static ref POOL: Pool = Pool::new();
+}
+
+impl Pool {
+    fn new() -> Pool {
+        let workers = std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1);
+        for _ in 0..workers {
+            // Without workers, jobs are run by the threads waiting for them.
+            let _ = std::thread::Builder::new()
+                .name("dyon-par".into())
+                .spawn(|| loop {
+                    POOL.next_job()();
+                });
+        }
+        Pool {
+            jobs: Mutex::new(VecDeque::new()),
+            cvar: Condvar::new(),
+            workers,
+        }
+    }
+
+    /// Waits for the next job in the queue.
+    fn next_job(&self) -> Job {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            match jobs.pop_front() {
+                Some(job) => return job,
+                None => jobs = self.cvar.wait(jobs).unwrap_or_else(|err| err.into_inner()),
+            }
+        }
+    }
+}
+
+/// Returns the number of worker threads in the pool.
+pub(crate) fn workers() -> usize {
+    POOL.workers
+}
+
+/// Runs jobs on the pool and returns their results in the same order.
+///
+/// A result is `None` if the job panicked.
+pub(crate) fn run<T: Send + 'static>(jobs: Vec<Box<dyn FnOnce() -> T + Send>>) -> Vec<Option<T>> {
+    let pool = &*POOL;
+    let n = jobs.len();
+    let (tx, rx) = mpsc::channel();
+    {
+        let mut queue = pool.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        for (i, job) in jobs.into_iter().enumerate() {
+            let tx = tx.clone();
+            queue.push_back(Box::new(move || {
+                let res = catch_unwind(AssertUnwindSafe(job)).ok();
+                let _ = tx.send((i, res));
+            }));
+        }
+    }
+    pool.cvar.notify_all();
+    drop(tx);
+
+    let mut res: Vec<Option<T>> = (0..n).map(|_| None).collect();
+    let mut received = 0;
+    while received < n {
+        if let Ok((i, x)) = rx.try_recv() {
+            res[i] = x;
+            received += 1;
+            continue;
+        }
+        // Help with queued jobs, which might be the ones waited for.
+        let job = pool.jobs.lock().unwrap_or_else(|err| err.into_inner()).pop_front();
+        match job {
+            Some(job) => job(),
+            // The remaining jobs are running and do not wait for this thread.
+            None => match rx.recv() {
+                Ok((i, x)) => {
+                    res[i] = x;
+                    received += 1;
+                }
+                Err(_) => break,
+            },
+        }
+    }
+    res
+}
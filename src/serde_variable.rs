@@ -0,0 +1,750 @@
+//! Serde support for Dyon values.
+//!
+//! - numbers become `f64`, and `f64` without fraction converts to integers
+//! - strings and chars become `str`
+//! - structs and maps with string keys become objects, other maps become Dyon maps
+//! - sequences, tuples and tuple structs become arrays
+//! - `Option` becomes `opt` and `Result` becomes `res`
+//! - `Vec4` and `Mat4` become `vec4` and `mat4`
+//! - unit variants become strings, and other variants become objects with one key
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+use serde::{Deserialize, Deserializer};
+
+use crate::{Key, Mat4, Variable, Vec4};
+
+/// Converts a value into a Dyon variable.
+pub fn to_variable<T: Serialize + ?Sized>(val: &T) -> Result<Variable, String> {
+    val.serialize(VariableSerializer)
+        .map_err(|err| err.to_string())
+}
+
+/// Converts a Dyon variable into a value.
+pub fn from_variable<T: DeserializeOwned>(var: &Variable) -> Result<T, String> {
+    T::deserialize(var.clone()).map_err(|err| err.to_string())
+}
+
+/// Creates an object when all keys are strings, or a map otherwise.
+fn object_or_map(pairs: Vec<(Variable, Variable)>) -> Result<Variable, String> {
+    if pairs.iter().all(|(key, _)| matches!(*key, Variable::Str(_))) {
+        let mut obj = HashMap::with_capacity(pairs.len());
+        for (key, val) in pairs {
+            if let Variable::Str(key) = key {
+                obj.insert(key, val);
+            }
+        }
+        Ok(Variable::Object(Arc::new(obj)))
+    } else {
+        let mut map = HashMap::with_capacity(pairs.len());
+        for (key, val) in pairs {
+            match Key::from_variable(&key, &[]) {
+                Some(key) => map.insert(key, val),
+                None => return Err(format!("Expected hashable key, found `{}`", key.typeof_var())),
+            };
+        }
+        Ok(Variable::Map(Arc::new(map)))
+    }
+}
+
+/// Creates an object with a single key for an enum variant.
+fn variant(name: &str, val: Variable) -> Variable {
+    let mut obj = HashMap::new();
+    obj.insert(Arc::new(name.into()), val);
+    Variable::Object(Arc::new(obj))
+}
+
+/// Returns the items of a link.
+fn link_items(link: &crate::Link) -> Vec<Variable> {
+    let mut items = vec![];
+    let mut link = link.clone();
+    while let Some(item) = link.head() {
+        items.push(*item);
+        link = link.tail();
+    }
+    items
+}
+
+impl Serialize for Variable {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Variable::Bool(val, _) => s.serialize_bool(val),
+            Variable::F64(val, _) => s.serialize_f64(val),
+            Variable::Str(ref val) => s.serialize_str(val),
+            Variable::Vec4(v) => Vec4(v).serialize(s),
+            Variable::Mat4(ref m) => Mat4(**m).serialize(s),
+            Variable::Array(ref arr) => s.collect_seq(arr.iter()),
+            Variable::Object(ref obj) => s.collect_map(obj.iter().map(|(k, v)| (&**k, v))),
+            Variable::Map(ref map) => s.collect_map(map.iter().map(|(k, v)| (k.to_variable(), v))),
+            Variable::Set(ref set) => s.collect_seq(set.iter().map(Key::to_variable)),
+            Variable::Link(ref link) => s.collect_seq(link_items(link).iter()),
+            Variable::Option(None) => s.serialize_none(),
+            Variable::Option(Some(ref val)) => s.serialize_some(&**val),
+            Variable::Result(Ok(ref val)) => s.serialize_newtype_variant("Result", 0, "Ok", &**val),
+            Variable::Result(Err(ref err)) => {
+                s.serialize_newtype_variant("Result", 1, "Err", &err.message)
+            }
+            ref x => Err(ser::Error::custom(format!(
+                "Can not serialize `{}`",
+                x.typeof_var()
+            ))),
+        }
+    }
+}
+
+impl Serialize for Vec4 {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct("Vec4", &self.0)
+    }
+}
+
+impl Serialize for Mat4 {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct("Mat4", &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Vec4 {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Vec4, D::Error> {
+        struct Vec4Visitor;
+
+        impl<'de> Visitor<'de> for Vec4Visitor {
+            type Value = Vec4;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a vec4")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Vec4, D::Error> {
+                <[f32; 4]>::deserialize(d).map(Vec4)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Vec4, A::Error> {
+                <[f32; 4]>::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Vec4)
+            }
+        }
+
+        d.deserialize_newtype_struct("Vec4", Vec4Visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mat4 {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Mat4, D::Error> {
+        struct Mat4Visitor;
+
+        impl<'de> Visitor<'de> for Mat4Visitor {
+            type Value = Mat4;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a mat4")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Mat4, D::Error> {
+                <[[f32; 4]; 4]>::deserialize(d).map(Mat4)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Mat4, A::Error> {
+                <[[f32; 4]; 4]>::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Mat4)
+            }
+        }
+
+        d.deserialize_newtype_struct("Mat4", Mat4Visitor)
+    }
+}
+
+/// Builds Dyon variables from Rust values.
+struct VariableSerializer;
+
+/// Collects items of an array.
+struct SerializeArray {
+    items: Vec<Variable>,
+}
+
+/// Collects keys and values of an object or map.
+struct SerializeObject {
+    pairs: Vec<(Variable, Variable)>,
+    key: Option<Variable>,
+}
+
+/// Wraps an array or object in an object with the variant name as key.
+struct SerializeVariant<T> {
+    name: &'static str,
+    inner: T,
+}
+
+impl Serializer for VariableSerializer {
+    type Ok = Variable;
+    type Error = Error;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeVariant<SerializeArray>;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeVariant<SerializeObject>;
+
+    fn serialize_bool(self, v: bool) -> Result<Variable, Error> {
+        Ok(Variable::bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Variable, Error> {
+        Ok(Variable::f64(v as f64))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Variable, Error> {
+        Ok(Variable::f64(v as f64))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Variable, Error> {
+        Ok(Variable::f64(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Variable, Error> {
+        Ok(Variable::f64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Variable, Error> {
+        Ok(Variable::Str(Arc::new(v.to_string())))
+    }
+    fn serialize_str(self, v: &str) -> Result<Variable, Error> {
+        Ok(Variable::Str(Arc::new(v.into())))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Variable, Error> {
+        Ok(Variable::Array(Arc::new(
+            v.iter().map(|&b| Variable::f64(b.into())).collect(),
+        )))
+    }
+    fn serialize_none(self) -> Result<Variable, Error> {
+        Ok(Variable::Option(None))
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Variable, Error> {
+        Ok(Variable::Option(Some(Box::new(v.serialize(self)?))))
+    }
+    fn serialize_unit(self) -> Result<Variable, Error> {
+        Ok(Variable::Option(None))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Variable, Error> {
+        Ok(Variable::Option(None))
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Variable, Error> {
+        Ok(Variable::Str(Arc::new(variant.into())))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        v: &T,
+    ) -> Result<Variable, Error> {
+        fn row(v: &Variable) -> Option<[f32; 4]> {
+            match *v {
+                Variable::Array(ref arr) if arr.len() == 4 => {
+                    let mut res = [0.0; 4];
+                    for (r, x) in res.iter_mut().zip(arr.iter()) {
+                        match *x {
+                            Variable::F64(x, _) => *r = x as f32,
+                            _ => return None,
+                        }
+                    }
+                    Some(res)
+                }
+                _ => None,
+            }
+        }
+
+        let val = v.serialize(self)?;
+        match name {
+            "Vec4" => {
+                let v = row(&val).ok_or_else(|| ser::Error::custom("Expected 4 numbers"))?;
+                Ok(Variable::Vec4(v))
+            }
+            "Mat4" => {
+                let mut m = [[0.0; 4]; 4];
+                if let Variable::Array(ref arr) = val {
+                    if arr.len() == 4 {
+                        for (r, x) in m.iter_mut().zip(arr.iter()) {
+                            *r = row(x).ok_or_else(|| ser::Error::custom("Expected 4 rows"))?;
+                        }
+                        return Ok(Variable::Mat4(Box::new(m)));
+                    }
+                }
+                Err(ser::Error::custom("Expected 4 rows"))
+            }
+            _ => Ok(val),
+        }
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _index: u32,
+        name_variant: &'static str,
+        v: &T,
+    ) -> Result<Variable, Error> {
+        let val = v.serialize(self)?;
+        Ok(match (name, name_variant) {
+            ("Result", "Ok") => Variable::Result(Ok(Box::new(val))),
+            ("Result", "Err") => Variable::Result(Err(Box::new(crate::Error {
+                message: val,
+                trace: vec![],
+            }))),
+            _ => variant(name_variant, val),
+        })
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeArray>, Error> {
+        Ok(SerializeVariant {
+            name,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeObject, Error> {
+        Ok(SerializeObject {
+            pairs: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeObject, Error> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeObject>, Error> {
+        Ok(SerializeVariant {
+            name,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.items.push(v.serialize(VariableSerializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Variable, Error> {
+        Ok(Variable::Array(Arc::new(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, v)
+    }
+    fn end(self) -> Result<Variable, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, v)
+    }
+    fn end(self) -> Result<Variable, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeArray> {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, v)
+    }
+    fn end(self) -> Result<Variable, Error> {
+        Ok(variant(self.name, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(VariableSerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("Expected key before value"))?;
+        self.pairs.push((key, v.serialize(VariableSerializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Variable, Error> {
+        object_or_map(self.pairs).map_err(ser::Error::custom)
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        v: &T,
+    ) -> Result<(), Error> {
+        let key = Variable::Str(Arc::new(key.into()));
+        self.pairs.push((key, v.serialize(VariableSerializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Variable, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeObject> {
+    type Ok = Variable;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        v: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, v)
+    }
+    fn end(self) -> Result<Variable, Error> {
+        Ok(variant(self.name, ser::SerializeMap::end(self.inner)?))
+    }
+}
+
+impl<'de> Deserialize<'de> for Variable {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Variable, D::Error> {
+        d.deserialize_any(VariableVisitor)
+    }
+}
+
+/// Builds Dyon variables from any serde format.
+struct VariableVisitor;
+
+impl<'de> Visitor<'de> for VariableVisitor {
+    type Value = Variable;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Dyon value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Variable, E> {
+        Ok(Variable::bool(v))
+    }
+    fn visit_i64<E>(self, v: i64) -> Result<Variable, E> {
+        Ok(Variable::f64(v as f64))
+    }
+    fn visit_u64<E>(self, v: u64) -> Result<Variable, E> {
+        Ok(Variable::f64(v as f64))
+    }
+    fn visit_f64<E>(self, v: f64) -> Result<Variable, E> {
+        Ok(Variable::f64(v))
+    }
+    fn visit_str<E>(self, v: &str) -> Result<Variable, E> {
+        Ok(Variable::Str(Arc::new(v.into())))
+    }
+    fn visit_string<E>(self, v: String) -> Result<Variable, E> {
+        Ok(Variable::Str(Arc::new(v)))
+    }
+    fn visit_none<E>(self) -> Result<Variable, E> {
+        Ok(Variable::Option(None))
+    }
+    fn visit_unit<E>(self) -> Result<Variable, E> {
+        Ok(Variable::Option(None))
+    }
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Variable, D::Error> {
+        Ok(Variable::Option(Some(Box::new(Variable::deserialize(d)?))))
+    }
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Variable, D::Error> {
+        Variable::deserialize(d)
+    }
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Variable, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Variable::Array(Arc::new(items)))
+    }
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Variable, A::Error> {
+        let mut pairs = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(pair) = map.next_entry()? {
+            pairs.push(pair);
+        }
+        object_or_map(pairs).map_err(de::Error::custom)
+    }
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Variable, A::Error> {
+        use serde::de::VariantAccess;
+
+        let (name, access): (String, _) = data.variant()?;
+        let val: Variable = access.newtype_variant()?;
+        Ok(match &*name {
+            "Ok" => Variable::Result(Ok(Box::new(val))),
+            "Err" => Variable::Result(Err(Box::new(crate::Error {
+                message: val,
+                trace: vec![],
+            }))),
+            _ => variant(&name, val),
+        })
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Variable {
+    type Deserializer = Variable;
+
+    fn into_deserializer(self) -> Variable {
+        self
+    }
+}
+
+/// Visits an array of variables.
+fn visit_array<'de, V: Visitor<'de>>(items: Vec<Variable>, visitor: V) -> Result<V::Value, Error> {
+    let mut seq = SeqDeserializer::new(items.into_iter());
+    let res = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(res)
+}
+
+/// Visits keys and values of an object or map.
+fn visit_pairs<'de, V, I>(pairs: I, visitor: V) -> Result<V::Value, Error>
+where
+    V: Visitor<'de>,
+    I: Iterator<Item = (Variable, Variable)>,
+{
+    let mut map = MapDeserializer::new(pairs);
+    let res = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(res)
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self {
+                Variable::F64(val, _) if val.fract() == 0.0 && val >= i64::MIN as f64 &&
+                                         val < i64::MAX as f64 => visitor.visit_i64(val as i64),
+                x => x.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Variable {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Variable::Bool(val, _) => visitor.visit_bool(val),
+            Variable::F64(val, _) => visitor.visit_f64(val),
+            Variable::Str(val) => visitor.visit_string((*val).clone()),
+            Variable::Vec4(v) => {
+                visit_array(v.iter().map(|&x| Variable::f64(x.into())).collect(), visitor)
+            }
+            Variable::Mat4(m) => visit_array(
+                m.iter()
+                    .map(|row| {
+                        Variable::Array(Arc::new(
+                            row.iter().map(|&x| Variable::f64(x.into())).collect(),
+                        ))
+                    })
+                    .collect(),
+                visitor,
+            ),
+            Variable::Array(arr) => visit_array((*arr).clone(), visitor),
+            Variable::Object(obj) => visit_pairs(
+                obj.iter().map(|(k, v)| (Variable::Str(k.clone()), v.clone())),
+                visitor,
+            ),
+            Variable::Map(map) => {
+                visit_pairs(map.iter().map(|(k, v)| (k.to_variable(), v.clone())), visitor)
+            }
+            Variable::Set(set) => visit_array(set.iter().map(Key::to_variable).collect(), visitor),
+            Variable::Link(link) => visit_array(link_items(&link), visitor),
+            Variable::Option(None) => visitor.visit_none(),
+            Variable::Option(Some(val)) => visitor.visit_some(*val),
+            Variable::Result(res) => {
+                let (name, val) = match res {
+                    Ok(val) => ("Ok", *val),
+                    Err(err) => ("Err", err.message),
+                };
+                visitor.visit_enum(EnumDeserializer {
+                    name: name.into(),
+                    val: Some(val),
+                })
+            }
+            x => Err(de::Error::custom(format!(
+                "Can not deserialize `{}`",
+                x.typeof_var()
+            ))),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Variable::Option(None) => visitor.visit_none(),
+            Variable::Option(Some(val)) => visitor.visit_some(*val),
+            x => visitor.visit_some(x),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Variable::Option(None) => visitor.visit_unit(),
+            x => x.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Variable::Str(name) => visitor.visit_enum(EnumDeserializer {
+                name: (*name).clone(),
+                val: None,
+            }),
+            Variable::Object(ref obj) if obj.len() == 1 => {
+                let (name, val) = obj.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    name: (**name).clone(),
+                    val: Some(val.clone()),
+                })
+            }
+            x @ Variable::Result(_) => x.deserialize_any(visitor),
+            x => Err(de::Error::custom(format!(
+                "Expected `str` or object with one key, found `{}`",
+                x.typeof_var()
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any i128 u128
+    }
+}
+
+/// Reads an enum variant by name, with an optional value.
+struct EnumDeserializer {
+    name: String,
+    val: Option<Variable>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer), Error> {
+        let name = seed.deserialize(self.name.into_deserializer())?;
+        Ok((name, VariantDeserializer { val: self.val }))
+    }
+}
+
+/// Reads the value of an enum variant.
+struct VariantDeserializer {
+    val: Option<Variable>,
+}
+
+impl VariantDeserializer {
+    fn value(self, expected: &str) -> Result<Variable, Error> {
+        self.val
+            .ok_or_else(|| de::Error::invalid_type(Unexpected::UnitVariant, &expected))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.val {
+            None | Some(Variable::Option(None)) => Ok(()),
+            Some(_) => Err(de::Error::invalid_type(Unexpected::NewtypeVariant, &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.value("newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.value("tuple variant")?.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value("struct variant")?.deserialize_any(visitor)
+    }
+}
@@ -29,6 +29,10 @@ pub enum Type {
     Array(Box<Type>),
     /// Object type.
     Object,
+    /// Map type.
+    Map,
+    /// Set type.
+    Set,
     /// Option type.
     Option(Box<Type>),
     /// Result type.
@@ -41,6 +45,14 @@ pub enum Type {
     /// In-type.
     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
     In(Box<Type>),
+    /// Out-type.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    Out(Box<Type>),
+    /// Shared type.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    Shared(Box<Type>),
+    /// Iterator type, returned by generator functions.
+    Iter(Box<Type>),
     /// Ad-hoc type.
     AdHoc(Arc<String>, Box<Type>),
     /// Closure type.
@@ -89,6 +101,8 @@ impl Type {
                 }
             }
             Object => "{}".into(),
+            Map => "map".into(),
+            Set => "set".into(),
             Option(ref ty) => {
                 if let Any = **ty {
                     "opt".into()
@@ -136,6 +150,38 @@ impl Type {
                     res
                 }
             }
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Out(ref ty) => {
+                if let Any = **ty {
+                    "out".into()
+                } else {
+                    let mut res = String::from("out[");
+                    res.push_str(&ty.description());
+                    res.push(']');
+                    res
+                }
+            }
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            Shared(ref ty) => {
+                if let Any = **ty {
+                    "shared".into()
+                } else {
+                    let mut res = String::from("shared[");
+                    res.push_str(&ty.description());
+                    res.push(']');
+                    res
+                }
+            }
+            Iter(ref ty) => {
+                if let Any = **ty {
+                    "iter".into()
+                } else {
+                    let mut res = String::from("iter[");
+                    res.push_str(&ty.description());
+                    res.push(']');
+                    res
+                }
+            }
             AdHoc(ref ad, ref ty) => (&**ad).clone() + " " + &ty.description(),
             Closure(ref closure) => {
                 let mut s = String::new();
@@ -185,6 +231,23 @@ impl Type {
         Type::In(Box::new(Type::Any))
     }
 
+    /// Returns an out-type with an `any` as inner type.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub fn out_ty() -> Type {
+        Type::Out(Box::new(Type::Any))
+    }
+
+    /// Returns a shared type with an `any` as inner type.
+    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+    pub fn shared() -> Type {
+        Type::Shared(Box::new(Type::Any))
+    }
+
+    /// Returns an iterator type with an `any` as inner type.
+    pub fn iter() -> Type {
+        Type::Iter(Box::new(Type::Any))
+    }
+
     /// Binds refinement type variables.
     ///
     /// Returns the type argument to compare to.
@@ -295,6 +358,11 @@ impl Type {
             (&Thread(ref x), &Thread(ref y)) if x.ambiguous(y) => true,
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             (&In(ref x), &In(ref y)) if x.ambiguous(y) => true,
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            (&Out(ref x), &Out(ref y)) if x.ambiguous(y) => true,
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            (&Shared(ref x), &Shared(ref y)) if x.ambiguous(y) => true,
+            (&Iter(ref x), &Iter(ref y)) if x.ambiguous(y) => true,
             (&Bool, &Any) => true,
             (&F64, &Any) => true,
             (&Str, &Any) => true,
@@ -302,6 +370,8 @@ impl Type {
             (&Mat4, &Any) => true,
             (&Link, &Any) => true,
             (&Array(_), &Any) => true,
+            (&Map, &Any) => true,
+            (&Set, &Any) => true,
             (&Option(_), &Any) => true,
             (&Result(_), &Any) => true,
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
@@ -309,6 +379,11 @@ impl Type {
             (&Secret(_), &Any) => true,
             #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
             (&In(_), &Any) => true,
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            (&Out(_), &Any) => true,
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            (&Shared(_), &Any) => true,
+            (&Iter(_), &Any) => true,
             _ => false,
         }
     }
@@ -335,8 +410,11 @@ impl Type {
         use self::Type::*;
 
         // Invert the order because of complex ad-hoc logic.
-        if let AdHoc(_, _) = *other {
+        if let AdHoc(_, ref other_ty) = *other {
             if let AdHoc(_, _) = *self {
+            } else if let Secret(_) = *self {
+                // An ad-hoc type only goes with `sec` when the inner type does.
+                return self.goes_with(other_ty);
             } else {
                 return other.goes_with(self);
             }
@@ -405,6 +483,35 @@ impl Type {
                     false
                 }
             }
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            &Out(ref out_ty) => {
+                if let Out(ref other_ty) = *other {
+                    out_ty.goes_with(other_ty)
+                } else if let Any = *other {
+                    true
+                } else {
+                    false
+                }
+            }
+            #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+            &Shared(ref shared_ty) => {
+                if let Shared(ref other_ty) = *other {
+                    shared_ty.goes_with(other_ty)
+                } else if let Any = *other {
+                    true
+                } else {
+                    false
+                }
+            }
+            &Iter(ref iter_ty) => {
+                if let Iter(ref other_ty) = *other {
+                    iter_ty.goes_with(other_ty)
+                } else if let Any = *other {
+                    true
+                } else {
+                    false
+                }
+            }
             &Closure(ref cl) => {
                 if let Closure(ref other_cl) = *other {
                     if cl.tys.len() != other_cl.tys.len() {
@@ -518,6 +625,12 @@ impl Type {
             } else if let Ok((range, _)) = convert.meta_bool("obj_any") {
                 convert.update(range);
                 ty = Some(Type::Object);
+            } else if let Ok((range, _)) = convert.meta_bool("map_any") {
+                convert.update(range);
+                ty = Some(Type::Map);
+            } else if let Ok((range, _)) = convert.meta_bool("set_any") {
+                convert.update(range);
+                ty = Some(Type::Set);
             } else if let Ok((range, val)) = Type::from_meta_data("opt", convert, ignored) {
                 convert.update(range);
                 ty = Some(Type::Option(Box::new(val)));
@@ -558,6 +671,7 @@ impl Type {
                     ret,
                     ext: vec![],
                     lazy: crate::LAZY_NO,
+                    defaults: 0,
                 })));
             } else {
                 loop {
@@ -573,6 +687,16 @@ impl Type {
                         ty = Some(Type::Thread(Box::new(val)));
                         break;
                     }
+                    if let Ok((range, _)) = convert.meta_bool("iter_any") {
+                        convert.update(range);
+                        ty = Some(Type::Iter(Box::new(Type::Any)));
+                        break;
+                    }
+                    if let Ok((range, val)) = Type::from_meta_data("iter", convert, ignored) {
+                        convert.update(range);
+                        ty = Some(Type::Iter(Box::new(val)));
+                        break;
+                    }
                     #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
                     if let Ok((range, _)) = convert.meta_bool("in_any") {
                         convert.update(range);
@@ -585,6 +709,30 @@ impl Type {
                         ty = Some(Type::In(Box::new(val)));
                         break;
                     }
+                    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+                    if let Ok((range, _)) = convert.meta_bool("out_any") {
+                        convert.update(range);
+                        ty = Some(Type::Out(Box::new(Type::Any)));
+                        break;
+                    }
+                    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+                    if let Ok((range, val)) = Type::from_meta_data("out", convert, ignored) {
+                        convert.update(range);
+                        ty = Some(Type::Out(Box::new(val)));
+                        break;
+                    }
+                    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+                    if let Ok((range, _)) = convert.meta_bool("shared_any") {
+                        convert.update(range);
+                        ty = Some(Type::Shared(Box::new(Type::Any)));
+                        break;
+                    }
+                    #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+                    if let Ok((range, val)) = Type::from_meta_data("shared", convert, ignored) {
+                        convert.update(range);
+                        ty = Some(Type::Shared(Box::new(val)));
+                        break;
+                    }
                     let range = convert.ignore();
                     convert.update(range);
                     ignored.push(range);
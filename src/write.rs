@@ -112,6 +112,30 @@ where
             }
             write!(w, "}}")?;
         }
+        Variable::Map(ref map) => {
+            write!(w, "map {{")?;
+            let n = map.len();
+            for (i, (k, v)) in map.iter().enumerate() {
+                write_variable(w, rt, &k.to_variable(), EscapeString::Json, tabs)?;
+                write!(w, ": ")?;
+                write_variable(w, rt, v, EscapeString::Json, tabs)?;
+                if i + 1 < n {
+                    write!(w, ", ")?;
+                }
+            }
+            write!(w, "}}")?;
+        }
+        Variable::Set(ref set) => {
+            write!(w, "set {{")?;
+            let n = set.len();
+            for (i, k) in set.iter().enumerate() {
+                write_variable(w, rt, &k.to_variable(), EscapeString::Json, tabs)?;
+                if i + 1 < n {
+                    write!(w, ", ")?;
+                }
+            }
+            write!(w, "}}")?;
+        }
         Variable::Array(ref arr) => {
             write!(w, "[")?;
             let n = arr.len();
@@ -151,6 +175,11 @@ where
         Variable::Closure(ref closure, _) => write_closure(w, rt, closure, tabs)?,
         #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         Variable::In(_) => write!(w, "_in")?,
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Variable::Out(_) => write!(w, "_out")?,
+        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
+        Variable::Shared(_) => write!(w, "_shared")?,
+        Variable::Iter(_) => write!(w, "_iter")?,
         // ref x => panic!("Could not print out `{:?}`", x)
     }
     Ok(())
@@ -166,6 +195,183 @@ pub(crate) fn eprint_variable(rt: &Runtime, v: &Variable, escape_string: EscapeS
     write_variable(&mut io::stderr(), rt, v, escape_string, 0).unwrap();
 }
 
+/// Stores a format specification for numbers, e.g. `08.3`.
+///
+/// The syntax is `[[fill]align][sign][0][width][separator][.precision][type]`:
+///
+/// - align: `<` left, `>` right (default) or `^` center, padded with `fill`
+/// - sign: `+` always, `-` only negative numbers (default) or ` ` space for positive
+/// - `0`: pads with zeros after the sign
+/// - separator: `,` or `_` between groups of digits
+/// - type: `f` fixed, `e`/`E` exponent, `x`/`X` hexadecimal, `o` octal or `b` binary
+#[derive(Clone, Debug)]
+pub(crate) struct NumberSpec {
+    fill: char,
+    align: Option<char>,
+    sign: char,
+    zero: bool,
+    width: usize,
+    separator: Option<char>,
+    precision: Option<usize>,
+    ty: Option<char>,
+}
+
+/// The largest width or precision in a number format specification.
+const MAX_SPEC_LEN: usize = 1000;
+
+impl Default for NumberSpec {
+    fn default() -> NumberSpec {
+        NumberSpec {
+            fill: ' ',
+            align: None,
+            sign: '-',
+            zero: false,
+            width: 0,
+            separator: None,
+            precision: None,
+            ty: None,
+        }
+    }
+}
+
+impl NumberSpec {
+    /// Parses a format specification.
+    pub(crate) fn parse(spec: &str) -> Result<NumberSpec, String> {
+        fn digits(chars: &[char], i: &mut usize) -> Option<usize> {
+            let start = *i;
+            while *i < chars.len() && chars[*i].is_ascii_digit() {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>().parse().ok()
+        }
+
+        let is_align = |ch: char| matches!(ch, '<' | '>' | '^');
+        let chars: Vec<char> = spec.chars().collect();
+        let mut res = NumberSpec::default();
+        let mut i = 0;
+        if chars.len() >= 2 && is_align(chars[1]) {
+            res.fill = chars[0];
+            res.align = Some(chars[1]);
+            i = 2;
+        } else if !chars.is_empty() && is_align(chars[0]) {
+            res.align = Some(chars[0]);
+            i = 1;
+        }
+        if i < chars.len() && matches!(chars[i], '+' | '-' | ' ') {
+            res.sign = chars[i];
+            i += 1;
+        }
+        if i < chars.len() && chars[i] == '0' {
+            res.zero = true;
+            i += 1;
+        }
+        if let Some(width) = digits(&chars, &mut i) {
+            res.width = width;
+        }
+        if i < chars.len() && matches!(chars[i], ',' | '_') {
+            res.separator = Some(chars[i]);
+            i += 1;
+        }
+        if i < chars.len() && chars[i] == '.' {
+            i += 1;
+            match digits(&chars, &mut i) {
+                Some(precision) => res.precision = Some(precision),
+                None => return Err(format!("Expected precision after `.` in `{}`", spec)),
+            }
+        }
+        if i < chars.len() && matches!(chars[i], 'f' | 'e' | 'E' | 'x' | 'X' | 'o' | 'b') {
+            res.ty = Some(chars[i]);
+            i += 1;
+        }
+        if i < chars.len() {
+            return Err(format!("Unexpected `{}` in number format `{}`", chars[i], spec));
+        }
+        if res.width > MAX_SPEC_LEN || res.precision.is_some_and(|p| p > MAX_SPEC_LEN) {
+            return Err(format!(
+                "Expected width and precision at most {} in `{}`",
+                MAX_SPEC_LEN, spec
+            ));
+        }
+        if res.precision.is_some() && res.radix().is_some() {
+            return Err(format!("Precision is not supported with radix in `{}`", spec));
+        }
+        Ok(res)
+    }
+
+    fn radix(&self) -> Option<u32> {
+        match self.ty {
+            Some('x') | Some('X') => Some(16),
+            Some('o') => Some(8),
+            Some('b') => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Formats a number.
+    pub(crate) fn format(&self, x: f64) -> Result<String, String> {
+        let neg = x.is_sign_negative() && !x.is_nan();
+        let abs = x.abs();
+        let mut body = if !x.is_finite() {
+            format!("{}", abs)
+        } else if self.radix().is_some() {
+            if abs.fract() != 0.0 || abs >= u64::MAX as f64 {
+                return Err(format!("Expected integer for radix format, found `{}`", x));
+            }
+            let n = abs as u64;
+            match self.ty {
+                Some('x') => format!("{:x}", n),
+                Some('X') => format!("{:X}", n),
+                Some('o') => format!("{:o}", n),
+                _ => format!("{:b}", n),
+            }
+        } else {
+            match (self.ty, self.precision) {
+                (Some('e'), Some(p)) => format!("{:.*e}", p, abs),
+                (Some('e'), None) => format!("{:e}", abs),
+                (Some('E'), Some(p)) => format!("{:.*E}", p, abs),
+                (Some('E'), None) => format!("{:E}", abs),
+                (_, Some(p)) => format!("{:.*}", p, abs),
+                (_, None) => format!("{}", abs),
+            }
+        };
+        if let (Some(sep), true) = (self.separator, x.is_finite()) {
+            // Hexadecimal digits can be `e` or `E`, but never a fraction or exponent.
+            let (group, end) = if self.radix().is_some() {
+                (4, None)
+            } else {
+                (3, body.find(['.', 'e', 'E']))
+            };
+            let (int, rest) = body.split_at(end.unwrap_or(body.len()));
+            let mut grouped = String::with_capacity(body.len() + int.len() / group);
+            for (i, ch) in int.chars().enumerate() {
+                if i > 0 && (int.len() - i) % group == 0 {
+                    grouped.push(sep);
+                }
+                grouped.push(ch);
+            }
+            grouped.push_str(rest);
+            body = grouped;
+        }
+        let sign = match (neg, self.sign) {
+            (true, _) => "-",
+            (false, '+') => "+",
+            (false, ' ') => " ",
+            (false, _) => "",
+        };
+        let len = sign.len() + body.chars().count();
+        let pad = self.width.saturating_sub(len);
+        if self.zero && self.align.is_none() && x.is_finite() {
+            return Ok(format!("{}{}{}", sign, "0".repeat(pad), body));
+        }
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        Ok(match self.align {
+            Some('<') => format!("{}{}{}", sign, body, fill(pad)),
+            Some('^') => format!("{}{}{}{}", fill(pad / 2), sign, body, fill(pad - pad / 2)),
+            _ => format!("{}{}{}", fill(pad), sign, body),
+        })
+    }
+}
+
 fn write_tabs<W: io::Write>(w: &mut W, tabs: u32) -> Result<(), io::Error> {
     for _ in 0..tabs {
         write!(w, "    ")?;
@@ -210,6 +416,8 @@ fn write_expr<W: io::Write>(
         }
         E::Link(ref link) => write_link(w, rt, link, tabs)?,
         E::Object(ref obj) => write_obj(w, rt, obj, tabs)?,
+        E::Map(ref map) => write_map(w, rt, map, tabs)?,
+        E::Set(ref set) => write_set(w, rt, set, tabs)?,
         E::Array(ref arr) => write_arr(w, rt, arr, tabs)?,
         E::ArrayFill(ref arr_fill) => write_arr_fill(w, rt, arr_fill, tabs)?,
         E::Call(ref call) => write_call(w, rt, &call.info.name, &call.args, tabs)?,
@@ -230,12 +438,20 @@ fn write_expr<W: io::Write>(
             write_expr(w, rt, expr, tabs)?;
         }
         E::ReturnVoid(_) => write!(w, "return")?,
+        E::Yield(ref expr) => {
+            write!(w, "yield ")?;
+            write_expr(w, rt, expr, tabs)?;
+        }
         E::Break(ref br) => {
             if let Some(ref label) = br.label {
                 write!(w, "break '{}", label)?;
             } else {
                 write!(w, "break")?;
             }
+            if let Some(ref val) = br.val {
+                write!(w, " ")?;
+                write_expr(w, rt, val, tabs)?;
+            }
         }
         E::Continue(ref c) => {
             if let Some(ref label) = c.label {
@@ -256,30 +472,27 @@ fn write_expr<W: io::Write>(
         E::Vec4(ref vec4) => write_vec4(w, rt, vec4, tabs)?,
         E::Mat4(ref mat4) => write_mat4(w, rt, mat4, tabs)?,
         E::For(ref f) => write_for(w, rt, f, tabs)?,
+        E::WhileLet(ref while_let) => {
+            write!(w, "while let some({}) = ", while_let.name)?;
+            write_expr(w, rt, &while_let.expr, tabs)?;
+            write!(w, " ")?;
+            write_block(w, rt, &while_let.block, tabs + 1)?;
+        }
         E::ForN(ref for_n) => {
             write!(w, "for ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::ForIn(ref for_in) => {
             write!(w, "for ")?;
             write_for_in(w, rt, for_in, tabs)?;
         }
-        #[cfg(not(all(not(target_family = "wasm"), feature = "threading")))]
-        E::ForIn(ref for_in) |
-        E::SumIn(ref for_in) |
-        E::ProdIn(ref for_in) |
-        E::MinIn(ref for_in) |
-        E::MaxIn(ref for_in) |
-        E::SiftIn(ref for_in) |
-        E::AnyIn(ref for_in) |
-        E::AllIn(ref for_in) |
-        E::LinkIn(ref for_in) => match **for_in {},
         E::Sum(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "sum ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SumIn(ref for_in) => {
             write!(w, "sum ")?;
             write_for_in(w, rt, for_in, tabs)?;
@@ -289,10 +502,12 @@ fn write_expr<W: io::Write>(
             write_for_n(w, rt, for_n, tabs)?;
         }
         E::Prod(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "prod ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::ProdIn(ref for_in) => {
             write!(w, "prod ")?;
             write_for_in(w, rt, for_in, tabs)?;
@@ -302,46 +517,56 @@ fn write_expr<W: io::Write>(
             write_for_n(w, rt, for_n, tabs)?;
         }
         E::Min(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "min ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MinIn(ref for_in) => {
             write!(w, "min ")?;
             write_for_in(w, rt, for_in, tabs)?;
         }
         E::Max(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "max ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::MaxIn(ref for_in) => {
             write!(w, "max ")?;
             write_for_in(w, rt, for_in, tabs)?;
         }
         E::Sift(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "sift ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::SiftIn(ref for_in) => {
             write!(w, "sift ")?;
             write_for_in(w, rt, for_in, tabs)?;
         }
         E::Any(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "any ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AnyIn(ref for_in) => {
             write!(w, "any ")?;
             write_for_in(w, rt, for_in, tabs)?;
         }
         E::All(ref for_n) => {
+            if for_n.par {
+                write!(w, "par ")?;
+            }
             write!(w, "all ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::AllIn(ref for_in) => {
             write!(w, "all ")?;
             write_for_in(w, rt, for_in, tabs)?;
@@ -350,7 +575,6 @@ fn write_expr<W: io::Write>(
             write!(w, "link ")?;
             write_for_n(w, rt, for_n, tabs)?;
         }
-        #[cfg(all(not(target_family = "wasm"), feature = "threading"))]
         E::LinkIn(ref for_in) => {
             write!(w, "link ")?;
             write_for_in(w, rt, for_in, tabs)?;
@@ -566,6 +790,42 @@ fn write_obj<W: io::Write>(
     Ok(())
 }
 
+fn write_map<W: io::Write>(
+    w: &mut W,
+    rt: &Runtime,
+    map: &ast::Map,
+    tabs: u32,
+) -> Result<(), io::Error> {
+    write!(w, "map {{")?;
+    for (i, key_value) in map.key_values.iter().enumerate() {
+        write_expr(w, rt, &key_value.0, tabs + 1)?;
+        write!(w, ": ")?;
+        write_expr(w, rt, &key_value.1, tabs + 1)?;
+        if i + 1 < map.key_values.len() {
+            write!(w, ", ")?;
+        }
+    }
+    write!(w, "}}")?;
+    Ok(())
+}
+
+fn write_set<W: io::Write>(
+    w: &mut W,
+    rt: &Runtime,
+    set: &ast::Set,
+    tabs: u32,
+) -> Result<(), io::Error> {
+    write!(w, "set {{")?;
+    for (i, item) in set.items.iter().enumerate() {
+        write_expr(w, rt, item, tabs + 1)?;
+        if i + 1 < set.items.len() {
+            write!(w, ", ")?;
+        }
+    }
+    write!(w, "}}")?;
+    Ok(())
+}
+
 fn write_call<W: io::Write>(
     w: &mut W,
     rt: &Runtime,
@@ -650,7 +910,19 @@ fn write_assign<W: io::Write>(
     assign: &ast::Assign,
     tabs: u32,
 ) -> Result<(), io::Error> {
-    write_expr(w, rt, &assign.left, tabs)?;
+    match (&assign.left, assign.destructure()) {
+        (&ast::Expression::Object(_), Some(items)) => {
+            write!(w, "{{")?;
+            for (i, item) in items.iter().enumerate() {
+                write!(w, "{}", item.name)?;
+                if i + 1 < items.len() {
+                    write!(w, ", ")?;
+                }
+            }
+            write!(w, "}}")?;
+        }
+        _ => write_expr(w, rt, &assign.left, tabs)?,
+    }
     write!(w, " {} ", assign.op.symbol())?;
     write_expr(w, rt, &assign.right, tabs)?;
     Ok(())
@@ -743,17 +1015,20 @@ fn write_for<W: io::Write>(
 ) -> Result<(), io::Error> {
     if let ast::Expression::Block(ref b) = f.init {
         if b.expressions.is_empty() {
-            if let ast::Expression::Variable(ref range_var) = f.cond {
-                if let (_, Variable::Bool(b, _)) = **range_var {
-                    if b {
-                        if let ast::Expression::Block(ref b) = f.step {
-                            if b.expressions.is_empty() {
-                                write!(w, "loop ")?;
-                                write_block(w, rt, &f.block, tabs + 1)?;
-                                return Ok(());
-                            }
+            if let ast::Expression::Block(ref b) = f.step {
+                if b.expressions.is_empty() {
+                    if let ast::Expression::Variable(ref range_var) = f.cond {
+                        if let (_, Variable::Bool(true, _)) = **range_var {
+                            write!(w, "loop ")?;
+                            write_block(w, rt, &f.block, tabs + 1)?;
+                            return Ok(());
                         }
                     }
+                    write!(w, "while ")?;
+                    write_expr(w, rt, &f.cond, tabs)?;
+                    write!(w, " ")?;
+                    write_block(w, rt, &f.block, tabs + 1)?;
+                    return Ok(());
                 }
             }
         }
@@ -789,6 +1064,11 @@ fn standard_binop(name: &Arc<String>, args: &[ast::Expression]) -> Option<ast::B
         "pow" => Pow,
         "and_also" => AndAlso,
         "or_else" => OrElse,
+        "bit_and" => BitAnd,
+        "bit_or" => BitOr,
+        "bit_xor" => BitXor,
+        "shl" => Shl,
+        "shr" => Shr,
         "less" => Less,
         "less_or_equal" => LessOrEqual,
         "greater" => Greater,
@@ -820,7 +1100,6 @@ fn write_for_n<W: io::Write>(
     Ok(())
 }
 
-#[cfg(all(not(target_family = "wasm"), feature = "threading"))]
 fn write_for_in<W: io::Write>(
     w: &mut W,
     rt: &Runtime,
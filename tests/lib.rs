@@ -3,15 +3,32 @@ extern crate piston_meta;
 
 use dyon::*;
 
-pub fn test_src(source: &str) {
+/// Returns an error when a check in a script fails.
+fn check(rt: &mut Runtime) -> Result<(), String> {
+    let ok: bool = rt.pop()?;
+    if ok {
+        Ok(())
+    } else {
+        Err("Check failed".into())
+    }
+}
+
+/// Creates a module with `check(bool)` for the scripts in `source/`.
+fn module() -> Module {
     let mut module = Module::new();
+    module.add_str("check", check, Dfn::nl(vec![Type::Bool], Type::Void));
+    module
+}
+
+pub fn test_src(source: &str) {
+    let mut module = module();
     load(source, &mut module).unwrap_or_else(|err| {
         panic!("In `{}`:\n{}", source, err);
     });
 }
 
 pub fn test_fail_src(source: &str) {
-    let mut module = Module::new();
+    let mut module = module();
     match load(source, &mut module) {
         Ok(_) => panic!("`{}` should fail", source),
         Err(err) => {
@@ -23,16 +40,19 @@ pub fn test_fail_src(source: &str) {
 }
 
 pub fn debug_src(source: &str) {
-    let mut module = Module::new();
+    let mut module = module();
     load(source, &mut module).unwrap_or_else(|err| {
         panic!("In `{}`:\n{}", source, err);
     });
 }
 
 pub fn run_src(source: &str) {
-    run(source).unwrap_or_else(|err| {
-        panic!("In `{}`:\n{}", source, err);
-    });
+    let mut module = module();
+    load(source, &mut module)
+        .and_then(|_| Runtime::new().run(&std::sync::Arc::new(module)))
+        .unwrap_or_else(|err| {
+            panic!("In `{}`:\n{}", source, err);
+        });
 }
 
 #[cfg(feature = "file")]
@@ -54,6 +74,13 @@ fn test_syntax() {
     test_fail_src("source/syntax/add_fail_1.dyon");
     test_src("source/syntax/mul.dyon");
     test_src("source/syntax/pow.dyon");
+    test_src("source/syntax/bitwise.dyon");
+    test_src("source/syntax/map.dyon");
+    test_src("source/syntax/map_set_names.dyon");
+    test_src("source/syntax/while.dyon");
+    test_src("source/syntax/destructure.dyon");
+    test_src("source/syntax/default_args.dyon");
+    test_src("source/syntax/par.dyon");
     test_src("source/syntax/add_mul.dyon");
     test_src("source/syntax/mul_add.dyon");
     test_src("source/syntax/pos_len.dyon");
@@ -96,6 +123,7 @@ fn test_syntax() {
     test_src("source/syntax/accessor.dyon");
     test_src("source/syntax/sum.dyon");
     test_src("source/syntax/link_for.dyon");
+    test_src("source/syntax/link_values.dyon");
     test_src("source/syntax/min_max.dyon");
     test_src("source/syntax/vec4.dyon");
     test_src("source/syntax/vec4_2.dyon");
@@ -186,6 +214,15 @@ fn test_typechk() {
     test_fail_src("source/typechk/call_2.dyon");
     test_src("source/typechk/call_4.dyon");
     test_src("source/typechk/obj_pass_1.dyon");
+    test_fail_src("source/typechk/map.dyon");
+    test_fail_src("source/typechk/break_value.dyon");
+    test_fail_src("source/typechk/break_value_2.dyon");
+    test_fail_src("source/typechk/destructure.dyon");
+    test_fail_src("source/typechk/destructure_2.dyon");
+    test_fail_src("source/typechk/default_arg.dyon");
+    test_fail_src("source/typechk/default_arg_2.dyon");
+    test_fail_src("source/typechk/default_arg_3.dyon");
+    test_fail_src("source/typechk/default_arg_4.dyon");
     test_src("source/typechk/arr_pass_1.dyon");
     test_src("source/typechk/arr_pass_2.dyon");
     test_src("source/typechk/arr_pass_3.dyon");
@@ -237,9 +274,22 @@ fn test_typechk() {
     test_src("source/typechk/secret_9.dyon");
     test_fail_src("source/typechk/secret_10.dyon");
     test_src("source/typechk/secret_11.dyon");
+    test_src("source/typechk/secret_12.dyon");
+    test_fail_src("source/typechk/secret_13.dyon");
     test_src("source/typechk/dot.dyon");
     test_src("source/typechk/in.dyon");
     test_fail_src("source/typechk/in_2.dyon");
+    test_fail_src("source/typechk/channel.dyon");
+    test_fail_src("source/typechk/par.dyon");
+    test_fail_src("source/typechk/par_2.dyon");
+    test_fail_src("source/typechk/shared.dyon");
+    test_src("source/typechk/iter.dyon");
+    test_fail_src("source/typechk/iter_2.dyon");
+    test_fail_src("source/typechk/iter_3.dyon");
+    test_fail_src("source/typechk/iter_4.dyon");
+    test_fail_src("source/typechk/iter_5.dyon");
+    test_fail_src("source/typechk/iter_6.dyon");
+    test_fail_src("source/typechk/iter_7.dyon");
     test_fail_src("source/typechk/vec4_2.dyon");
     test_fail_src("source/typechk/mat4_1.dyon");
     test_src("source/typechk/mat4_2.dyon");
@@ -292,6 +342,30 @@ fn test_typechk() {
 #[test]
 fn test_runs() {
     run_src("source/print_closure/binop.dyon");
+    run_src("source/functions/integer.dyon");
+    run_src("source/functions/map.dyon");
+    run_src("source/syntax/loop_value.dyon");
+    run_src("source/syntax/destructure.dyon");
+    run_src("source/syntax/default_args.dyon");
+    run_src("source/default_args/loader.dyon");
+    run_src("source/functions/channel.dyon");
+    run_src("source/functions/thread.dyon");
+    run_src("source/functions/shared.dyon");
+    run_src("source/syntax/par.dyon");
+    run_src("source/syntax/link_values.dyon");
+    run_src("source/functions/template.dyon");
+    run_src("source/functions/contributions.dyon");
+    run_src("source/syntax/generator.dyon");
+    run_src("source/functions/random.dyon");
+    run_src("source/functions/string.dyon");
+    run_src("source/functions/number_format.dyon");
+    run_src("source/functions/json.dyon");
+    run_src("source/functions/schema.dyon");
+    run_src("source/functions/data_format.dyon");
+    run_src("source/functions/csv.dyon");
+    run_src("source/functions/fs.dyon");
+    #[cfg(feature = "process")]
+    run_src("source/functions/process.dyon");
 }
 
 #[cfg(feature = "file")]